@@ -1,21 +1,37 @@
 use crate::{
-    expand_home_dir, get_configuration_path, Configuration, Error, Result, StatusBar,
-    WallpaperImage, WallpaperPath, WallpaperPathMessage,
+    apply_wallpaper, clamp_thumbnail_size, download_image, format_count, gallery_id, gallery_view,
+    get_screen_resolution, has_config_errors, import_files, is_archive, is_smaller_than,
+    listen_ipc, load_configuration, load_image_files, load_index, load_regolith_config,
+    load_regolith_look, load_sources, load_tags, load_thumbnail_async, look_brightness,
+    move_all_to_trash, move_files, open_in_file_manager, rename_file, save_index, save_sources,
+    save_tags, save_user_setting, scan_duplicates, set_wallpaper_on_config, split_archive_path,
+    to_hex, watch_library, ApplyOptions, ApplySource, Brightness, BrightnessFilter, Collection,
+    ColorRanking, ConfigBanner, ConfigBannerMessage, ConfigDiagnostic, Configuration, Destination,
+    DuplicateGroup, Duplicates, DuplicatesMessage, Error, FileAction, FileActions,
+    FileActionsMessage, GalleryLayout, ImageSource, Import, ImportMessage, ImportOptions,
+    IpcCommand, IpcReply, LibraryChanges, LibraryIndex, LoadErrors, LoadErrorsMessage,
+    LoadProgress, Online, OnlineMessage, PickOptions, RemoteImage, Resolution, Result, Selection,
+    Slideshow, SortBy, SourceStore, StatusBar, TagStore, Thumbnail, ThumbnailCache, TileState,
+    WallpaperImage, WallpaperPath, WallpaperPathMessage, COLOR_PICK_CANDIDATES, DEFAULT_INTERVAL,
+    DUPLICATE_MAX_DISTANCE, FAVORITE_TAG, MAX_THUMBNAIL_SIZE, MIN_THUMBNAIL_SIZE,
+    THUMBNAIL_SIZE_STEP, THUMBNAIL_WIDTH,
 };
-use futures::StreamExt;
-use iced::font::Weight;
+use iced::font::{self, Weight};
 use iced::keyboard::KeyCode;
 use iced::widget::{
-    button, column, container, horizontal_space, row, scrollable, text, vertical_space,
+    button, checkbox, column, container, horizontal_space, pick_list, row, scrollable, slider,
+    text, text_input, vertical_space, Row,
+};
+use iced::{
+    event, executor, keyboard, mouse, subscription, theme, window, Alignment, Color, Event, Font,
+    Length, Subscription,
 };
-use iced::{executor, keyboard, subscription, window, Event, Font, Length, Subscription};
 use iced::{Application, Command, Element, Theme};
-use iced_aw::Grid;
-use image::ImageFormat;
+use iced_aw::ColorPicker;
 use rand::seq::IteratorRandom;
-use std::path::PathBuf;
-use tokio::fs::{read_dir, read_to_string, write};
-use tokio_stream::wrappers::ReadDirStream;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone)]
 pub enum Message {
@@ -29,11 +45,76 @@ pub enum Message {
     },
     WallpaperPathSetted,
     LoadedPaths(Result<Vec<PathBuf>>),
-    LoadedImage(Result<WallpaperImage>),
+    LoadedImage(PathBuf, Result<WallpaperImage>),
     SelectImage(usize),
     PickRandomImage,
     UpdateStatusBar(Result<String>),
     ConfigSaved(Result<()>),
+    CollectionSelected(Collection),
+    TagInputEdit(String),
+    AddTag,
+    RemoveTag(String),
+    ClearSelection,
+    SelectAll,
+    SelectionAction(FileAction),
+    AddToCollection(String),
+    ToggleFavorite,
+    StartSlideshow,
+    StopSlideshow,
+    SlideshowTick,
+    TagsSaved(Result<()>),
+    SortSelected(SortBy),
+    IndexSaved(Result<()>),
+    ShowView(ViewMode),
+    DuplicatesMessage(DuplicatesMessage),
+    FindDuplicates,
+    DuplicatesFound(Vec<DuplicateGroup>),
+    TrashImages(Vec<PathBuf>),
+    ImagesTrashed(Vec<(PathBuf, Result<PathBuf>)>),
+    FontLoaded(std::result::Result<(), font::Error>),
+    ColorPickerToogle(bool),
+    ColorPicked(Color),
+    MoreLikeThis(usize),
+    ClearColorRanking,
+    BrightnessFilterSelected(BrightnessFilter),
+    LookLoaded(Result<String>),
+    MatchLookToogle(bool),
+    ScreenResolution(Result<Resolution>),
+    ConfirmApply,
+    CancelApply,
+    LibraryChanged(LibraryChanges),
+    GalleryScrolled(scrollable::Viewport),
+    ThumbnailLoaded(PathBuf, Result<Thumbnail>),
+    ThumbnailSizeChanged(u32),
+    LoadErrorsMessage(LoadErrorsMessage),
+    ConfigBannerMessage(ConfigBannerMessage),
+    RetryImages(Vec<PathBuf>),
+    IgnoreImage(PathBuf),
+    OpenInFileManager(PathBuf),
+    FileManagerOpened(Result<()>),
+    FileAction(usize, FileAction),
+    FileActionsMessage(FileActionsMessage),
+    RenameFile(PathBuf, String),
+    MoveFiles(Vec<PathBuf>, PathBuf),
+    FilesMoved(Vec<(PathBuf, Result<PathBuf>)>),
+    ImportMessage(ImportMessage),
+    ImportFiles(Vec<PathBuf>, PathBuf, ImportOptions),
+    FilesImported(Vec<(PathBuf, Result<PathBuf>)>),
+    OnlineMessage(OnlineMessage),
+    OnlineListed(usize, Result<Vec<RemoteImage>>),
+    PreviewLoaded(String, Result<Vec<u8>>),
+    DownloadImages(usize, Vec<RemoteImage>),
+    ImageDownloaded(usize, String, Result<(PathBuf, ImageSource)>),
+    SourcesSaved(Result<()>),
+    IpcCommand(IpcCommand, IpcReply),
+}
+
+/// Main content shown below the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewMode {
+    Library,
+    Duplicates,
+    Online,
 }
 
 pub struct RegolithWallpaperApp {
@@ -44,22 +125,677 @@ pub struct RegolithWallpaperApp {
     images: Vec<WallpaperImage>,
     status_bar: StatusBar,
     configuration: Configuration,
-    max_images: Option<usize>,
+    /// Problems found on the config when loading it
+    config_banner: ConfigBanner,
+    tags: TagStore,
+    collection: Collection,
+    tag_input: String,
+    modifiers: keyboard::Modifiers,
+    index: LibraryIndex,
+    index_changed: bool,
+    next_id: usize,
+    pending_images: usize,
+    sort_by: SortBy,
+    view_mode: ViewMode,
+    duplicates: Duplicates,
+    color_picker_show: bool,
+    color_ranking: Option<ColorRanking>,
+    brightness_filter: BrightnessFilter,
+    /// Brightness of the active Regolith look
+    look_brightness: Option<Brightness>,
+    match_look: bool,
+    screen_resolution: Option<Resolution>,
+    /// Image waiting for confirmation before being applied, as it is smaller
+    /// than the screen
+    pending_apply: Option<usize>,
+    /// Decoded thumbnails of the images shown
+    thumbnails: ThumbnailCache,
+    /// Scroll position of the gallery
+    gallery_offset: f32,
+    window_size: (u32, u32),
+    /// Width of the thumbnails
+    thumbnail_size: u32,
+    load_progress: LoadProgress,
+    load_errors: LoadErrors,
+    file_actions: FileActions,
+    selection: Selection,
+    /// Files dropped on the window
+    import: Import,
+    /// Selected images being applied one after the other
+    slideshow: Option<Slideshow>,
+    online: Online,
+    /// Sources of the downloaded images
+    sources: SourceStore,
+    /// Indexes of `images` in the gallery order (filtered and sorted), `None`
+    /// when the images, the filters or the sort changed. Rebuilt after each
+    /// message, so events that change nothing don't sort the library again.
+    gallery_order: Option<Vec<usize>>,
 }
 
 impl RegolithWallpaperApp {
-    fn unselect_images(&mut self) {
-        self.images.iter_mut().for_each(|image| {
-            image.selected = false;
-        });
+    fn root(&self) -> Option<&Path> {
+        self.configuration.wallpapers_path()
+    }
+
+    /// Indexes of the images on the active collection, sorted by
+    /// `color_ranking` or `sort_by`
+    fn sort_gallery(&self) -> Vec<usize> {
+        let filtered = self
+            .images
+            .iter()
+            .enumerate()
+            .filter(|(_, image)| {
+                self.brightness_filter.matches(image.entry.brightness)
+                    && self
+                        .tags
+                        .matches(&image.path, self.root(), &self.collection)
+            })
+            .collect::<Vec<_>>();
+        let mut entries = filtered
+            .iter()
+            .map(|(_, image)| &image.entry)
+            .collect::<Vec<_>>();
+        match &self.color_ranking {
+            Some(ranking) => ranking.sort(&mut entries),
+            None => self.sort_by.sort(&mut entries),
+        }
+        let indexes = filtered
+            .iter()
+            .map(|(i, image)| (&image.path, *i))
+            .collect::<HashMap<_, _>>();
+        entries
+            .into_iter()
+            .map(|entry| indexes[&entry.path])
+            .collect()
+    }
+
+    fn invalidate_gallery_order(&mut self) {
+        self.gallery_order = None;
+    }
+
+    /// Images on the active collection, in the gallery order
+    fn visible_images(&self) -> Vec<&WallpaperImage> {
+        match &self.gallery_order {
+            Some(order) => order.iter().map(|i| &self.images[*i]).collect(),
+            None => self
+                .sort_gallery()
+                .into_iter()
+                .map(|i| &self.images[i])
+                .collect(),
+        }
+    }
+
+    /// Paths of the visible images, in the gallery order
+    fn visible_paths(&self) -> Vec<PathBuf> {
+        self.visible_images()
+            .into_iter()
+            .map(|image| image.path.clone())
+            .collect()
+    }
+
+    /// Selected images that are visible, in the gallery order
+    fn selected_paths(&self) -> Vec<PathBuf> {
+        if self.selection.is_empty() {
+            return Vec::new();
+        }
+        self.visible_images()
+            .into_iter()
+            .filter(|image| self.selection.contains(&image.path))
+            .map(|image| image.path.clone())
+            .collect()
+    }
+
+    /// Adds the image to the gallery, replacing the one with the same path
+    fn add_image(&mut self, mut image: WallpaperImage) {
+        match image.thumbnail.take() {
+            Some(thumbnail) => self.thumbnails.insert(image.path.clone(), thumbnail),
+            None => self.thumbnails.remove(&image.path),
+        }
+        match self.images.iter_mut().find(|i| i.path == image.path) {
+            Some(existing) => *existing = image,
+            None => self.images.push(image),
+        }
+        self.invalidate_gallery_order();
+    }
+
+    /// Loads the thumbnails of the rows around the gallery scroll position (or
+    /// of the duplicates), evicting the ones not shown for a while
+    fn load_visible_thumbnails(&mut self) -> Command<Message> {
+        let paths = match self.view_mode {
+            ViewMode::Library => {
+                let images = self.visible_images();
+                let layout = self.gallery_layout(images.len());
+                images[layout.visible(images.len())]
+                    .iter()
+                    .map(|image| image.path.clone())
+                    .collect::<Vec<_>>()
+            }
+            ViewMode::Duplicates => self
+                .duplicates
+                .groups
+                .iter()
+                .flat_map(|group| group.entries.iter().map(|entry| entry.path.clone()))
+                .collect(),
+            ViewMode::Online => Vec::new(),
+        };
+        let size = self.thumbnail_size;
+        let missing = self
+            .thumbnails
+            .request(paths.iter().map(PathBuf::as_path), size);
+        Command::batch(missing.into_iter().filter_map(|path| {
+            let entry = self.index.entries.get(&path)?.clone();
+            Some(Command::perform(
+                load_thumbnail_async(entry, size),
+                move |result| Message::ThumbnailLoaded(path.clone(), result),
+            ))
+        }))
+    }
+
+    /// Rows of the gallery around the scroll position. The gallery is a bit
+    /// smaller than the window, so this includes a few extra images at most.
+    fn gallery_layout(&self, len: usize) -> GalleryLayout {
+        let (width, height) = self.window_size;
+        GalleryLayout::new(
+            len,
+            width as f32 - 40.0,
+            height as f32,
+            self.gallery_offset,
+            self.thumbnail_size,
+        )
+    }
+
+    /// Changes the thumbnail size, scrolling to keep the first visible image
+    fn set_thumbnail_size(&mut self, size: u32) -> Command<Message> {
+        let size = clamp_thumbnail_size(size);
+        if size == self.thumbnail_size {
+            return Command::none();
+        }
+        let len = self.visible_images().len();
+        let first_visible = self.gallery_layout(len).first_visible(self.gallery_offset);
+        self.thumbnail_size = size;
+        self.gallery_offset = self.gallery_layout(len).offset_of(first_visible);
+        self.configuration.thumbnail_size = Some(size);
+        Command::batch(vec![
+            scrollable::scroll_to(
+                gallery_id(),
+                scrollable::AbsoluteOffset {
+                    x: 0.0,
+                    y: self.gallery_offset,
+                },
+            ),
+            Command::perform(
+                save_user_setting("thumbnail_size", size),
+                Message::ConfigSaved,
+            ),
+        ])
+    }
+
+    /// Images affected by a context menu action: the selection if the image is
+    /// selected, or else only the image
+    fn action_targets(&self, id: usize) -> Vec<PathBuf> {
+        match self.images.iter().find(|image| image.id == id) {
+            Some(image) if self.selection.contains(&image.path) => self.selected_paths(),
+            Some(image) => vec![image.path.clone()],
+            None => self
+                .current_wallpaper
+                .iter()
+                .filter(|image| image.id == id)
+                .map(|image| image.path.clone())
+                .collect(),
+        }
+    }
+
+    /// Whether some of the images are inside an archive used as library root
+    fn is_read_only(&self, paths: &[PathBuf]) -> bool {
+        paths.iter().any(|path| split_archive_path(path).is_some())
+    }
+
+    /// Folders of the library (and the other library roots) where images can
+    /// be moved to, leaving archives out
+    fn move_destinations(&self) -> Vec<Destination> {
+        let mut folders = self
+            .root()
+            .into_iter()
+            .chain(self.images.iter().filter_map(|image| image.path.parent()))
+            .map(Path::to_path_buf)
+            .collect::<BTreeSet<_>>();
+        folders.extend(self.configuration.extra_wallpapers_paths().iter().cloned());
+        folders
+            .into_iter()
+            .filter(|folder| !is_archive(folder) && split_archive_path(folder).is_none())
+            .map(|folder| Destination::new(folder, self.root()))
+            .collect()
+    }
+
+    fn file_action(&mut self, targets: Vec<PathBuf>, action: FileAction) -> Command<Message> {
+        if targets.is_empty() {
+            return Command::none();
+        }
+        let changes_files = matches!(
+            action,
+            FileAction::Rename | FileAction::Move | FileAction::Trash
+        );
+        if changes_files && self.is_read_only(&targets) {
+            self.status_bar = StatusBar::Error(Error::ReadOnlyArchive.to_string());
+            return Command::none();
+        }
+        match action {
+            FileAction::Rename if targets.len() == 1 => {
+                self.file_actions.start_rename(targets[0].clone());
+                Command::none()
+            }
+            FileAction::Rename => {
+                self.status_bar = StatusBar::Error("Select a single image to rename.".into());
+                Command::none()
+            }
+            FileAction::Move => {
+                self.file_actions
+                    .start_move(targets, self.move_destinations());
+                Command::none()
+            }
+            FileAction::CopyPath => {
+                let paths = targets
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>();
+                self.status_bar = StatusBar::Ok(format!("{} paths copied.", paths.len()));
+                iced::clipboard::write(paths.join("\n"))
+            }
+            FileAction::Reveal => {
+                // Each folder is opened once, images inside archives show the
+                // archive
+                let mut folders = BTreeSet::new();
+                Command::batch(
+                    targets
+                        .into_iter()
+                        .map(|path| match split_archive_path(&path) {
+                            Some((archive, _)) => archive.to_path_buf(),
+                            None => path,
+                        })
+                        .filter(|path| folders.insert(path.parent().map(Path::to_path_buf)))
+                        .map(|path| {
+                            Command::perform(open_in_file_manager(path), Message::FileManagerOpened)
+                        }),
+                )
+            }
+            FileAction::Trash => self.update(Message::TrashImages(targets)),
+        }
+    }
+
+    /// Updates the gallery, index and tags after a file was renamed or moved
+    fn file_moved(&mut self, from: &Path, to: &Path) {
+        let in_library = self.root().is_some_and(|root| to.starts_with(root));
+        if let Some(mut entry) = self.index.entries.remove(from) {
+            entry.path = to.to_path_buf();
+            self.index.insert(entry);
+            self.index_changed = true;
+        }
+        if in_library {
+            if let Some(image) = self.images.iter_mut().find(|image| image.path == from) {
+                image.path = to.to_path_buf();
+                image.entry.path = to.to_path_buf();
+            }
+        } else {
+            self.images.retain(|image| image.path != from);
+        }
+        self.thumbnails.remove(from);
+        self.tags.move_path(from, to);
+        self.invalidate_gallery_order();
+        self.sources.move_path(from, to);
+        self.selection.move_path(from, to);
+    }
+
+    /// Click on a tile: Ctrl toggles the image on the selection, Shift selects
+    /// the range from the last clicked image, and a plain click applies it
+    fn click_image(&mut self, id: usize) -> Command<Message> {
+        let Some(path) = self
+            .images
+            .iter()
+            .find(|image| image.id == id)
+            .map(|image| image.path.clone())
+        else {
+            return Command::none();
+        };
+        if self.modifiers.control() {
+            self.selection.toggle(&path);
+            Command::none()
+        } else if self.modifiers.shift() {
+            let order = self.visible_paths();
+            let order = order.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+            self.selection.select_range(&order, &path);
+            Command::none()
+        } else {
+            self.selection.focus(&path);
+            self.apply_image(id)
+        }
+    }
+
+    /// Keyboard navigation on the gallery: arrows move the cursor (extending
+    /// the selection with Shift), Space toggles the image, Enter applies it
+    /// and Ctrl + A selects every visible image
+    fn key_pressed(
+        &mut self,
+        key_code: KeyCode,
+        modifiers: keyboard::Modifiers,
+    ) -> Command<Message> {
+        if self.view_mode != ViewMode::Library || self.wallpaper_path_show {
+            return Command::none();
+        }
+        let images = self.visible_images();
+        let columns = self.gallery_layout(images.len()).columns as isize;
+        let order = images
+            .iter()
+            .map(|image| (image.id, image.path.clone()))
+            .collect::<Vec<_>>();
+        let paths = order
+            .iter()
+            .map(|(_, path)| path.as_path())
+            .collect::<Vec<_>>();
+        let delta = match key_code {
+            KeyCode::Left => -1,
+            KeyCode::Right => 1,
+            KeyCode::Up => -columns,
+            KeyCode::Down => columns,
+            KeyCode::A if modifiers.control() => {
+                self.selection.select_all(&paths);
+                return Command::none();
+            }
+            KeyCode::Space => {
+                if let Some(cursor) = self.selection.cursor().map(Path::to_path_buf) {
+                    self.selection.toggle(&cursor);
+                }
+                return Command::none();
+            }
+            KeyCode::Enter => {
+                let cursor = self.selection.cursor();
+                return match order
+                    .iter()
+                    .find(|(_, path)| Some(path.as_path()) == cursor)
+                {
+                    Some((id, _)) => self.apply_image(*id),
+                    None => Command::none(),
+                };
+            }
+            _ => return Command::none(),
+        };
+        match self.selection.move_cursor(&paths, delta, modifiers.shift()) {
+            Some(index) => self.scroll_into_view(index, order.len()),
+            None => Command::none(),
+        }
+    }
+
+    /// Scrolls the gallery so the image at `index` is fully shown
+    fn scroll_into_view(&mut self, index: usize, len: usize) -> Command<Message> {
+        let layout = self.gallery_layout(len);
+        let top = layout.offset_of(index);
+        let height = self.window_size.1.saturating_sub(40) as f32;
+        let offset = if top < self.gallery_offset {
+            top
+        } else if top + layout.tile_height > self.gallery_offset + height {
+            top + layout.tile_height - height
+        } else {
+            return Command::none();
+        };
+        self.gallery_offset = offset.max(0.0);
+        scrollable::scroll_to(
+            gallery_id(),
+            scrollable::AbsoluteOffset {
+                x: 0.0,
+                y: self.gallery_offset,
+            },
+        )
+    }
+
+    /// Applies the next image of the slideshow, skipping the removed ones
+    fn slideshow_next(&mut self) -> Command<Message> {
+        let Some(slideshow) = &mut self.slideshow else {
+            return Command::none();
+        };
+        for _ in 0..slideshow.paths.len() {
+            let Some(path) = slideshow.next_path() else {
+                break;
+            };
+            if let Some(image) = self.images.iter().find(|image| image.path == path) {
+                return self.select_image(image.id);
+            }
+        }
+        self.slideshow = None;
+        self.status_bar = StatusBar::Error("The slideshow images were removed.".into());
+        Command::none()
+    }
+
+    /// Random image matching the filters sent by another invocation
+    fn random_image(&self, options: &PickOptions) -> Option<usize> {
+        let collection = Collection::from(options.collection.clone());
+        let brightness = options
+            .brightness
+            .or(self.look_brightness.filter(|_| options.match_look));
+        let mut entries = self
+            .images
+            .iter()
+            .filter(|image| {
+                brightness.is_none_or(|b| image.entry.brightness == b)
+                    && self.tags.matches(&image.path, self.root(), &collection)
+            })
+            .map(|image| &image.entry)
+            .collect::<Vec<_>>();
+        if let Some(color) = options.color {
+            ColorRanking::Color(color).sort(&mut entries);
+            entries.truncate(COLOR_PICK_CANDIDATES);
+        }
+        let path = &entries.into_iter().choose(&mut rand::thread_rng())?.path;
+        self.images
+            .iter()
+            .find(|image| &image.path == path)
+            .map(|image| image.id)
+    }
+
+    /// Commands sent by other invocations (e.g.: `regolith-wallpaper next`)
+    fn ipc_command(&mut self, command: IpcCommand, reply: IpcReply) -> Command<Message> {
+        match command {
+            IpcCommand::Next if self.slideshow.is_some() => {
+                let command = self.slideshow_next();
+                if self.slideshow.is_none() {
+                    reply.send(Err(Error::IpcFailed(
+                        "the slideshow images were removed".into(),
+                    )));
+                }
+                reply_when_applied(command, reply)
+            }
+            IpcCommand::Next => self.ipc_command(IpcCommand::Random(PickOptions::default()), reply),
+            IpcCommand::Random(options) => match self.random_image(&options) {
+                Some(id) => reply_when_applied(self.select_image(id), reply),
+                None => {
+                    self.status_bar = StatusBar::Error("No images match the filters.".into());
+                    reply.send(Err(Error::IpcFailed("no images match the filters".into())));
+                    Command::none()
+                }
+            },
+            IpcCommand::Set { path } => {
+                let options = self.apply_options(&path);
+                reply_when_applied(
+                    Command::perform(
+                        apply_wallpaper(path, options),
+                        Message::CurrentWallpaperPath,
+                    ),
+                    reply,
+                )
+            }
+            IpcCommand::Reload => match load_configuration() {
+                // The config in use is kept until the errors are fixed
+                Ok((_, diagnostics)) if has_config_errors(&diagnostics) => {
+                    self.config_banner = ConfigBanner::new(diagnostics);
+                    self.status_bar = StatusBar::Error("Invalid configuration.".into());
+                    reply.send(Err(Error::IpcFailed("invalid configuration".into())));
+                    Command::none()
+                }
+                Ok((configuration, diagnostics)) => {
+                    self.config_banner = ConfigBanner::new(diagnostics);
+                    if configuration.wallpapers_path() != self.configuration.wallpapers_path() {
+                        self.images.clear();
+                        self.selection.clear();
+                    }
+                    self.invalidate_gallery_order();
+                    self.online = Online::new(&configuration.providers);
+                    self.configuration = configuration;
+                    self.wallpaper_path = WallpaperPath::from_config(&self.configuration);
+                    self.status_bar = StatusBar::Ok("Config reloaded.".into());
+                    reply.send(Ok("Config reloaded.".into()));
+                    match self.root().map(Path::to_path_buf) {
+                        Some(root) => {
+                            Command::perform(load_image_files(root), Message::LoadedPaths)
+                        }
+                        None => Command::none(),
+                    }
+                }
+                Err(e) => {
+                    self.status_bar = StatusBar::Error(e.to_string());
+                    reply.send(Err(e));
+                    Command::none()
+                }
+            },
+            IpcCommand::Focus => {
+                reply.send(Ok("Window focused.".into()));
+                window::gain_focus()
+            }
+        }
+    }
+
+    /// Loads the images, using the index entries for the unchanged files (their
+    /// thumbnails are loaded when shown)
+    fn load_images(&mut self, paths: Vec<PathBuf>) -> Command<Message> {
+        let mut commands = Vec::new();
+        for path in paths {
+            if self.configuration.ignored_files.contains(&path) {
+                continue;
+            }
+            let id = self.next_id;
+            self.next_id += 1;
+            match self.index.fresh_entry(&path) {
+                Some(entry) => self.add_image(WallpaperImage::new(id, entry.clone())),
+                None => commands.push(Command::perform(
+                    WallpaperImage::from_path(id, path.clone()),
+                    move |result| Message::LoadedImage(path.clone(), result),
+                )),
+            }
+        }
+        self.pending_images += commands.len();
+        self.load_progress.add(commands.len(), Instant::now());
+        commands.push(self.save_index_if_done());
+        Command::batch(commands)
+    }
+
+    /// Saves the index once all the pending images are loaded
+    fn image_loaded(&mut self) -> Command<Message> {
+        self.pending_images = self.pending_images.saturating_sub(1);
+        self.load_progress.finish_one();
+        if self.pending_images == 0 && !self.load_errors.errors.is_empty() {
+            self.status_bar = StatusBar::Error(format!(
+                "{} files failed to load.",
+                format_count(self.load_errors.errors.len())
+            ));
+        }
+        self.save_index_if_done()
+    }
+
+    fn save_index_if_done(&mut self) -> Command<Message> {
+        if self.pending_images == 0 && self.index_changed {
+            self.index_changed = false;
+            Command::perform(save_index(self.index.clone()), Message::IndexSaved)
+        } else {
+            Command::none()
+        }
+    }
+
+    /// Looks for duplicates on a background thread, the view shows the
+    /// previous groups until done
+    fn find_duplicates(&mut self) -> Command<Message> {
+        let entries = self
+            .images
+            .iter()
+            .map(|image| image.entry.clone())
+            .collect();
+        self.duplicates.scanning = true;
+        Command::perform(
+            scan_duplicates(entries, DUPLICATE_MAX_DISTANCE),
+            Message::DuplicatesFound,
+        )
+    }
+
+    /// Saves the tags, after changes that can take images out of the
+    /// active collection
+    fn save_tags(&mut self) -> Command<Message> {
+        self.invalidate_gallery_order();
+        Command::perform(save_tags(self.tags.clone()), Message::TagsSaved)
+    }
+
+    fn save_sources(&self) -> Command<Message> {
+        Command::perform(save_sources(self.sources.clone()), Message::SourcesSaved)
+    }
+
+    /// Lists the images of the selected provider, if not listed yet
+    fn list_online(&mut self) -> Command<Message> {
+        let Some(index) = self.online.needs_listing() else {
+            return Command::none();
+        };
+        let Some(provider) = self.online.provider(index) else {
+            return Command::none();
+        };
+        self.online.start_listing();
+        Command::perform(async move { provider.list().await }, move |result| {
+            Message::OnlineListed(index, result)
+        })
+    }
+
+    /// Starts the next download of each provider, into its folder inside the
+    /// library root
+    fn start_downloads(&mut self) -> Command<Message> {
+        let Some(root) = self.root().map(Path::to_path_buf) else {
+            return Command::none();
+        };
+        let downloads = self.online.queue.start();
+        Command::batch(downloads.into_iter().filter_map(|(index, image)| {
+            let provider = self.online.provider(index)?;
+            let folder = root.join(self.online.folder(index)?);
+            let url = image.url.clone();
+            Some(Command::perform(
+                download_image(provider, image, folder),
+                move |result| Message::ImageDownloaded(index, url.clone(), result),
+            ))
+        }))
+    }
+
+    /// Applies the image, asking for confirmation first if it is smaller than
+    /// the screen
+    fn apply_image(&mut self, id: usize) -> Command<Message> {
+        let image = self.images.iter().find(|image| image.id == id);
+        match (image, self.screen_resolution) {
+            (Some(image), Some(screen)) if is_smaller_than(image.entry.resolution(), screen) => {
+                self.pending_apply = Some(id);
+                Command::none()
+            }
+            _ => self.select_image(id),
+        }
+    }
+
+    /// Hooks of the config, with the colors of the image if it is indexed
+    fn apply_options(&self, path: &Path) -> ApplyOptions {
+        ApplyOptions {
+            source: ApplySource::Gui,
+            colors: self
+                .index
+                .entries
+                .get(path)
+                .map(|entry| entry.colors.clone())
+                .unwrap_or_default(),
+            hooks: self.configuration.hooks.clone(),
+        }
     }
 
     fn select_image(&mut self, id: usize) -> Command<Message> {
-        self.unselect_images();
-        if let Some(image) = self.images.iter_mut().find(|image| image.id == id) {
-            image.selected = false;
+        if let Some(image) = self.images.iter().find(|image| image.id == id) {
             Command::perform(
-                set_wallpaper_on_config(image.path.clone()),
+                apply_wallpaper(image.path.clone(), self.apply_options(&image.path)),
                 Message::CurrentWallpaperPath,
             )
         } else {
@@ -68,23 +804,60 @@ impl RegolithWallpaperApp {
     }
 }
 
+/// Answers the IPC request with the result of applying the wallpaper of
+/// `command`
+fn reply_when_applied(command: Command<Message>, reply: IpcReply) -> Command<Message> {
+    command.map(move |message| {
+        if let Message::CurrentWallpaperPath(result) = &message {
+            reply.send(
+                result
+                    .as_ref()
+                    .map(|path| format!("Wallpaper changed: {}", path.display()))
+                    .map_err(Clone::clone),
+            );
+        }
+        message
+    })
+}
+
 impl Application for RegolithWallpaperApp {
     type Executor = executor::Default;
-    type Flags = Configuration;
+    /// Config and the problems found on it
+    type Flags = (Configuration, Vec<ConfigDiagnostic>);
     type Message = Message;
     type Theme = Theme;
 
-    fn new(config: Self::Flags) -> (RegolithWallpaperApp, Command<Self::Message>) {
+    fn new((config, diagnostics): Self::Flags) -> (RegolithWallpaperApp, Command<Self::Message>) {
         let wallpaper_path = WallpaperPath::from_config(&config);
-        let (wallpaper_path_show, focus_cmd) = match &wallpaper_path.path {
-            Some(path) => (
-                false,
-                Command::perform(load_image_files(path.clone()), Message::LoadedPaths),
-            ),
-            None => (true, wallpaper_path.focus_input()),
-        };
+        // A missing library is asked for again
+        let (wallpaper_path_show, focus_cmd) =
+            match wallpaper_path.path.as_ref().filter(|path| path.exists()) {
+                Some(path) => (
+                    false,
+                    Command::perform(load_image_files(path.clone()), Message::LoadedPaths),
+                ),
+                None => (true, wallpaper_path.focus_input()),
+            };
+        let screen_resolution_cmd = Command::perform(
+            get_screen_resolution(config.screen_resolution),
+            Message::ScreenResolution,
+        );
         let load_regolith_config_cmd =
             Command::perform(load_regolith_config(), Message::CurrentWallpaperPath);
+        let (tags, mut status_bar) = match load_tags() {
+            Ok(tags) => (tags, StatusBar::None),
+            Err(e) => (TagStore::default(), StatusBar::Error(e.to_string())),
+        };
+        let sources = load_sources().unwrap_or_else(|e| {
+            status_bar = StatusBar::Error(e.to_string());
+            SourceStore::default()
+        });
+        let online = Online::new(&config.providers);
+        let thumbnail_size = clamp_thumbnail_size(config.thumbnail_size.unwrap_or(THUMBNAIL_WIDTH));
+        let index = load_index().unwrap_or_else(|e| {
+            status_bar = StatusBar::Error(e.to_string());
+            LibraryIndex::default()
+        });
         (
             RegolithWallpaperApp {
                 current_wallpaper: None,
@@ -92,11 +865,48 @@ impl Application for RegolithWallpaperApp {
                 wallpaper_path,
                 wallpaper_path_show,
                 images: Vec::new(),
-                status_bar: StatusBar::None,
-                max_images: config.max_images,
+                status_bar,
                 configuration: config,
+                config_banner: ConfigBanner::new(diagnostics),
+                tags,
+                collection: Collection::All,
+                tag_input: String::new(),
+                modifiers: keyboard::Modifiers::default(),
+                index,
+                index_changed: false,
+                next_id: 1,
+                pending_images: 0,
+                sort_by: SortBy::default(),
+                view_mode: ViewMode::Library,
+                duplicates: Duplicates::default(),
+                color_picker_show: false,
+                color_ranking: None,
+                brightness_filter: BrightnessFilter::All,
+                look_brightness: None,
+                match_look: false,
+                screen_resolution: None,
+                pending_apply: None,
+                thumbnails: ThumbnailCache::default(),
+                gallery_offset: 0.0,
+                window_size: window::Settings::default().size,
+                thumbnail_size,
+                load_progress: LoadProgress::default(),
+                load_errors: LoadErrors::default(),
+                file_actions: FileActions::default(),
+                selection: Selection::default(),
+                import: Import::default(),
+                slideshow: None,
+                online,
+                sources,
+                gallery_order: None,
             },
-            Command::batch(vec![focus_cmd, load_regolith_config_cmd]),
+            Command::batch(vec![
+                focus_cmd,
+                load_regolith_config_cmd,
+                Command::perform(load_regolith_look(), Message::LookLoaded),
+                screen_resolution_cmd,
+                font::load(iced_aw::graphics::icons::ICON_FONT_BYTES).map(Message::FontLoaded),
+            ]),
         )
     }
 
@@ -109,21 +919,109 @@ impl Application for RegolithWallpaperApp {
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
-        subscription::events().map(Message::EventOcurred)
+        // The wheel is captured by the gallery, but it is needed for zooming
+        let events = subscription::events_with(|event, status| match (&event, status) {
+            (Event::Mouse(mouse::Event::WheelScrolled { .. }), _) => {
+                Some(Message::EventOcurred(event))
+            }
+            (_, event::Status::Ignored) => Some(Message::EventOcurred(event)),
+            _ => None,
+        });
+        let mut subscriptions = vec![
+            events,
+            listen_ipc().map(|(command, reply)| Message::IpcCommand(command, reply)),
+        ];
+        // Archives used as library root are not watched
+        if let Some(root) = self
+            .root()
+            .filter(|root| !is_archive(root))
+            .map(Path::to_path_buf)
+        {
+            subscriptions.push(watch_library(root).map(Message::LibraryChanged));
+        }
+        if self.slideshow.is_some() {
+            let interval = self
+                .configuration
+                .slideshow_interval
+                .unwrap_or(DEFAULT_INTERVAL);
+            subscriptions.push(
+                iced::time::every(Duration::from_secs(interval)).map(|_| Message::SlideshowTick),
+            );
+        }
+        Subscription::batch(subscriptions)
     }
 
     fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
+        let command = self.handle_message(message);
+        if self.gallery_order.is_none() {
+            self.gallery_order = Some(self.sort_gallery());
+        }
+        Command::batch(vec![command, self.load_visible_thumbnails()])
+    }
+
+    fn view(&self) -> Element<'_, Self::Message> {
+        self.main_view()
+    }
+}
+
+impl RegolithWallpaperApp {
+    fn handle_message(&mut self, message: Message) -> Command<Message> {
         match message {
+            Message::EventOcurred(Event::Keyboard(keyboard::Event::KeyPressed {
+                key_code: KeyCode::Escape,
+                modifiers: _,
+            })) if !self.selection.is_empty() => self.update(Message::ClearSelection),
             Message::EventOcurred(Event::Keyboard(keyboard::Event::KeyPressed {
                 key_code: KeyCode::Escape | KeyCode::Q,
                 modifiers: _,
             })) => window::close(),
             Message::EventOcurred(Event::Window(window::Event::CloseRequested)) => window::close(),
+            Message::EventOcurred(Event::Keyboard(keyboard::Event::ModifiersChanged(
+                modifiers,
+            ))) => {
+                self.modifiers = modifiers;
+                Command::none()
+            }
+            Message::EventOcurred(Event::Window(window::Event::Resized { width, height })) => {
+                self.window_size = (width, height);
+                Command::none()
+            }
+            Message::EventOcurred(Event::Mouse(mouse::Event::WheelScrolled { delta }))
+                if self.modifiers.control() =>
+            {
+                let y = match delta {
+                    mouse::ScrollDelta::Lines { y, .. } | mouse::ScrollDelta::Pixels { y, .. } => y,
+                };
+                let size = if y > 0.0 {
+                    self.thumbnail_size + THUMBNAIL_SIZE_STEP
+                } else {
+                    self.thumbnail_size.saturating_sub(THUMBNAIL_SIZE_STEP)
+                };
+                self.set_thumbnail_size(size)
+            }
+            Message::EventOcurred(Event::Keyboard(keyboard::Event::KeyPressed {
+                key_code,
+                modifiers,
+            })) => self.key_pressed(key_code, modifiers),
+            Message::EventOcurred(Event::Window(window::Event::FileDropped(path))) => {
+                let mut destinations = self.move_destinations();
+                // The library root first, selected by default
+                destinations
+                    .sort_by_key(|destination| Some(destination.path.as_path()) != self.root());
+                self.import.add(path, destinations, self.screen_resolution);
+                Command::none()
+            }
             Message::EventOcurred(_) => Command::none(),
-            Message::CurrentWallpaperPath(Ok(path)) => Command::perform(
-                WallpaperImage::from_path(0, path),
-                Message::CurrentWallpaperImage,
-            ),
+            Message::CurrentWallpaperPath(Ok(path)) => match self.index.fresh_entry(&path) {
+                Some(entry) => Command::perform(
+                    WallpaperImage::from_entry(0, entry.clone()),
+                    Message::CurrentWallpaperImage,
+                ),
+                None => Command::perform(
+                    WallpaperImage::from_path(0, path),
+                    Message::CurrentWallpaperImage,
+                ),
+            },
             Message::CurrentWallpaperPath(Err(e)) => {
                 tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to get wallpaper path from current regolith configuration.");
                 self.current_wallpaper_error = Some(e.to_string());
@@ -153,7 +1051,8 @@ impl Application for RegolithWallpaperApp {
             Message::WallpaperPathSetted => {
                 if let Some(path) = self.wallpaper_path.path.clone() {
                     self.images.clear();
-                    self.configuration.wallpapers_path = Some(path.clone());
+                    self.invalidate_gallery_order();
+                    self.configuration.set_wallpapers_path(path.clone());
                     let toogle_cmd = self.update(Message::WallpaperPathToogle {
                         show: false,
                         msg: Some(Ok(format!("Path setted to {:?}", path))),
@@ -161,7 +1060,10 @@ impl Application for RegolithWallpaperApp {
                     Command::batch(vec![
                         toogle_cmd,
                         Command::perform(
-                            save_config(self.configuration.clone()),
+                            save_user_setting(
+                                "wallpapers_paths",
+                                self.configuration.wallpapers_paths.clone(),
+                            ),
                             Message::ConfigSaved,
                         ),
                         Command::perform(load_image_files(path), Message::LoadedPaths),
@@ -170,34 +1072,81 @@ impl Application for RegolithWallpaperApp {
                     Command::none()
                 }
             }
-            Message::LoadedPaths(Ok(paths)) => {
-                let commands = paths
-                    .into_iter()
-                    .enumerate()
-                    .take(self.max_images.unwrap_or(usize::MAX))
-                    .map(|(i, path)| {
-                        Command::perform(WallpaperImage::from_path(i, path), Message::LoadedImage)
-                    })
-                    .collect::<Vec<_>>();
-                Command::batch(commands)
-            }
+            Message::LoadedPaths(Ok(mut paths)) => match self.root().map(Path::to_path_buf) {
+                Some(root) => {
+                    paths.retain(|path| !self.configuration.ignored_files.contains(path));
+                    if self.index.prune(&root, &paths) > 0 {
+                        self.index_changed = true;
+                    }
+                    self.load_images(paths)
+                }
+                None => Command::none(),
+            },
             Message::LoadedPaths(Err(e)) => {
                 self.status_bar = StatusBar::Error(e.to_string());
                 Command::none()
             }
-            Message::LoadedImage(Ok(image)) => {
-                self.images.push(image);
-                Command::none()
+            Message::LoadedImage(_, Ok(image)) => {
+                if self.index.entries.get(&image.path) != Some(&image.entry) {
+                    self.index.insert(image.entry.clone());
+                    self.index_changed = true;
+                }
+                self.add_image(image);
+                self.image_loaded()
             }
-            Message::LoadedImage(Err(e)) => {
-                self.status_bar = StatusBar::Error(e.to_string());
+            Message::LoadedImage(path, Err(e)) => {
+                self.load_errors.add(path, e.to_string());
+                self.image_loaded()
+            }
+            Message::ConfigBannerMessage(msg) => {
+                self.config_banner.update(msg);
                 Command::none()
             }
-            Message::SelectImage(id) => self.select_image(id),
+            Message::LoadErrorsMessage(msg) => match self.load_errors.update(msg) {
+                Some(msg) => self.update(msg),
+                None => Command::none(),
+            },
+            Message::RetryImages(paths) => {
+                for path in &paths {
+                    self.load_errors.errors.remove(path);
+                    self.thumbnails.remove(path);
+                }
+                self.load_images(paths)
+            }
+            Message::IgnoreImage(path) => {
+                self.images.retain(|image| image.path != path);
+                self.invalidate_gallery_order();
+                if self.index.entries.remove(&path).is_some() {
+                    self.index_changed = true;
+                }
+                self.thumbnails.remove(&path);
+                self.configuration.ignored_files.insert(path);
+                Command::batch(vec![
+                    Command::perform(
+                        save_user_setting(
+                            "ignored_files",
+                            self.configuration.ignored_files.clone(),
+                        ),
+                        Message::ConfigSaved,
+                    ),
+                    self.save_index_if_done(),
+                ])
+            }
+            Message::OpenInFileManager(path) => {
+                Command::perform(open_in_file_manager(path), Message::FileManagerOpened)
+            }
+            Message::SelectImage(id) => self.click_image(id),
             Message::PickRandomImage => {
                 let mut rng = rand::thread_rng();
-                if let Some(id) = self.images.iter().map(|image| image.id).choose(&mut rng) {
-                    self.select_image(id)
+                let look_brightness = self.look_brightness.filter(|_| self.match_look);
+                let id = self
+                    .visible_images()
+                    .into_iter()
+                    .filter(|image| look_brightness.is_none_or(|b| image.entry.brightness == b))
+                    .map(|image| image.id)
+                    .choose(&mut rng);
+                if let Some(id) = id {
+                    self.apply_image(id)
                 } else {
                     Command::none()
                 }
@@ -209,22 +1158,432 @@ impl Application for RegolithWallpaperApp {
                 }
                 Command::none()
             }
-            Message::ConfigSaved(result) => {
+            Message::ConfigSaved(result)
+            | Message::FileManagerOpened(result)
+            | Message::TagsSaved(result)
+            | Message::IndexSaved(result)
+            | Message::SourcesSaved(result) => {
                 if let Err(e) = result {
                     self.status_bar = StatusBar::Error(e.to_string());
                 }
                 Command::none()
             }
+            Message::CollectionSelected(collection) => {
+                self.collection = collection;
+                self.invalidate_gallery_order();
+                Command::none()
+            }
+            Message::SortSelected(sort_by) => {
+                self.sort_by = sort_by;
+                self.invalidate_gallery_order();
+                Command::none()
+            }
+            Message::ShowView(view_mode) => {
+                self.view_mode = view_mode;
+                match view_mode {
+                    ViewMode::Duplicates => self.find_duplicates(),
+                    ViewMode::Online => self.list_online(),
+                    ViewMode::Library => Command::none(),
+                }
+            }
+            Message::DuplicatesMessage(msg) => match self.duplicates.update(msg) {
+                Some(msg) => self.update(msg),
+                None => Command::none(),
+            },
+            Message::FindDuplicates => self.find_duplicates(),
+            Message::DuplicatesFound(groups) => {
+                self.duplicates = Duplicates::new(groups);
+                Command::none()
+            }
+            Message::FontLoaded(result) => {
+                if let Err(e) = result {
+                    tracing::error!(error=?e, "Failed to load icons font.");
+                }
+                Command::none()
+            }
+            Message::ColorPickerToogle(show) => {
+                self.color_picker_show = show;
+                Command::none()
+            }
+            Message::ColorPicked(color) => {
+                self.color_picker_show = false;
+                let [r, g, b, _] = color.into_rgba8();
+                self.color_ranking = Some(ColorRanking::Color([r, g, b]));
+                self.invalidate_gallery_order();
+                Command::none()
+            }
+            Message::MoreLikeThis(id) => {
+                if let Some(image) = self.images.iter().find(|image| image.id == id) {
+                    self.color_ranking = Some(ColorRanking::Like(image.entry.clone()));
+                    self.invalidate_gallery_order();
+                }
+                Command::none()
+            }
+            Message::ClearColorRanking => {
+                self.color_ranking = None;
+                self.invalidate_gallery_order();
+                Command::none()
+            }
+            Message::BrightnessFilterSelected(filter) => {
+                self.brightness_filter = filter;
+                self.invalidate_gallery_order();
+                Command::none()
+            }
+            Message::LookLoaded(Ok(look)) => {
+                let brightness = look_brightness(&look, &self.configuration.look_brightness);
+                tracing::info!(look, %brightness, "Loaded regolith look.");
+                self.look_brightness = Some(brightness);
+                Command::none()
+            }
+            Message::LookLoaded(Err(e)) => {
+                tracing::warn!(error.message=%e, "Failed to get the regolith look.");
+                Command::none()
+            }
+            Message::MatchLookToogle(match_look) => {
+                self.match_look = match_look;
+                Command::none()
+            }
+            Message::ScreenResolution(Ok(resolution)) => {
+                tracing::info!(%resolution, "Screen resolution.");
+                self.screen_resolution = Some(resolution);
+                Command::none()
+            }
+            Message::ScreenResolution(Err(e)) => {
+                tracing::warn!(error.message=%e, "Failed to get the screen resolution.");
+                Command::none()
+            }
+            Message::ConfirmApply => match self.pending_apply.take() {
+                Some(id) => self.select_image(id),
+                None => Command::none(),
+            },
+            Message::CancelApply => {
+                self.pending_apply = None;
+                Command::none()
+            }
+            Message::LibraryChanged(changes) => {
+                tracing::info!(
+                    changed = changes.changed.len(),
+                    removed = changes.removed.len(),
+                    regolith_config = changes.regolith_config,
+                    "Library changed."
+                );
+                let mut commands = Vec::new();
+                if changes.regolith_config {
+                    commands.push(Command::perform(
+                        load_regolith_config(),
+                        Message::CurrentWallpaperPath,
+                    ));
+                    commands.push(Command::perform(load_regolith_look(), Message::LookLoaded));
+                }
+                if !changes.removed.is_empty() {
+                    self.images.retain(|image| !changes.is_removed(&image.path));
+                    self.invalidate_gallery_order();
+                    let before = self.index.entries.len();
+                    self.index
+                        .entries
+                        .retain(|path, _| !changes.is_removed(path));
+                    if self.index.entries.len() != before {
+                        self.index_changed = true;
+                    }
+                    for path in &changes.removed {
+                        self.thumbnails.remove(path);
+                    }
+                    self.duplicates.remove(|path| changes.is_removed(path));
+                }
+                // Also saves the index if there are no images to analyze
+                commands.push(self.load_images(changes.changed.into_iter().collect()));
+                Command::batch(commands)
+            }
+            Message::ThumbnailSizeChanged(size) => self.set_thumbnail_size(size),
+            Message::GalleryScrolled(viewport) => {
+                self.gallery_offset = viewport.absolute_offset().y;
+                Command::none()
+            }
+            Message::ThumbnailLoaded(path, Ok(thumbnail)) => {
+                self.thumbnails.insert(path, thumbnail);
+                Command::none()
+            }
+            Message::ThumbnailLoaded(path, Err(e)) => {
+                tracing::warn!(?path, error.message=%e, "Failed to load thumbnail.");
+                self.thumbnails.failed(path.clone());
+                self.load_errors.add(path, e.to_string());
+                Command::none()
+            }
+            Message::FileAction(id, action) => {
+                let targets = self.action_targets(id);
+                self.file_action(targets, action)
+            }
+            Message::SelectionAction(action) => {
+                let targets = self.selected_paths();
+                self.file_action(targets, action)
+            }
+            Message::FileActionsMessage(msg) => match self.file_actions.update(msg) {
+                Some(msg) => self.update(msg),
+                None => Command::none(),
+            },
+            Message::RenameFile(path, name) => {
+                let from = path.clone();
+                Command::perform(rename_file(path, name), move |result| {
+                    Message::FilesMoved(vec![(from.clone(), result)])
+                })
+            }
+            Message::MoveFiles(paths, folder) => {
+                Command::perform(move_files(paths, folder), Message::FilesMoved)
+            }
+            Message::FilesMoved(results) => {
+                let mut moved = 0;
+                let mut errors = Vec::new();
+                let mut commands = Vec::new();
+                for (from, result) in results {
+                    match result {
+                        Ok(to) if to == from => {}
+                        Ok(to) => {
+                            self.file_moved(&from, &to);
+                            moved += 1;
+                            // Keep the current wallpaper working
+                            if self
+                                .current_wallpaper
+                                .as_ref()
+                                .is_some_and(|image| image.path == from)
+                            {
+                                commands.push(Command::perform(
+                                    set_wallpaper_on_config(to),
+                                    Message::CurrentWallpaperPath,
+                                ));
+                            }
+                        }
+                        Err(e) => errors.push(e),
+                    }
+                }
+                self.status_bar = match errors.first() {
+                    Some(e) => StatusBar::Error(format!("{} ({} errors)", e, errors.len())),
+                    None => StatusBar::Ok(format!("{} files moved.", moved)),
+                };
+                commands.push(self.save_tags());
+                if !self.sources.sources.is_empty() {
+                    commands.push(self.save_sources());
+                }
+                commands.push(self.save_index_if_done());
+                Command::batch(commands)
+            }
+            Message::ImportMessage(msg) => match self.import.update(msg) {
+                Some(msg) => self.update(msg),
+                None => Command::none(),
+            },
+            Message::ImportFiles(paths, folder, options) => {
+                Command::perform(import_files(paths, folder, options), Message::FilesImported)
+            }
+            Message::FilesImported(results) => {
+                let mut imported = Vec::new();
+                let mut duplicates = 0;
+                let mut errors = Vec::new();
+                for (_, result) in results {
+                    match result {
+                        Ok(path) => imported.push(path),
+                        Err(Error::DuplicateImage(_)) => duplicates += 1,
+                        Err(e) => errors.push(e),
+                    }
+                }
+                self.status_bar = match errors.first() {
+                    Some(e) => StatusBar::Error(format!("{} ({} errors)", e, errors.len())),
+                    None if duplicates > 0 => StatusBar::Ok(format!(
+                        "{} images imported, {} already on the library.",
+                        imported.len(),
+                        duplicates
+                    )),
+                    None => StatusBar::Ok(format!("{} images imported.", imported.len())),
+                };
+                // Images imported outside the library are not shown
+                imported.retain(|path| self.root().is_some_and(|root| path.starts_with(root)));
+                self.load_images(imported)
+            }
+            Message::TrashImages(paths) if self.is_read_only(&paths) => {
+                self.status_bar = StatusBar::Error(Error::ReadOnlyArchive.to_string());
+                Command::none()
+            }
+            Message::TrashImages(paths) => {
+                Command::perform(move_all_to_trash(paths), Message::ImagesTrashed)
+            }
+            Message::ImagesTrashed(results) => {
+                let mut trashed = HashSet::new();
+                let mut errors = Vec::new();
+                for (path, result) in results {
+                    match result {
+                        Ok(_) => {
+                            trashed.insert(path);
+                        }
+                        Err(e) => errors.push(e),
+                    }
+                }
+                self.images.retain(|image| !trashed.contains(&image.path));
+                self.invalidate_gallery_order();
+                self.selection.retain(|path| !trashed.contains(path));
+                self.index.entries.retain(|path, _| !trashed.contains(path));
+                self.duplicates.remove(|path| trashed.contains(path));
+                self.status_bar = match errors.first() {
+                    Some(e) => StatusBar::Error(format!("{} ({} errors)", e, errors.len())),
+                    None => StatusBar::Ok(format!("{} files moved to trash.", trashed.len())),
+                };
+                Command::perform(save_index(self.index.clone()), Message::IndexSaved)
+            }
+            Message::TagInputEdit(input) => {
+                self.tag_input = input;
+                Command::none()
+            }
+            Message::AddTag => {
+                let tag = std::mem::take(&mut self.tag_input);
+                self.tags.add_tag(&self.selected_paths(), &tag);
+                self.save_tags()
+            }
+            Message::RemoveTag(tag) => {
+                self.tags.remove_tag(&self.selected_paths(), &tag);
+                self.save_tags()
+            }
+            Message::ClearSelection => {
+                self.selection.clear();
+                Command::none()
+            }
+            Message::SelectAll => {
+                let order = self.visible_paths();
+                let order = order.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+                self.selection.select_all(&order);
+                Command::none()
+            }
+            Message::AddToCollection(collection) => {
+                self.tags.add_tag(&self.selected_paths(), &collection);
+                self.save_tags()
+            }
+            Message::ToggleFavorite => {
+                let selected = self.selected_paths();
+                if selected
+                    .iter()
+                    .all(|path| self.tags.has_tag(path, FAVORITE_TAG))
+                {
+                    self.tags.remove_tag(&selected, FAVORITE_TAG);
+                } else {
+                    self.tags.add_tag(&selected, FAVORITE_TAG);
+                }
+                self.save_tags()
+            }
+            Message::StartSlideshow => {
+                self.slideshow = Some(Slideshow::new(self.selected_paths()));
+                self.slideshow_next()
+            }
+            Message::StopSlideshow => {
+                self.slideshow = None;
+                Command::none()
+            }
+            Message::SlideshowTick => self.slideshow_next(),
+            Message::IpcCommand(command, reply) => self.ipc_command(command, reply),
+            Message::OnlineMessage(msg) => {
+                let command = match self.online.update(msg) {
+                    Some(msg) => self.update(msg),
+                    None => Command::none(),
+                };
+                Command::batch(vec![command, self.list_online()])
+            }
+            Message::OnlineListed(index, result) => {
+                let previews = self.online.listed(index, result);
+                let Some(provider) = self.online.provider(index) else {
+                    return Command::none();
+                };
+                Command::batch(previews.into_iter().map(|url| {
+                    let provider = provider.clone();
+                    let preview_url = url.clone();
+                    Command::perform(
+                        async move { provider.fetch(&preview_url).await },
+                        move |result| Message::PreviewLoaded(url.clone(), result),
+                    )
+                }))
+            }
+            Message::PreviewLoaded(url, result) => {
+                match result {
+                    Ok(bytes) => self.online.preview_loaded(url, bytes),
+                    Err(e) => tracing::warn!(url, error.message=%e, "Failed to load preview."),
+                }
+                Command::none()
+            }
+            Message::DownloadImages(index, images) => {
+                match self.root() {
+                    None => {
+                        self.status_bar = StatusBar::Error("Set the wallpapers path first.".into());
+                        return Command::none();
+                    }
+                    Some(root) if is_archive(root) => {
+                        self.status_bar = StatusBar::Error(Error::ReadOnlyArchive.to_string());
+                        return Command::none();
+                    }
+                    Some(_) => {}
+                }
+                let images = images
+                    .into_iter()
+                    .filter(|image| !self.sources.contains_url(&image.url))
+                    .collect();
+                self.online.queue.push(index, images);
+                self.start_downloads()
+            }
+            Message::ImageDownloaded(index, url, result) => {
+                self.online.queue.finish(index, &url);
+                let mut commands = vec![self.start_downloads()];
+                match result {
+                    Ok((path, source)) => {
+                        self.status_bar = StatusBar::Ok(format!("Downloaded {}", path.display()));
+                        self.sources.sources.insert(path.clone(), source);
+                        commands.push(self.save_sources());
+                        commands.push(self.load_images(vec![path]));
+                    }
+                    Err(e) => self.status_bar = StatusBar::Error(e.to_string()),
+                }
+                Command::batch(commands)
+            }
         }
     }
 
-    fn view(&self) -> Element<Self::Message> {
+    fn main_view(&self) -> Element<'_, Message> {
         let title = text("Regolith wallpaper picker").size(20).font(Font {
             weight: Weight::Bold,
             ..Default::default()
         });
 
-        let mut content = column!(title).spacing(25).padding(20);
+        let view_buttons = row([
+            ("Library", ViewMode::Library),
+            ("Duplicates", ViewMode::Duplicates),
+            ("Browse online", ViewMode::Online),
+        ]
+        .into_iter()
+        .map(|(label, view_mode)| {
+            let style = if view_mode == self.view_mode {
+                theme::Button::Primary
+            } else {
+                theme::Button::Secondary
+            };
+            button(text(label).size(14))
+                .padding([2, 10])
+                .style(style)
+                .on_press(Message::ShowView(view_mode))
+                .into()
+        })
+        .collect())
+        .spacing(4);
+        let mut content = column!(row!(title, horizontal_space(30), view_buttons))
+            .spacing(25)
+            .padding(20);
+        if let Some(banner) = self.config_banner.view() {
+            content = content.push(banner.map(Message::ConfigBannerMessage));
+        }
+
+        if self.view_mode == ViewMode::Duplicates {
+            content = content.push(
+                self.duplicates
+                    .view(&self.thumbnails)
+                    .map(Message::DuplicatesMessage),
+            );
+            return column!(content, self.status_bar.view(None)).into();
+        }
+        if self.view_mode == ViewMode::Online {
+            content = content.push(self.online.view(&self.sources).map(Message::OnlineMessage));
+            return column!(content, self.status_bar.view(None)).into();
+        }
 
         if self.wallpaper_path_show {
             let wallpaper_path = self
@@ -243,7 +1602,74 @@ impl Application for RegolithWallpaperApp {
                 show: true,
                 msg: None,
             });
-            content = content.push(edit_path_btn);
+            let collections = std::iter::once(Collection::All)
+                .chain(
+                    self.tags
+                        .collections(self.images.iter().map(|image| &image.path), self.root())
+                        .into_iter()
+                        .map(Collection::Named),
+                )
+                .collect::<Vec<_>>();
+            let collection_list = pick_list(
+                collections,
+                Some(self.collection.clone()),
+                Message::CollectionSelected,
+            )
+            .text_size(14)
+            .padding([2, 8]);
+            let sort_list = pick_list(&SortBy::ALL[..], Some(self.sort_by), Message::SortSelected)
+                .text_size(14)
+                .padding([2, 8]);
+            let color_btn = ColorPicker::new(
+                self.color_picker_show,
+                match &self.color_ranking {
+                    Some(ColorRanking::Color([r, g, b])) => Color::from_rgb8(*r, *g, *b),
+                    _ => Color::from_rgb(0.5, 0.5, 0.5),
+                },
+                button(text("Search by color").size(14))
+                    .padding([2, 8])
+                    .on_press(Message::ColorPickerToogle(true)),
+                Message::ColorPickerToogle(false),
+                Message::ColorPicked,
+            );
+            let brightness_list = pick_list(
+                &BrightnessFilter::ALL[..],
+                Some(self.brightness_filter),
+                Message::BrightnessFilterSelected,
+            )
+            .text_size(14)
+            .padding([2, 8]);
+            let zoom_slider = slider(
+                MIN_THUMBNAIL_SIZE..=MAX_THUMBNAIL_SIZE,
+                self.thumbnail_size,
+                Message::ThumbnailSizeChanged,
+            )
+            .step(THUMBNAIL_SIZE_STEP)
+            .width(120);
+            let mut header = row!(
+                edit_path_btn,
+                collection_list,
+                sort_list,
+                brightness_list,
+                color_btn,
+                text("Zoom").size(14),
+                zoom_slider
+            )
+            .spacing(10)
+            .align_items(Alignment::Center);
+            if let Some(ranking) = &self.color_ranking {
+                let label = match ranking {
+                    ColorRanking::Color(color) => format!("Closest to {} ×", to_hex(color)),
+                    ColorRanking::Like(entry) => format!("More like {} ×", entry.file_name()),
+                };
+                header = header.push(
+                    button(text(label).size(14))
+                        .padding([2, 6])
+                        .style(theme::Button::Secondary)
+                        .on_press(Message::ClearColorRanking),
+                );
+            }
+            content = content.push(header);
 
             if let Some(image) = &self.current_wallpaper {
                 let pick_random_btn = button(
@@ -253,11 +1679,38 @@ impl Application for RegolithWallpaperApp {
                 )
                 .padding([2, 4])
                 .on_press(Message::PickRandomImage);
+                let mut pick_random_row = row!(horizontal_space(30), pick_random_btn)
+                    .spacing(10)
+                    .align_items(Alignment::Center);
+                if let Some(brightness) = self.look_brightness {
+                    pick_random_row = pick_random_row.push(
+                        checkbox(
+                            format!("Match look ({})", brightness),
+                            self.match_look,
+                            Message::MatchLookToogle,
+                        )
+                        .size(14)
+                        .text_size(14),
+                    );
+                }
                 content = content.push(
                     column!(
                         text("Current wallpaper"),
-                        image.view(),
-                        row!(horizontal_space(30), pick_random_btn)
+                        image.view(
+                            image.thumbnail.as_ref().map(|thumbnail| &thumbnail.handle),
+                            self.screen_resolution,
+                            THUMBNAIL_WIDTH,
+                            TileState::default()
+                        ),
+                        text(
+                            self.sources
+                                .sources
+                                .get(&image.path)
+                                .map(|source| source.attribution())
+                                .unwrap_or_default()
+                        )
+                        .size(12),
+                        pick_random_row
                     )
                     .spacing(4),
                 );
@@ -267,141 +1720,166 @@ impl Application for RegolithWallpaperApp {
             }
         }
 
+        if let Some(warning) = self.apply_warning_view() {
+            content = content.push(warning);
+        }
+        if let Some(import) = self.import.view() {
+            content = content.push(import.map(Message::ImportMessage));
+        }
+        if let Some(file_actions) = self.file_actions.view() {
+            content = content.push(file_actions.map(Message::FileActionsMessage));
+        }
+
+        if !self.load_progress.is_done() {
+            content = content.push(self.load_progress.view());
+        }
+        if let Some(errors) = self.load_errors.view() {
+            content = content.push(errors.map(Message::LoadErrorsMessage));
+        }
+
+        if let Some(slideshow) = &self.slideshow {
+            content = content.push(
+                button(
+                    text(format!(
+                        "Rotating through {} images ×",
+                        slideshow.paths.len()
+                    ))
+                    .size(14),
+                )
+                .padding([2, 6])
+                .style(theme::Button::Secondary)
+                .on_press(Message::StopSlideshow),
+            );
+        }
+        let selected = self.selected_paths();
+        if !selected.is_empty() {
+            content = content.push(self.bulk_actions_view(&selected));
+        }
+
         if !self.images.is_empty() {
-            let images = Grid::with_children(
-                self.images
-                    .iter()
-                    .map(|image| image.view())
-                    .collect::<Vec<_>>(),
-            )
-            .strategy(iced_aw::Strategy::ColumnWidth(384.0));
-            content = content
-                .push(scrollable(container(images).width(Length::Fill).center_x()))
-                .height(Length::FillPortion(9));
+            let gallery = gallery_view(
+                self.visible_images(),
+                &self.thumbnails,
+                self.screen_resolution,
+                self.gallery_offset,
+                self.thumbnail_size,
+                &self.selection,
+            );
+            content = content.push(gallery).height(Length::FillPortion(9));
         }
 
         column!(
             content,
             vertical_space(Length::Fill),
-            self.status_bar.view()
+            self.status_bar.view(
+                (!selected.is_empty())
+                    .then(|| format!("{} selected", format_count(selected.len())))
+            )
         )
         .into()
     }
-}
-
-async fn save_config(config: Configuration) -> Result<()> {
-    let content = serde_yaml::to_string(&config).map_err(|e| {
-        tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to serialize content.");
-        Error::UnexpectedError(e.to_string())
-    })?;
-    let path = get_configuration_path()?;
-    write(&path, content).await.map_err(|e| {
-        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to path file.");
-        Error::FailedToWriteFile(path.clone())
-    })?;
-    Ok(())
-}
 
-#[tracing::instrument]
-pub async fn load_image_files(path: PathBuf) -> Result<Vec<PathBuf>> {
-    tracing::info!("Loading files...");
-    let image_files = ReadDirStream::new(
-        read_dir(path)
-            .await
-            .map_err(|e| Error::UnexpectedError(format!("Failed to read files {}", e)))?,
-    )
-    .filter_map(|res| async {
-        match res {
-            Ok(x) => {
-                let path = x.path();
-                if path.is_dir() {
-                    None
-                } else {
-                    path.extension()
-                        .and_then(ImageFormat::from_extension)
-                        .map(|_| path)
-                }
-            }
-            Err(_) => None,
-        }
-    })
-    .collect::<Vec<_>>()
-    .await;
-    tracing::info!("{} files loaded.", image_files.len());
-    Ok(image_files)
-}
-
-async fn read_regolith_config() -> Result<String> {
-    let path = expand_home_dir("~/.config/regolith3/Xresources");
-    if !path.exists() {
-        return Err(Error::NoRegConfigFile);
-    }
-    read_to_string(path).await.map_err(|e| {
-        tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to read file.");
-        Error::FailedReadRegConfigFile
-    })
-}
-
-async fn load_regolith_config() -> Result<PathBuf> {
-    read_regolith_config()
-        .await?
-        .lines()
-        .find(|line| line.starts_with("regolith.wallpaper.file:"))
-        .ok_or(Error::NoWallpaperOnRegConfigFile)?
-        .split(':')
-        .nth(1)
-        .ok_or(Error::NoWallpaperOnRegConfigFile)
-        .map(|path| expand_home_dir(path.trim()))
-}
-
-/// Sets the path on the current regolith config file, if success returns the
-/// setted image path
-pub async fn set_wallpaper_on_config(path: PathBuf) -> Result<PathBuf> {
-    let content = read_regolith_config().await?;
-    let mut lines = content.lines();
-    let mut new_content = String::new();
-    for line in &mut lines {
-        if line.starts_with("regolith.wallpaper.file:") {
-            let path_str = path.to_str().unwrap().to_string();
-            new_content.push_str(&format!("regolith.wallpaper.file: {}\n", path_str));
-            break;
-        } else {
-            new_content.push_str(line);
-            new_content.push('\n');
-        }
-    }
-    new_content.push_str(&lines.collect::<Vec<_>>().join("\n"));
-    let config_path = expand_home_dir("~/.config/regolith3/Xresources");
-    write(&config_path, new_content).await.map_err(|e| {
-        tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to write file.");
-        Error::FailedToWriteFile(config_path)
-    })?;
-    let exit_status = tokio::process::Command::new("/usr/bin/regolith-look")
-        .arg("refresh")
-        .status()
-        .await
-        .map_err(|e| {
-            tracing::error!(error.cause_chain=?e, error.message=%e);
-            Error::FailedToRunRefresh
-        })?;
-    if exit_status.success() {
-        Ok(path)
-    } else {
-        tracing::error!(
-            "Failed to run command, exited with code: {:?}",
-            exit_status.code()
-        );
-        Err(Error::FailedToRunRefresh)
+    /// Asks for confirmation before applying an image smaller than the screen
+    fn apply_warning_view(&self) -> Option<Element<'_, Message>> {
+        let id = self.pending_apply?;
+        let image = self.images.iter().find(|image| image.id == id)?;
+        let screen = self.screen_resolution?;
+        let warning = text(format!(
+            "{} is {}, smaller than the screen ({}). Apply anyway?",
+            image.entry.file_name(),
+            image.entry.resolution(),
+            screen
+        ))
+        .size(14)
+        .style(Color::from_rgb(0.95, 0.7, 0.3));
+        let apply_btn = button(text("Apply").size(14))
+            .padding([2, 10])
+            .style(theme::Button::Positive)
+            .on_press(Message::ConfirmApply);
+        let cancel_btn = button(text("Cancel").size(14))
+            .padding([2, 10])
+            .style(theme::Button::Destructive)
+            .on_press(Message::CancelApply);
+        Some(
+            row!(warning, apply_btn, cancel_btn)
+                .spacing(10)
+                .align_items(Alignment::Center)
+                .into(),
+        )
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    #[tokio::test]
-    async fn load_regolith_config_works() {
-        let res = load_regolith_config().await;
-        println!("{:?}", res);
+    /// Edits the tags of the selected images, and applies the actions of the
+    /// context menu to all of them
+    fn bulk_actions_view(&self, selected: &[PathBuf]) -> Element<'_, Message> {
+        let tags = selected
+            .iter()
+            .filter_map(|path| self.tags.tags.get(path))
+            .flatten()
+            .collect::<BTreeSet<_>>();
+        let tag_buttons = Row::with_children(
+            tags.into_iter()
+                .map(|tag| {
+                    button(text(format!("{} ×", tag)).size(14))
+                        .padding([2, 6])
+                        .style(theme::Button::Secondary)
+                        .on_press(Message::RemoveTag(tag.clone()))
+                        .into()
+                })
+                .collect(),
+        )
+        .spacing(4);
+        let input = text_input("Add tag...", &self.tag_input)
+            .on_input(Message::TagInputEdit)
+            .on_submit(Message::AddTag)
+            .size(14)
+            .width(200);
+        let collections = self
+            .tags
+            .collections(self.images.iter().map(|image| &image.path), self.root())
+            .into_iter()
+            .collect::<Vec<_>>();
+        let collection_list = pick_list(collections, None, Message::AddToCollection)
+            .placeholder("Add to collection...")
+            .text_size(14)
+            .padding([2, 8]);
+        let favorite = selected
+            .iter()
+            .all(|path| self.tags.has_tag(path, FAVORITE_TAG));
+        let action_btn = |label: &'static str, message| {
+            button(text(label).size(14))
+                .padding([2, 6])
+                .style(theme::Button::Secondary)
+                .on_press(message)
+        };
+        let actions = row!(
+            action_btn(
+                if favorite {
+                    "★ Unfavorite"
+                } else {
+                    "☆ Favorite"
+                },
+                Message::ToggleFavorite
+            ),
+            action_btn("Move to…", Message::SelectionAction(FileAction::Move)),
+            action_btn("Move to trash", Message::SelectionAction(FileAction::Trash))
+                .style(theme::Button::Destructive),
+            action_btn("Rotate through these", Message::StartSlideshow),
+            action_btn("Select all", Message::SelectAll),
+            action_btn("Clear selection", Message::ClearSelection),
+        )
+        .spacing(6);
+        column!(
+            row!(
+                text(format!("{} selected", format_count(selected.len()))).size(14),
+                tag_buttons,
+                input,
+                collection_list
+            )
+            .spacing(10)
+            .align_items(Alignment::Center),
+            actions
+        )
+        .spacing(6)
+        .into()
     }
 }
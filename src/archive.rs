@@ -0,0 +1,377 @@
+use crate::{get_cache_dir, Error, Result};
+use flate2::read::GzDecoder;
+use image::ImageFormat;
+use std::fs::{create_dir_all, remove_dir_all, rename, write, File};
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::UNIX_EPOCH;
+
+/// Max size of an image inside an archive, bigger entries are skipped
+const MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Archives that can be imported, or browsed as the library root
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether `path` is an archive file
+pub fn is_archive(path: &Path) -> bool {
+    ArchiveKind::from_path(path).is_some() && path.is_file()
+}
+
+/// Name of the archive without its extension, used as collection name
+pub fn archive_stem(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    [".tar.gz", ".tgz", ".tar", ".zip"]
+        .into_iter()
+        .find_map(|ext| {
+            name.to_lowercase()
+                .ends_with(ext)
+                .then(|| name[..name.len() - ext.len()].to_string())
+        })
+        .unwrap_or(name)
+}
+
+/// Splits the path of an image inside an archive (e.g.:
+/// `pack.zip/space/moon.jpg`) into the archive and the entry name
+pub fn split_archive_path(path: &Path) -> Option<(&Path, &Path)> {
+    let archive = path.ancestors().skip(1).find(|path| is_archive(path))?;
+    Some((archive, path.strip_prefix(archive).ok()?))
+}
+
+/// Image entries, skipping the ones that could be written outside the target
+/// folder and the hidden ones (e.g.: `__MACOSX/._moon.jpg`)
+fn image_entry_name(name: &Path) -> Option<PathBuf> {
+    let safe = name.components().all(|component| match component {
+        Component::Normal(part) => !part.to_string_lossy().starts_with('.') && part != "__MACOSX",
+        _ => false,
+    });
+    (safe && ImageFormat::from_path(name).is_ok()).then(|| name.to_path_buf())
+}
+
+fn open_tar(archive: &Path, kind: ArchiveKind) -> Result<tar::Archive<Box<dyn Read>>> {
+    let file =
+        BufReader::new(File::open(archive).map_err(|_| Error::FailedToRead(archive.into()))?);
+    let reader: Box<dyn Read> = match kind {
+        ArchiveKind::TarGz => Box::new(GzDecoder::new(file)),
+        _ => Box::new(file),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+fn open_zip(archive: &Path) -> Result<zip::ZipArchive<BufReader<File>>> {
+    let file = File::open(archive).map_err(|_| Error::FailedToRead(archive.into()))?;
+    zip::ZipArchive::new(BufReader::new(file)).map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?archive, "Failed to open archive.");
+        Error::InvalidArchive(archive.into())
+    })
+}
+
+/// Content of an entry, `None` if it is bigger than `limit` (whatever size the
+/// archive declares)
+fn read_entry(
+    reader: impl Read,
+    declared_size: u64,
+    limit: u64,
+) -> std::io::Result<Option<Vec<u8>>> {
+    let mut bytes = Vec::with_capacity(declared_size.min(limit) as usize);
+    reader.take(limit + 1).read_to_end(&mut bytes)?;
+    Ok((bytes.len() as u64 <= limit).then_some(bytes))
+}
+
+/// Calls `f` with the name and content of each image inside the archive, until
+/// it returns `false`. Images bigger than [`MAX_ENTRY_SIZE`] are skipped.
+pub fn for_each_image(archive: &Path, mut f: impl FnMut(PathBuf, Vec<u8>) -> bool) -> Result<()> {
+    let invalid = |e: std::io::Error| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?archive, "Failed to read archive.");
+        Error::InvalidArchive(archive.into())
+    };
+    match ArchiveKind::from_path(archive).ok_or(Error::InvalidArchive(archive.into()))? {
+        ArchiveKind::Zip => {
+            let mut zip = open_zip(archive)?;
+            for i in 0..zip.len() {
+                let mut file = zip
+                    .by_index(i)
+                    .map_err(|e| invalid(std::io::Error::other(e)))?;
+                let Some(name) = file
+                    .enclosed_name()
+                    .filter(|_| file.is_file())
+                    .and_then(|name| image_entry_name(&name))
+                else {
+                    continue;
+                };
+                let size = file.size();
+                let Some(bytes) = read_entry(&mut file, size, MAX_ENTRY_SIZE).map_err(invalid)?
+                else {
+                    tracing::warn!(?archive, ?name, "Image too big, skipped.");
+                    continue;
+                };
+                if !f(name, bytes) {
+                    break;
+                }
+            }
+        }
+        kind => {
+            let mut tar = open_tar(archive, kind)?;
+            for entry in tar.entries().map_err(invalid)? {
+                let mut entry = entry.map_err(invalid)?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let Some(name) = entry.path().ok().and_then(|name| image_entry_name(&name)) else {
+                    continue;
+                };
+                let size = entry.size();
+                let Some(bytes) = read_entry(&mut entry, size, MAX_ENTRY_SIZE).map_err(invalid)?
+                else {
+                    tracing::warn!(?archive, ?name, "Image too big, skipped.");
+                    continue;
+                };
+                if !f(name, bytes) {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Names of the images inside the archive
+pub fn list_archive_images(archive: &Path) -> Result<Vec<PathBuf>> {
+    match ArchiveKind::from_path(archive).ok_or(Error::InvalidArchive(archive.into()))? {
+        ArchiveKind::Zip => {
+            let mut zip = open_zip(archive)?;
+            Ok((0..zip.len())
+                .filter_map(|i| {
+                    let file = zip.by_index_raw(i).ok()?;
+                    file.enclosed_name()
+                        .filter(|_| file.is_file())
+                        .and_then(|name| image_entry_name(&name))
+                })
+                .collect())
+        }
+        kind => {
+            let mut tar = open_tar(archive, kind)?;
+            let entries = tar
+                .entries()
+                .map_err(|_| Error::InvalidArchive(archive.into()))?;
+            Ok(entries
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    if !entry.header().entry_type().is_file() {
+                        return None;
+                    }
+                    image_entry_name(&entry.path().ok()?)
+                })
+                .collect())
+        }
+    }
+}
+
+/// Content of an image file, also inside an archive
+pub fn read_image_bytes(path: &Path) -> Result<Vec<u8>> {
+    let Some((archive, name)) = split_archive_path(path) else {
+        return std::fs::read(path).map_err(|_| Error::FailedToRead(path.into()));
+    };
+    if ArchiveKind::from_path(archive) == Some(ArchiveKind::Zip) {
+        let mut zip = open_zip(archive)?;
+        let mut file = zip
+            .by_name(&name.to_string_lossy())
+            .map_err(|_| Error::FailedToRead(path.into()))?;
+        let size = file.size();
+        return read_entry(&mut file, size, MAX_ENTRY_SIZE)
+            .ok()
+            .flatten()
+            .ok_or(Error::FailedToRead(path.into()));
+    }
+    let folder = unpacked_tar(archive, &get_cache_dir()?.join("archives"))?;
+    std::fs::read(folder.join(name)).map_err(|_| Error::FailedToRead(path.into()))
+}
+
+/// Folder inside `cache_dir` the images of a tar archive are unpacked to, once
+/// per version of the archive: an entry can only be reached by reading (and
+/// decompressing) all the previous ones
+fn unpacked_tar(archive: &Path, cache_dir: &Path) -> Result<PathBuf> {
+    // Thumbnails of the same archive are loaded in parallel
+    static UNPACKING: Mutex<()> = Mutex::new(());
+    let metadata = std::fs::metadata(archive).map_err(|_| Error::FailedToRead(archive.into()))?;
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    let version = format!(
+        "{}:{}:{}",
+        archive.display(),
+        metadata.len(),
+        mtime.as_nanos()
+    );
+    let key = blake3::hash(version.as_bytes()).to_hex();
+    let folder = cache_dir.join(key.as_str());
+    let _unpacking = UNPACKING.lock().unwrap_or_else(PoisonError::into_inner);
+    if folder.is_dir() {
+        return Ok(folder);
+    }
+    // Unpacked next to it and renamed, so an interrupted unpack isn't used
+    let tmp_folder = cache_dir.join(format!("{}.tmp-{}", key, std::process::id()));
+    let _ = remove_dir_all(&tmp_folder);
+    create_dir_all(&tmp_folder).map_err(|_| Error::FailedToWriteFile(tmp_folder.clone()))?;
+    let mut written = Ok(());
+    for_each_image(archive, |name, bytes| {
+        let path = tmp_folder.join(name);
+        written = path
+            .parent()
+            .map_or(Ok(()), create_dir_all)
+            .and_then(|_| write(&path, bytes))
+            .map_err(|_| Error::FailedToWriteFile(path));
+        written.is_ok()
+    })?;
+    if let Err(e) = written.and_then(|_| {
+        rename(&tmp_folder, &folder).map_err(|_| Error::FailedToWriteFile(folder.clone()))
+    }) {
+        let _ = remove_dir_all(&tmp_folder);
+        // Unpacked meanwhile by another instance
+        if !folder.is_dir() {
+            return Err(e);
+        }
+    }
+    tracing::info!(?archive, ?folder, "Archive unpacked.");
+    Ok(folder)
+}
+
+/// Extracts an image from an archive used as library root to the cache, so it
+/// can be set as wallpaper. Other paths are returned as they are.
+pub async fn extract_if_archived(path: PathBuf) -> Result<PathBuf> {
+    if split_archive_path(&path).is_none() {
+        return Ok(path);
+    }
+    tokio::task::spawn_blocking(move || {
+        let bytes = read_image_bytes(&path)?;
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let folder = get_cache_dir()?.join("extracted");
+        let extracted = folder.join(format!("{}.{}", blake3::hash(&bytes).to_hex(), extension));
+        if !extracted.exists() {
+            create_dir_all(&folder).map_err(|_| Error::FailedToWriteFile(folder.clone()))?;
+            write(&extracted, bytes).map_err(|_| Error::FailedToWriteFile(extracted.clone()))?;
+        }
+        tracing::info!(?path, ?extracted, "Image extracted from archive.");
+        Ok(extracted)
+    })
+    .await
+    .map_err(|e| Error::UnexpectedError(e.to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    /// `Pack.ZIP` with an image, a text file, macOS metadata and an entry
+    /// trying to escape the archive
+    fn zip_fixture(root: &TempDir) -> PathBuf {
+        let path = root.path().join("Pack.ZIP");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        for (name, content) in [
+            ("space/moon.png", &b"moon"[..]),
+            ("readme.txt", b"text"),
+            ("__MACOSX/space/._moon.png", b"meta"),
+            ("../escape.png", b"evil"),
+        ] {
+            zip.start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap();
+        path
+    }
+
+    /// `pack.tar.gz` with a single image
+    fn tar_fixture(root: &TempDir) -> PathBuf {
+        let path = root.path().join("pack.tar.gz");
+        let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+            File::create(&path).unwrap(),
+            flate2::Compression::default(),
+        ));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(3);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, "sun.jpg", &b"sun"[..])
+            .unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn zip_listing_skips_other_files() {
+        let root = tempfile::tempdir().unwrap();
+        let images = list_archive_images(&zip_fixture(&root)).unwrap();
+        assert_eq!(images, [PathBuf::from("space/moon.png")]);
+    }
+
+    #[test]
+    fn tar_images_are_listed() {
+        let root = tempfile::tempdir().unwrap();
+        let images = list_archive_images(&tar_fixture(&root)).unwrap();
+        assert_eq!(images, [PathBuf::from("sun.jpg")]);
+    }
+
+    #[test]
+    fn zip_images_are_read() {
+        let root = tempfile::tempdir().unwrap();
+        let moon = read_image_bytes(&zip_fixture(&root).join("space/moon.png")).unwrap();
+        assert_eq!(moon, b"moon");
+    }
+
+    #[test]
+    fn tar_archives_are_unpacked_once() {
+        let root = tempfile::tempdir().unwrap();
+        let archive = tar_fixture(&root);
+        let cache_dir = root.path().join("cache");
+        let folder = unpacked_tar(&archive, &cache_dir).unwrap();
+        assert_eq!(std::fs::read(folder.join("sun.jpg")).unwrap(), b"sun");
+        std::fs::write(folder.join("sun.jpg"), b"cached").unwrap();
+        let folder = unpacked_tar(&archive, &cache_dir).unwrap();
+        assert_eq!(std::fs::read(folder.join("sun.jpg")).unwrap(), b"cached");
+        assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn entries_over_the_limit_are_skipped() {
+        // The declared size isn't trusted
+        let bytes = read_entry(&b"12345"[..], 2, 5).unwrap();
+        assert_eq!(bytes.as_deref(), Some(&b"12345"[..]));
+        assert_eq!(read_entry(&b"12345"[..], 2, 4).unwrap(), None);
+    }
+
+    #[test]
+    fn stems_drop_every_archive_extension() {
+        assert_eq!(archive_stem(Path::new("/downloads/pack.tar.gz")), "pack");
+        assert_eq!(archive_stem(Path::new("/downloads/Pack.ZIP")), "Pack");
+    }
+}
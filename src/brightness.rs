@@ -0,0 +1,197 @@
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Overall tone of an image, used to match the wallpaper with light or dark
+/// Regolith looks
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Brightness {
+    Dark,
+    #[default]
+    Mid,
+    Light,
+}
+
+impl Brightness {
+    pub const ALL: [Brightness; 3] = [Brightness::Dark, Brightness::Mid, Brightness::Light];
+}
+
+impl fmt::Display for Brightness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Brightness::Dark => "dark",
+            Brightness::Mid => "mid",
+            Brightness::Light => "light",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Luminance statistics of an image, all values are in the 0-1 range
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LuminanceStats {
+    pub mean: f32,
+    pub std_dev: f32,
+    /// Mean luminance difference between neighbour pixels, how busy (detailed)
+    /// the image is
+    pub busyness: f32,
+}
+
+impl LuminanceStats {
+    pub fn from_image(img: &RgbaImage) -> Self {
+        let (width, height) = img.dimensions();
+        if width == 0 || height == 0 {
+            return Self::default();
+        }
+        let luma = img
+            .pixels()
+            .map(|pixel| {
+                let [r, g, b, _] = pixel.0.map(|channel| f32::from(channel) / 255.0);
+                0.2126 * r + 0.7152 * g + 0.0722 * b
+            })
+            .collect::<Vec<_>>();
+        let n = luma.len() as f32;
+        let mean = luma.iter().sum::<f32>() / n;
+        let std_dev = (luma.iter().map(|l| (l - mean).powi(2)).sum::<f32>() / n).sqrt();
+        let (width, height) = (width as usize, height as usize);
+        let mut gradient = 0.0;
+        let mut count = 0;
+        for y in 0..height {
+            for x in 0..width {
+                let l = luma[y * width + x];
+                if x + 1 < width {
+                    gradient += (l - luma[y * width + x + 1]).abs();
+                    count += 1;
+                }
+                if y + 1 < height {
+                    gradient += (l - luma[(y + 1) * width + x]).abs();
+                    count += 1;
+                }
+            }
+        }
+        let busyness = if count > 0 {
+            // ~0.1 of mean gradient is already a very detailed image
+            (gradient / count as f32 * 10.0).min(1.0)
+        } else {
+            0.0
+        };
+        Self {
+            mean,
+            std_dev,
+            busyness,
+        }
+    }
+
+    /// Dark or light images that are busy and high contrast have bright and
+    /// dark spots all over, so they are classified as mid
+    pub fn brightness(&self) -> Brightness {
+        let noisy = self.busyness > 0.5 && self.std_dev > 0.25;
+        if self.mean < 0.3 && !noisy {
+            Brightness::Dark
+        } else if self.mean > 0.65 && !noisy {
+            Brightness::Light
+        } else {
+            Brightness::Mid
+        }
+    }
+}
+
+/// Tone filter for the gallery
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BrightnessFilter {
+    #[default]
+    All,
+    Only(Brightness),
+}
+
+impl BrightnessFilter {
+    pub const ALL: [BrightnessFilter; 4] = [
+        BrightnessFilter::All,
+        BrightnessFilter::Only(Brightness::Dark),
+        BrightnessFilter::Only(Brightness::Mid),
+        BrightnessFilter::Only(Brightness::Light),
+    ];
+
+    pub fn matches(&self, brightness: Brightness) -> bool {
+        match self {
+            BrightnessFilter::All => true,
+            BrightnessFilter::Only(b) => *b == brightness,
+        }
+    }
+}
+
+impl fmt::Display for BrightnessFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrightnessFilter::All => write!(f, "Any brightness"),
+            BrightnessFilter::Only(b) => write!(f, "Only {}", b),
+        }
+    }
+}
+
+/// Brightness of a Regolith look, from the user overrides or guessed from its
+/// name (most looks are dark)
+pub fn look_brightness(
+    look: &str,
+    overrides: &std::collections::BTreeMap<String, Brightness>,
+) -> Brightness {
+    if let Some(brightness) = overrides.get(look) {
+        return *brightness;
+    }
+    if look.to_lowercase().contains("light") {
+        Brightness::Light
+    } else {
+        Brightness::Dark
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn flat_images_are_classified_by_luminance() {
+        let dark = RgbaImage::from_pixel(8, 8, Rgba([20, 20, 30, 255]));
+        let light = RgbaImage::from_pixel(8, 8, Rgba([230, 230, 220, 255]));
+        let mid = RgbaImage::from_pixel(8, 8, Rgba([128, 128, 128, 255]));
+        assert_eq!(
+            LuminanceStats::from_image(&dark).brightness(),
+            Brightness::Dark
+        );
+        assert_eq!(
+            LuminanceStats::from_image(&light).brightness(),
+            Brightness::Light
+        );
+        assert_eq!(
+            LuminanceStats::from_image(&mid).brightness(),
+            Brightness::Mid
+        );
+    }
+
+    #[test]
+    fn busy_high_contrast_images_are_mid() {
+        let checkerboard = RgbaImage::from_fn(8, 8, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        });
+        let stats = LuminanceStats::from_image(&checkerboard);
+        assert!(stats.busyness > 0.9);
+        assert_eq!(stats.brightness(), Brightness::Mid);
+    }
+
+    #[test]
+    fn look_brightness_uses_overrides() {
+        let mut overrides = std::collections::BTreeMap::new();
+        assert_eq!(look_brightness("ayu-light", &overrides), Brightness::Light);
+        assert_eq!(look_brightness("nord", &overrides), Brightness::Dark);
+        overrides.insert("nord".to_string(), Brightness::Light);
+        assert_eq!(look_brightness("nord", &overrides), Brightness::Light);
+    }
+}
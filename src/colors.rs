@@ -0,0 +1,253 @@
+use crate::{Brightness, ImageEntry};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub type Rgb = [u8; 3];
+
+/// Number of dominant colors stored per image
+pub const DOMINANT_COLORS: usize = 5;
+
+/// Most common colors of the image, computed by bucketing the pixels into a
+/// 16x16x16 grid and averaging the most populated buckets
+pub fn dominant_colors(img: &RgbaImage, n: usize) -> Vec<Rgb> {
+    let mut buckets: HashMap<u16, (u32, [u32; 3])> = HashMap::new();
+    for pixel in img.pixels() {
+        let [r, g, b, a] = pixel.0;
+        if a < 128 {
+            continue;
+        }
+        let key = (u16::from(r >> 4) << 8) | (u16::from(g >> 4) << 4) | u16::from(b >> 4);
+        let (count, sum) = buckets.entry(key).or_default();
+        *count += 1;
+        sum[0] += u32::from(r);
+        sum[1] += u32::from(g);
+        sum[2] += u32::from(b);
+    }
+    let mut buckets = buckets.into_values().collect::<Vec<_>>();
+    buckets.sort_by_key(|(count, _)| std::cmp::Reverse(*count));
+    buckets
+        .into_iter()
+        .take(n)
+        .map(|(count, sum)| sum.map(|channel| (channel / count) as u8))
+        .collect()
+}
+
+/// Bins per channel of the color histogram
+const HISTOGRAM_BINS: usize = 4;
+
+/// Normalized 4x4x4 RGB histogram, each bin is the fraction of pixels scaled
+/// to 0-255
+pub fn color_histogram(img: &RgbaImage) -> Vec<u8> {
+    let mut counts = vec![0u32; HISTOGRAM_BINS.pow(3)];
+    let shift = 8 - HISTOGRAM_BINS.trailing_zeros();
+    for pixel in img.pixels() {
+        let [r, g, b, _] = pixel.0.map(|channel| usize::from(channel >> shift));
+        counts[(r * HISTOGRAM_BINS + g) * HISTOGRAM_BINS + b] += 1;
+    }
+    let total = counts.iter().sum::<u32>().max(1) as f32;
+    counts
+        .into_iter()
+        .map(|count| (count as f32 / total * 255.0).round() as u8)
+        .collect()
+}
+
+pub fn to_hex(color: &Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+/// Parses colors like `#1e90ff`, `1e90ff` or `#19f`
+pub fn parse_hex(s: &str) -> Result<Rgb, String> {
+    let hex = s.trim().trim_start_matches('#');
+    let hex = match hex.len() {
+        3 => hex.chars().flat_map(|c| [c, c]).collect::<String>(),
+        6 => hex.to_string(),
+        _ => return Err(format!("Invalid color `{}`, expected `#rrggbb`.", s)),
+    };
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .map_err(|_| format!("Invalid color `{}`, expected `#rrggbb`.", s))
+    };
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Color on the CIELAB space (D65 white point)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+impl From<Rgb> for Lab {
+    fn from(rgb: Rgb) -> Self {
+        let [r, g, b] = rgb.map(|channel| {
+            let c = f32::from(channel) / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        });
+        let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+        let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+        let f = |t: f32| {
+            if t > 0.008856 {
+                t.cbrt()
+            } else {
+                7.787 * t + 16.0 / 116.0
+            }
+        };
+        let (fx, fy, fz) = (f(x), f(y), f(z));
+        Self {
+            l: 116.0 * fy - 16.0,
+            a: 500.0 * (fx - fy),
+            b: 200.0 * (fy - fz),
+        }
+    }
+}
+
+impl Lab {
+    /// CIE76 color difference, ~2.3 is a just noticeable difference
+    pub fn delta_e(&self, other: &Lab) -> f32 {
+        ((self.l - other.l).powi(2) + (self.a - other.a).powi(2) + (self.b - other.b).powi(2))
+            .sqrt()
+    }
+}
+
+/// Distance between a color and a palette sorted by dominance: the closest
+/// palette color, penalizing the less dominant ones
+pub fn palette_color_distance(palette: &[Rgb], color: Rgb) -> f32 {
+    let color = Lab::from(color);
+    palette
+        .iter()
+        .enumerate()
+        .map(|(rank, &c)| Lab::from(c).delta_e(&color) * (1.0 + 0.15 * rank as f32))
+        .fold(f32::INFINITY, f32::min)
+}
+
+/// Symmetric distance between two palettes: mean distance from each color to
+/// the closest one on the other palette
+pub fn palette_distance(a: &[Rgb], b: &[Rgb]) -> f32 {
+    if a.is_empty() || b.is_empty() {
+        return f32::INFINITY;
+    }
+    let one_way = |from: &[Rgb], to: &[Rgb]| {
+        let to = to.iter().map(|&c| Lab::from(c)).collect::<Vec<_>>();
+        from.iter()
+            .map(|&c| {
+                let c = Lab::from(c);
+                to.iter()
+                    .map(|other| c.delta_e(other))
+                    .fold(f32::INFINITY, f32::min)
+            })
+            .sum::<f32>()
+            / from.len() as f32
+    };
+    (one_way(a, b) + one_way(b, a)) / 2.0
+}
+
+/// 0 for identical histograms, 1 for histograms without overlap
+pub fn histogram_distance(a: &[u8], b: &[u8]) -> f32 {
+    let intersection = a
+        .iter()
+        .zip(b)
+        .map(|(&x, &y)| u32::from(x.min(y)))
+        .sum::<u32>();
+    1.0 - (intersection as f32 / 255.0).min(1.0)
+}
+
+/// Number of closest images to pick from when picking by color
+pub const COLOR_PICK_CANDIDATES: usize = 10;
+
+/// Filters used when picking a wallpaper from the CLI, the daemon or the IPC
+/// socket
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PickOptions {
+    pub collection: Option<String>,
+    /// Pick among the images whose dominant colors are closest to this one
+    pub color: Option<Rgb>,
+    pub brightness: Option<Brightness>,
+    /// Pick images whose brightness matches the active Regolith look
+    pub match_look: bool,
+}
+
+/// Order of the images when searching by color
+#[derive(Clone, Debug)]
+pub enum ColorRanking {
+    /// Closest dominant palette to a color first
+    Color(Rgb),
+    /// Most similar images to this one first ("more like this")
+    Like(ImageEntry),
+}
+
+impl ColorRanking {
+    pub fn distance(&self, entry: &ImageEntry) -> f32 {
+        match self {
+            ColorRanking::Color(color) => palette_color_distance(&entry.colors, *color),
+            ColorRanking::Like(other) => image_distance(other, entry),
+        }
+    }
+
+    pub fn sort(&self, entries: &mut [&ImageEntry]) {
+        entries.sort_by(|a, b| self.distance(a).total_cmp(&self.distance(b)));
+    }
+}
+
+/// Dissimilarity used by "more like this": palette distance (scaled to ~0-1)
+/// plus histogram distance
+pub fn image_distance(a: &ImageEntry, b: &ImageEntry) -> f32 {
+    palette_distance(&a.colors, &b.colors) / 100.0 + histogram_distance(&a.histogram, &b.histogram)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn dominant_colors_are_sorted_by_frequency() {
+        let img = RgbaImage::from_fn(10, 10, |x, _| {
+            if x < 7 {
+                Rgba([250, 10, 10, 255])
+            } else {
+                Rgba([10, 10, 250, 255])
+            }
+        });
+        let colors = dominant_colors(&img, 5);
+        assert_eq!(colors, vec![[250, 10, 10], [10, 10, 250]]);
+        assert_eq!(to_hex(&colors[0]), "#fa0a0a");
+    }
+
+    #[test]
+    fn parse_hex_colors() {
+        assert_eq!(parse_hex("#1e90ff"), Ok([0x1e, 0x90, 0xff]));
+        assert_eq!(parse_hex("f0a"), Ok([0xff, 0x00, 0xaa]));
+        assert!(parse_hex("#12345").is_err());
+        assert!(parse_hex("zzzzzz").is_err());
+    }
+
+    #[test]
+    fn lab_distance_is_perceptual() {
+        let white = Lab::from([255, 255, 255]);
+        assert!((white.l - 100.0).abs() < 0.1);
+        assert!(white.a.abs() < 0.1 && white.b.abs() < 0.1);
+        let red = [220, 20, 20];
+        let palette = [[10, 10, 200], [200, 30, 30]];
+        assert!(
+            palette_color_distance(&palette, red) < palette_color_distance(&palette, [0, 0, 0])
+        );
+        assert_eq!(palette_distance(&palette, &palette), 0.0);
+    }
+
+    #[test]
+    fn histogram_of_flat_image() {
+        let img = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+        let histogram = color_histogram(&img);
+        assert_eq!(histogram.len(), 64);
+        assert_eq!(histogram[48], 255);
+        assert_eq!(histogram_distance(&histogram, &histogram), 0.0);
+    }
+}
@@ -1,21 +1,698 @@
-use crate::{load_image_files, set_wallpaper_on_config, Configuration};
-use anyhow::{anyhow, Context};
+use crate::{
+    accept_request, apply_or_forward, apply_wallpaper, bind_socket, daily_pick, find_duplicates,
+    get_configuration, get_i3_socket_path, get_screen_resolution, import_files, is_smaller_than,
+    list_regolith_backups, load_configuration, load_daily_state, load_history, load_image_files,
+    load_index, load_layered_configuration, load_regolith_config, load_regolith_look, load_tags,
+    look_brightness, menu_rows, move_to_trash, restore_regolith_backup, save_daily_state,
+    save_index, send_command, to_hex, watch_workspaces, ApplyOptions, ApplySource, Collection,
+    ColorRanking, Configuration, DailyState, Error, ImageEntry, ImportOptions, IpcCommand,
+    IpcResponder, MenuFormat, MenuSelection, PickOptions, Rgb, Severity, SortBy,
+    COLOR_PICK_CANDIDATES, DEFAULT_DAILY_SEED, DEFAULT_WORKSPACE_DEBOUNCE,
+};
+#[cfg(feature = "tui")]
+use crate::{GraphicsProtocol, Tui, TuiState};
+use anyhow::{anyhow, bail, Context};
 use rand::seq::SliceRandom;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
 
-pub fn pick_random_image(settings: Configuration) -> anyhow::Result<()> {
-    let path = settings
-        .wallpapers_path
-        .ok_or(anyhow!("No `wallpaper_path` on config."))?;
+/// Options for the `daily` command
+#[derive(Clone, Debug, Default)]
+pub struct DailyOptions {
+    pub pick: PickOptions,
+    /// Seed shared by the machines that should show the same image, the one
+    /// on the config file by default
+    pub seed: Option<String>,
+    /// Keep running, applying the image of the day when the date changes
+    pub watch: bool,
+    /// Apply the image of the day even if it was already applied today
+    pub force: bool,
+}
+
+/// Options for the `list` command
+#[derive(Clone, Debug, Default)]
+pub struct ListOptions {
+    pub collection: Option<String>,
+    pub sort_by: SortBy,
+    /// Sort by closeness to this color instead
+    pub color: Option<Rgb>,
+    /// Print the image metadata next to each path
+    pub long: bool,
+}
+
+/// Options for the `rofi` command
+#[derive(Clone, Debug, Default)]
+pub struct MenuOptions {
+    /// Label of the chosen row, none to list the rows
+    pub selection: Option<String>,
+    /// Collection listed when nothing was chosen yet
+    pub collection: Option<String>,
+    pub format: MenuFormat,
+}
+
+/// Options for the `dedupe` command
+#[derive(Clone, Debug)]
+pub struct DedupeOptions {
+    /// Only print the duplicates, without moving files to the trash
+    pub dry_run: bool,
+    /// Max different bits between perceptual hashes
+    pub max_distance: u32,
+}
+
+/// Sends the command to the running GUI or daemon, printing its answer.
+/// Returns whether an instance answered.
+async fn forward(command: IpcCommand) -> anyhow::Result<bool> {
+    match send_command(command).await? {
+        Some(response) if response.ok => {
+            if !response.message.is_empty() {
+                println!("{}", response.message);
+            }
+            Ok(true)
+        }
+        Some(response) => bail!(response.message),
+        None => Ok(false),
+    }
+}
+
+pub fn pick_random_image(settings: Configuration, options: PickOptions) -> anyhow::Result<()> {
+    let rt = Runtime::new().context("Failed to create runtime.")?;
+    rt.block_on(async {
+        if !forward(IpcCommand::Random(options.clone())).await? {
+            set_random_image(&settings, &options, ApplySource::Cli).await?;
+        }
+        Ok(())
+    })
+}
+
+/// Changes to the next wallpaper of the running instance, or to a random one
+pub fn next(settings: Configuration) -> anyhow::Result<()> {
+    let rt = Runtime::new().context("Failed to create runtime.")?;
+    rt.block_on(async {
+        if !forward(IpcCommand::Next).await? {
+            set_random_image(&settings, &PickOptions::default(), ApplySource::Cli).await?;
+        }
+        Ok(())
+    })
+}
+
+/// Sets the image as wallpaper, through the running instance if there is one
+pub fn set(settings: Configuration, path: PathBuf) -> anyhow::Result<()> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("File not found: {}", path.display()))?;
+    let rt = Runtime::new().context("Failed to create runtime.")?;
+    rt.block_on(async {
+        if !forward(IpcCommand::Set { path: path.clone() }).await? {
+            let options = apply_options(&settings, ApplySource::Cli, &path);
+            apply_wallpaper(path, options).await?;
+        }
+        Ok(())
+    })
+}
+
+/// Makes the running instance read the config file again
+pub fn reload() -> anyhow::Result<()> {
     let rt = Runtime::new().context("Failed to create runtime.")?;
     rt.block_on(async {
-        let mut rng = rand::thread_rng();
-        let image_path = load_image_files(path)
-            .await?
-            .choose(&mut rng)
-            .ok_or(anyhow!("No files loaded."))?
-            .clone();
-        set_wallpaper_on_config(image_path).await?;
+        if !forward(IpcCommand::Reload).await? {
+            bail!("No running instance.");
+        }
         Ok(())
     })
 }
+
+/// Brings the window of the running GUI to the front. Returns whether there
+/// is one.
+pub fn focus_running_instance() -> anyhow::Result<bool> {
+    let rt = Runtime::new().context("Failed to create runtime.")?;
+    rt.block_on(async {
+        let response = send_command(IpcCommand::Focus).await?;
+        Ok(response.is_some_and(|response| response.ok))
+    })
+}
+
+/// Keeps running, picking a random wallpaper every `interval` (unless it is
+/// zero) and answering the commands of other invocations. With workspace
+/// rules on the config, also applies the wallpaper of the focused i3 workspace.
+pub fn run_daemon(
+    settings: Configuration,
+    options: PickOptions,
+    interval: Duration,
+) -> anyhow::Result<()> {
+    let rt = Runtime::new().context("Failed to create runtime.")?;
+    rt.block_on(async {
+        let listener = match bind_socket().await {
+            Ok(listener) => Some(listener),
+            Err(Error::AlreadyRunning) => bail!(Error::AlreadyRunning),
+            Err(e) => {
+                tracing::warn!(error.message=%e, "IPC disabled.");
+                None
+            }
+        };
+        let mut workspaces = watch_workspace_rules(&settings, None).await;
+        // Only the last workspace focused during the debounce delay is applied
+        let mut focused_workspace = None;
+        let debounce = tokio::time::sleep(Duration::ZERO);
+        tokio::pin!(debounce);
+        let mut settings = settings;
+        let rotate = !interval.is_zero();
+        let mut interval = tokio::time::interval(interval.max(Duration::from_secs(1)));
+        loop {
+            tokio::select! {
+                workspace = async {
+                    match &mut workspaces {
+                        Some(workspaces) => workspaces.recv().await,
+                        None => futures::future::pending().await,
+                    }
+                } => {
+                    let Some(workspace) = workspace else {
+                        workspaces = None;
+                        continue;
+                    };
+                    focused_workspace = Some(workspace);
+                    let delay = settings.workspaces.debounce.unwrap_or(DEFAULT_WORKSPACE_DEBOUNCE);
+                    debounce
+                        .as_mut()
+                        .reset(tokio::time::Instant::now() + Duration::from_millis(delay));
+                }
+                _ = &mut debounce, if focused_workspace.is_some() => {
+                    let workspace = focused_workspace.take().unwrap_or_default();
+                    let Some(path) = settings.workspaces.wallpaper(&workspace) else {
+                        continue;
+                    };
+                    // Workspaces sharing a wallpaper don't refresh the look
+                    if load_regolith_config().await.is_ok_and(|current| current == path) {
+                        continue;
+                    }
+                    let options = apply_options(&settings, ApplySource::Daemon, &path);
+                    match apply_wallpaper(path, options).await {
+                        Ok(path) => tracing::info!(?path, workspace, "Wallpaper changed."),
+                        Err(e) => tracing::error!(error.message=%e, "Failed to change wallpaper."),
+                    }
+                }
+                _ = interval.tick(), if rotate => {
+                    match set_random_image(&settings, &options, ApplySource::Daemon).await {
+                        Ok(path) => tracing::info!(?path, "Wallpaper changed."),
+                        Err(e) => tracing::error!(error.message=%e, "Failed to change wallpaper."),
+                    }
+                }
+                request = async {
+                    match &listener {
+                        Some(listener) => accept_request(listener).await,
+                        None => futures::future::pending().await,
+                    }
+                } => {
+                    match request {
+                        Ok((command, responder)) => {
+                            handle_daemon_command(&mut settings, &options, &mut interval, command, responder).await;
+                            // A reload can add or remove the workspace rules
+                            workspaces = watch_workspace_rules(&settings, workspaces).await;
+                        }
+                        Err(e) => tracing::warn!(error.message=%e, "Failed to accept IPC request."),
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Watches the focused workspace while there are workspace rules, keeping the
+/// watch already running
+async fn watch_workspace_rules(
+    settings: &Configuration,
+    watching: Option<mpsc::Receiver<String>>,
+) -> Option<mpsc::Receiver<String>> {
+    if settings.workspaces.rules.is_empty() {
+        return None;
+    }
+    if watching.is_some() {
+        return watching;
+    }
+    match get_i3_socket_path().await {
+        Ok(path) => Some(watch_workspaces(path)),
+        Err(e) => {
+            tracing::warn!(error.message=%e, "Workspace wallpapers disabled.");
+            None
+        }
+    }
+}
+
+async fn handle_daemon_command(
+    settings: &mut Configuration,
+    options: &PickOptions,
+    interval: &mut tokio::time::Interval,
+    command: IpcCommand,
+    responder: IpcResponder,
+) {
+    let result = match command {
+        IpcCommand::Next => set_random_image(settings, options, ApplySource::Daemon).await,
+        IpcCommand::Random(options) => {
+            set_random_image(settings, &options, ApplySource::Daemon).await
+        }
+        IpcCommand::Set { path } => {
+            let options = apply_options(settings, ApplySource::Daemon, &path);
+            apply_wallpaper(path, options)
+                .await
+                .map_err(anyhow::Error::from)
+        }
+        IpcCommand::Reload => {
+            let result = get_configuration().map(|configuration| *settings = configuration);
+            responder
+                .respond(result.map(|_| "Config reloaded.".to_string()))
+                .await;
+            return;
+        }
+        IpcCommand::Focus => {
+            let e = Error::IpcFailed("the daemon has no window".into());
+            responder.respond(Err(e)).await;
+            return;
+        }
+    };
+    // The next automatic change is a full interval after this one
+    if result.is_ok() {
+        interval.reset();
+    }
+    let result = result
+        .map(|path| format!("Wallpaper changed: {}", path.display()))
+        .map_err(|e| Error::UnexpectedError(e.to_string()));
+    responder.respond(result).await;
+}
+
+/// Applies the wallpaper of the day, picked from the library (or collection)
+/// with a seeded choice. With `watch`, keeps checking the date every minute,
+/// so the image is also changed after the machine wakes up.
+pub fn daily(settings: Configuration, options: DailyOptions) -> anyhow::Result<()> {
+    let rt = Runtime::new().context("Failed to create runtime.")?;
+    rt.block_on(async {
+        if !options.watch {
+            set_daily_image(&settings, &options).await?;
+            return Ok(());
+        }
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            if let Err(e) = set_daily_image(&settings, &options).await {
+                tracing::error!(error.message=%e, "Failed to change wallpaper.");
+            }
+        }
+    })
+}
+
+/// Applies the image of today, unless it was already applied. Returns the
+/// applied image.
+async fn set_daily_image(
+    settings: &Configuration,
+    options: &DailyOptions,
+) -> anyhow::Result<Option<PathBuf>> {
+    let seed = options
+        .seed
+        .clone()
+        .or(settings.daily_seed.clone())
+        .unwrap_or(DEFAULT_DAILY_SEED.to_string());
+    let key = [
+        Some(seed.clone()),
+        options.pick.collection.clone(),
+        options
+            .pick
+            .brightness
+            .map(|brightness| brightness.to_string()),
+        options.pick.match_look.then(|| "match-look".to_string()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(" ");
+    let today = chrono::Local::now().date_naive();
+    let state = load_daily_state()?;
+    if let Some(state) = state.filter(|state| state.is_applied(today, &key) && !options.force) {
+        tracing::info!(path=?state.path, "Wallpaper of the day already applied.");
+        return Ok(None);
+    }
+    let candidates = pick_candidates(settings, &options.pick).await?;
+    let path = daily_pick(
+        candidates.iter().map(|entry| entry.path.as_path()),
+        settings.wallpapers_path(),
+        &seed,
+        today,
+    )
+    .ok_or(anyhow!("No images match the filters."))?;
+    let entry = candidates
+        .iter()
+        .find(|entry| entry.path == path)
+        .ok_or(anyhow!("No images match the filters."))?;
+    let source = if options.watch {
+        ApplySource::Daemon
+    } else {
+        ApplySource::Cli
+    };
+    let applied = set_entry(settings, entry, source).await?;
+    tracing::info!(path=?applied, date=%today, "Wallpaper of the day applied.");
+    save_daily_state(DailyState {
+        date: today.to_string(),
+        key,
+        path: entry.path.clone(),
+    })
+    .await?;
+    Ok(Some(applied))
+}
+
+pub async fn set_random_image(
+    settings: &Configuration,
+    options: &PickOptions,
+    source: ApplySource,
+) -> anyhow::Result<PathBuf> {
+    let candidates = pick_candidates(settings, options).await?;
+    let entry = candidates
+        .choose(&mut rand::thread_rng())
+        .ok_or(anyhow!("No images match the filters."))?;
+    set_entry(settings, entry, source).await
+}
+
+/// Images matching the filters, the closest ones to the color if set
+async fn pick_candidates(
+    settings: &Configuration,
+    options: &PickOptions,
+) -> anyhow::Result<Vec<ImageEntry>> {
+    let entries = indexed_images(settings, options.collection.clone()).await?;
+    let brightness = match options.brightness {
+        Some(brightness) => Some(brightness),
+        None if options.match_look => {
+            let look = load_regolith_look().await?;
+            let brightness = look_brightness(&look, &settings.look_brightness);
+            tracing::info!(look, %brightness, "Matching regolith look.");
+            Some(brightness)
+        }
+        None => None,
+    };
+    let mut candidates = entries
+        .iter()
+        .filter(|entry| brightness.is_none_or(|b| entry.brightness == b))
+        .collect::<Vec<_>>();
+    if let Some(color) = options.color {
+        ColorRanking::Color(color).sort(&mut candidates);
+        candidates.truncate(COLOR_PICK_CANDIDATES);
+    }
+    Ok(candidates.into_iter().cloned().collect())
+}
+
+/// Hooks of the config, with the colors of the image if it is indexed
+fn apply_options(settings: &Configuration, source: ApplySource, path: &Path) -> ApplyOptions {
+    let colors = load_index()
+        .ok()
+        .and_then(|index| index.entries.get(path).map(|entry| entry.colors.clone()))
+        .unwrap_or_default();
+    ApplyOptions {
+        source,
+        colors,
+        hooks: settings.hooks.clone(),
+    }
+}
+
+/// Sets the image as wallpaper, warning if it is smaller than the screen
+async fn set_entry(
+    settings: &Configuration,
+    entry: &ImageEntry,
+    source: ApplySource,
+) -> anyhow::Result<PathBuf> {
+    if let Ok(screen) = get_screen_resolution(settings.screen_resolution).await {
+        if is_smaller_than(entry.resolution(), screen) {
+            tracing::warn!(
+                path=?entry.path,
+                "Image resolution ({}) is smaller than the screen ({}).",
+                entry.resolution(),
+                screen
+            );
+        }
+    }
+    let options = ApplyOptions {
+        source,
+        colors: entry.colors.clone(),
+        hooks: settings.hooks.clone(),
+    };
+    Ok(apply_wallpaper(entry.path.clone(), options).await?)
+}
+
+/// Prints the images on the library, read from the index
+pub fn list_images(settings: Configuration, options: ListOptions) -> anyhow::Result<()> {
+    let rt = Runtime::new().context("Failed to create runtime.")?;
+    rt.block_on(async {
+        let entries = indexed_images(&settings, options.collection.clone()).await?;
+        let mut entries = entries.iter().collect::<Vec<_>>();
+        match options.color {
+            Some(color) => ColorRanking::Color(color).sort(&mut entries),
+            None => options.sort_by.sort(&mut entries),
+        }
+        for entry in entries {
+            if options.long {
+                let colors = entry.colors.iter().map(to_hex).collect::<Vec<_>>();
+                println!(
+                    "{}\t{}x{}\t{}\t{}\t{}\t{}",
+                    entry.path.display(),
+                    entry.width,
+                    entry.height,
+                    entry.format,
+                    entry.size,
+                    entry.brightness,
+                    colors.join(",")
+                );
+            } else {
+                println!("{}", entry.path.display());
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Menu for rofi (script mode) or dmenu: prints the rows, or applies the
+/// chosen image printing nothing (so rofi closes). Choosing a collection
+/// prints its rows.
+pub fn menu(settings: Configuration, options: MenuOptions) -> anyhow::Result<()> {
+    let root = settings
+        .wallpapers_path()
+        .map(Path::to_path_buf)
+        .ok_or(anyhow!("No `wallpapers_paths` on config."))?;
+    let selection = options
+        .selection
+        .as_deref()
+        .filter(|selection| !selection.is_empty())
+        .map(|selection| MenuSelection::parse(selection, &root));
+    let rt = Runtime::new().context("Failed to create runtime.")?;
+    rt.block_on(async {
+        let collection = match selection {
+            Some(MenuSelection::Image(path)) => {
+                let options = apply_options(&settings, ApplySource::Cli, &path);
+                apply_or_forward(path, options).await?;
+                return Ok(());
+            }
+            Some(MenuSelection::Collection(name)) => Some(name),
+            Some(MenuSelection::Back) => None,
+            None => options.collection,
+        };
+        let entries = indexed_images(&settings, collection.clone()).await?;
+        let tags = load_tags()?;
+        let collection = Collection::from(collection);
+        if options.format == MenuFormat::Rofi {
+            println!("\0prompt\x1f{}", collection);
+            println!("\0no-custom\x1ftrue");
+        }
+        for row in menu_rows(&entries, &root, &tags, &collection) {
+            println!("{}", row.format(options.format));
+        }
+        Ok(())
+    })
+}
+
+/// Browses the library on the terminal, previewing the images with the given
+/// graphics protocol (guessed from the terminal if not set)
+#[cfg(feature = "tui")]
+pub fn tui(settings: Configuration, graphics: Option<GraphicsProtocol>) -> anyhow::Result<()> {
+    let root = settings
+        .wallpapers_path()
+        .map(Path::to_path_buf)
+        .ok_or(anyhow!("No `wallpapers_paths` on config."))?;
+    let rt = Runtime::new().context("Failed to create runtime.")?;
+    let entries = rt.block_on(indexed_images(&settings, None))?;
+    let state = TuiState::new(root, entries, load_tags()?);
+    let graphics = graphics.unwrap_or_else(GraphicsProtocol::detect);
+    Tui::new(state, graphics, settings.hooks.clone())
+        .run(&rt)
+        .context("Terminal UI failed.")
+}
+
+/// Prints the configuration merged from every layer, as YAML or with the
+/// origin of each value
+pub fn show_config(origin: bool) -> anyhow::Result<()> {
+    let (config, diagnostics) = load_layered_configuration()?;
+    // The invalid layers are left out
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    if !origin {
+        print!("{}", serde_yaml::to_string(&config.value)?);
+        return Ok(());
+    }
+    for (key, value) in config.entries() {
+        let value = serde_json::to_string(&value)?;
+        println!("{} = {}  # {}", key, value, config.origin(&key));
+    }
+    Ok(())
+}
+
+/// Prints the problems found on the configuration, failing if any is an error
+pub fn check_config() -> anyhow::Result<()> {
+    let (_, diagnostics) = load_configuration()?;
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(anyhow!("The configuration has errors ({}).", errors));
+    }
+    if diagnostics.is_empty() {
+        println!("Configuration is valid.");
+    }
+    Ok(())
+}
+
+/// Restores a backup of the Regolith config file, or lists them
+pub fn restore_backup(date: Option<String>, list: bool) -> anyhow::Result<()> {
+    if list {
+        for backup in list_regolith_backups()? {
+            println!("{}", backup.display());
+        }
+        return Ok(());
+    }
+    let rt = Runtime::new().context("Failed to create runtime.")?;
+    rt.block_on(async {
+        let backup = restore_regolith_backup(date).await?;
+        println!("Restored {}", backup.display());
+        Ok(())
+    })
+}
+
+/// Prints the wallpapers applied, the latest `limit` ones if set
+pub fn history(limit: Option<usize>) -> anyhow::Result<()> {
+    let history = load_history()?;
+    let skip = limit.map_or(0, |limit| history.entries.len().saturating_sub(limit));
+    for entry in history.entries.iter().skip(skip) {
+        println!("{}\t{}", entry.applied_at, entry.path.display());
+    }
+    Ok(())
+}
+
+/// Finds near-duplicate images, keeping the highest resolution one of each
+/// group and moving the others to the trash
+pub fn dedupe(settings: Configuration, options: DedupeOptions) -> anyhow::Result<()> {
+    let rt = Runtime::new().context("Failed to create runtime.")?;
+    rt.block_on(async {
+        let entries = indexed_images(&settings, None).await?;
+        let groups = find_duplicates(&entries, options.max_distance);
+        let mut trashed = Vec::new();
+        for group in &groups {
+            let keep = group.keep();
+            println!(
+                "keep  {} ({}x{})",
+                keep.path.display(),
+                keep.width,
+                keep.height
+            );
+            for entry in group.others() {
+                let action = if options.dry_run {
+                    "would trash"
+                } else {
+                    "trash"
+                };
+                println!(
+                    "  {} {} ({}x{})",
+                    action,
+                    entry.path.display(),
+                    entry.width,
+                    entry.height
+                );
+                if !options.dry_run {
+                    move_to_trash(&entry.path).await?;
+                    trashed.push(entry.path.clone());
+                }
+            }
+        }
+        println!("{} groups of duplicates found.", groups.len());
+        if !trashed.is_empty() {
+            let mut index = load_index()?;
+            index.entries.retain(|path, _| !trashed.contains(path));
+            save_index(index).await?;
+        }
+        Ok(())
+    })
+}
+
+/// Imports the images into `folder` (the library root by default), printing
+/// the new path of each one
+pub fn import(
+    settings: Configuration,
+    paths: Vec<PathBuf>,
+    folder: Option<PathBuf>,
+    options: ImportOptions,
+) -> anyhow::Result<()> {
+    let folder = folder
+        .or(settings.wallpapers_path().map(Path::to_path_buf))
+        .ok_or(anyhow!("No `wallpapers_paths` on config, use `--to`."))?;
+    let rt = Runtime::new().context("Failed to create runtime.")?;
+    rt.block_on(async {
+        let results = import_files(paths, folder, options).await;
+        let mut failed = 0;
+        for (path, result) in results {
+            match result {
+                Ok(to) => println!("{} -> {}", path.display(), to.display()),
+                Err(Error::DuplicateImage(existing)) => {
+                    println!("{} skipped, same as {}", path.display(), existing.display())
+                }
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            return Err(anyhow!("{} files failed to import.", failed));
+        }
+        Ok(())
+    })
+}
+
+/// Index entries of the images on the library, updating the index for the
+/// files that changed since the last run
+async fn indexed_images(
+    settings: &Configuration,
+    collection: Option<String>,
+) -> anyhow::Result<Vec<ImageEntry>> {
+    let root = settings
+        .wallpapers_path()
+        .map(Path::to_path_buf)
+        .ok_or(anyhow!("No `wallpapers_paths` on config."))?;
+    let mut paths = load_image_files(root.clone()).await?;
+    paths.retain(|path| !settings.ignored_files.contains(path));
+    let mut index = load_index()?;
+    let (updated, errors) = index.refresh(&paths).await;
+    for (path, e) in errors {
+        tracing::warn!(?path, error.message=%e, "Failed to index image.");
+    }
+    let changed = updated + index.prune(&root, &paths) > 0;
+    let tags = load_tags()?;
+    let collection = Collection::from(collection);
+    let entries = paths
+        .iter()
+        .filter(|path| tags.matches(path, Some(&root), &collection))
+        .filter_map(|path| index.entries.get(path).cloned())
+        .collect();
+    if changed {
+        save_index(index).await?;
+    }
+    Ok(entries)
+}
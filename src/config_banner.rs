@@ -0,0 +1,69 @@
+use crate::{has_config_errors, ConfigDiagnostic, Severity};
+use iced::widget::{button, column, container, horizontal_space, row, text, Column};
+use iced::{theme, Alignment, Color, Element, Length};
+
+#[derive(Debug, Clone)]
+pub enum ConfigBannerMessage {
+    Dismiss,
+}
+
+/// Problems found on the config, shown above the gallery until dismissed
+#[derive(Debug, Default)]
+pub struct ConfigBanner {
+    diagnostics: Vec<ConfigDiagnostic>,
+}
+
+impl ConfigBanner {
+    pub fn new(diagnostics: Vec<ConfigDiagnostic>) -> Self {
+        Self { diagnostics }
+    }
+
+    pub fn update(&mut self, message: ConfigBannerMessage) {
+        match message {
+            ConfigBannerMessage::Dismiss => self.diagnostics.clear(),
+        }
+    }
+
+    /// Nothing if there are no problems
+    pub fn view(&self) -> Option<Element<'_, ConfigBannerMessage>> {
+        if self.diagnostics.is_empty() {
+            return None;
+        }
+        let title = if has_config_errors(&self.diagnostics) {
+            "Invalid configuration, the wrong settings are ignored \
+             (run `regolith-wallpaper config check` after fixing them)"
+        } else {
+            "The configuration has warnings"
+        };
+        let dismiss_btn = button(text("Dismiss").size(12))
+            .padding([1, 6])
+            .style(theme::Button::Secondary)
+            .on_press(ConfigBannerMessage::Dismiss);
+        let header = row!(
+            text(title).size(14),
+            horizontal_space(Length::Fill),
+            dismiss_btn
+        )
+        .spacing(10)
+        .align_items(Alignment::Center);
+        let rows = self.diagnostics.iter().map(|diagnostic| {
+            let color = match diagnostic.severity {
+                Severity::Error => Color::from_rgb(0.9, 0.2, 0.2),
+                Severity::Warning => Color::from_rgb(0.9, 0.7, 0.2),
+            };
+            text(diagnostic).size(12).style(color).into()
+        });
+        let content = column!(header, Column::with_children(rows.collect()).spacing(2)).spacing(6);
+        Some(
+            container(content)
+                .width(Length::Fill)
+                .padding(8)
+                .style(|_: &_| container::Appearance {
+                    border_width: 1.0,
+                    border_color: Color::from_rgb(0.9, 0.2, 0.2),
+                    ..Default::default()
+                })
+                .into(),
+        )
+    }
+}
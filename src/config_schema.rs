@@ -0,0 +1,460 @@
+use crate::{expand_home_dir, ConfigOrigin, Configuration, LayeredConfiguration};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Version of the config file format, files without `version` are version 1
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Keys dropped from the format, with the reason shown when found
+const REMOVED_KEYS: &[(&str, &str)] = &[("max_images", "the gallery only loads the images shown")];
+
+/// Keys renamed by the migrations, to find the line of a value on old files
+const RENAMED_KEYS: &[(&str, &str)] = &[("wallpapers_paths", "wallpapers_path")];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Problem found on the config, e.g.:
+/// `~/.config/regolith-wallpaper/config.yaml:3: error: slideshow_interval: must be greater than 0`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigDiagnostic {
+    pub severity: Severity,
+    pub origin: ConfigOrigin,
+    /// Line on the file of the origin, starting at 1
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.origin {
+            ConfigOrigin::System(path) | ConfigOrigin::User(path) => {
+                write!(f, "{}", path.display())?;
+                if let Some(line) = self.line {
+                    write!(f, ":{}", line)?;
+                }
+            }
+            origin => write!(f, "{}", origin)?,
+        }
+        write!(f, ": {}: {}", self.severity, self.message)
+    }
+}
+
+impl ConfigDiagnostic {
+    pub fn error(origin: ConfigOrigin, line: Option<usize>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            origin,
+            line,
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(origin: ConfigOrigin, line: Option<usize>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(origin, line, message)
+        }
+    }
+}
+
+/// Whether any of the problems is an error
+pub fn has_config_errors(diagnostics: &[ConfigDiagnostic]) -> bool {
+    diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+}
+
+/// Line (starting at 1) of the dotted key on a YAML file, following the
+/// indentation of block mappings (only the direct children of each parent
+/// match, not the keys with the same name nested deeper)
+pub fn key_line(content: &str, key: &str) -> Option<usize> {
+    let names = key.split('.').collect::<Vec<_>>();
+    let mut depth = 0;
+    let mut parent_indent = None;
+    let mut child_indent = None;
+    for (i, line) in content.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+        match parent_indent {
+            // Left the mapping of the parent key
+            Some(parent) if indent <= parent => return None,
+            None if indent > 0 => continue,
+            Some(_) if *child_indent.get_or_insert(indent) != indent => continue,
+            _ => {}
+        }
+        let is_key = trimmed
+            .strip_prefix(names[depth])
+            .is_some_and(|rest| rest.starts_with(':'));
+        if is_key {
+            if depth + 1 == names.len() {
+                return Some(i + 1);
+            }
+            depth += 1;
+            parent_indent = Some(indent);
+            child_indent = None;
+        }
+    }
+    None
+}
+
+/// Line of the key on the file, under its current or its old name
+fn file_key_line(content: &str, key: &str) -> Option<usize> {
+    key_line(content, key).or_else(|| {
+        RENAMED_KEYS
+            .iter()
+            .find(|(new, _)| key == *new || key.starts_with(&format!("{}.", new)))
+            .and_then(|(new, old)| key_line(content, &key.replacen(new, old, 1)))
+    })
+}
+
+/// Version 1 had a single `wallpapers_path` and the `extra_wallpapers_paths`
+fn migrate_v1(mapping: &mut Mapping, notes: &mut Vec<(String, String)>) {
+    let root = mapping.remove("wallpapers_path").unwrap_or_default();
+    let extra = mapping.remove("extra_wallpapers_paths").unwrap_or_default();
+    let extra = extra.as_sequence().cloned().unwrap_or_default();
+    match root {
+        Value::Null if !extra.is_empty() => notes.push((
+            "extra_wallpapers_paths".into(),
+            "ignored without `wallpapers_path`".into(),
+        )),
+        Value::Null => {}
+        root => {
+            let paths = std::iter::once(root).chain(extra).collect();
+            mapping.insert("wallpapers_paths".into(), Value::Sequence(paths));
+        }
+    }
+}
+
+/// Upgrades a config file to [`CONFIG_VERSION`]. Returns the keys dropped on
+/// the way with the reason, or why it can't be read.
+pub fn migrate_config(layer: &mut Value) -> Result<Vec<(String, String)>, String> {
+    let Value::Mapping(mapping) = layer else {
+        return match layer {
+            Value::Null => Ok(Vec::new()),
+            _ => Err("expected a mapping of settings".into()),
+        };
+    };
+    let version = match mapping.get("version") {
+        None | Some(Value::Null) => 1,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| {
+                let value = serde_yaml::to_string(version).unwrap_or_default();
+                format!("version: expected a version number, not `{}`", value.trim())
+            })?,
+    };
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "written for config version {}, this version reads up to {}",
+            version, CONFIG_VERSION
+        ));
+    }
+    let mut notes = Vec::new();
+    if version < 2 {
+        migrate_v1(mapping, &mut notes);
+    }
+    for (key, reason) in REMOVED_KEYS {
+        if mapping.remove(*key).is_some() {
+            notes.push((key.to_string(), format!("no longer used, {}", reason)));
+        }
+    }
+    mapping.insert("version".into(), CONFIG_VERSION.into());
+    Ok(notes)
+}
+
+/// Checks a config file: migrates it, warns about unknown keys and makes sure
+/// the values have the right types. Returns the layer, if usable.
+pub fn check_config_file(
+    origin: &ConfigOrigin,
+    content: &str,
+    diagnostics: &mut Vec<ConfigDiagnostic>,
+) -> Option<Value> {
+    let mut layer: Value = match serde_yaml::from_str(content) {
+        Ok(layer) => layer,
+        Err(e) => {
+            let line = e.location().map(|location| location.line());
+            diagnostics.push(ConfigDiagnostic::error(origin.clone(), line, e.to_string()));
+            return None;
+        }
+    };
+    // Was never a valid value, even if the key is no longer used
+    let no_images = layer.get("max_images").and_then(Value::as_u64) == Some(0);
+    match migrate_config(&mut layer) {
+        Ok(notes) => diagnostics.extend(notes.into_iter().map(|(key, note)| {
+            let line = file_key_line(content, &key);
+            if key == "max_images" && no_images {
+                let message = format!("{}: must be greater than 0 ({})", key, note);
+                return ConfigDiagnostic::error(origin.clone(), line, message);
+            }
+            ConfigDiagnostic::warning(origin.clone(), line, format!("{}: {}", key, note))
+        })),
+        Err(e) => {
+            let line = key_line(content, "version");
+            diagnostics.push(ConfigDiagnostic::error(origin.clone(), line, e));
+            return None;
+        }
+    }
+    // Empty file, e.g. only comments
+    if layer.is_null() {
+        return Some(layer);
+    }
+    let known = LayeredConfiguration::default().value;
+    for key in layer.as_mapping().into_iter().flat_map(Mapping::keys) {
+        let key = key.as_str().unwrap_or_default();
+        if known.get(key).is_none() {
+            let line = key_line(content, key);
+            let message = format!("unknown setting `{}`", key);
+            diagnostics.push(ConfigDiagnostic::warning(origin.clone(), line, message));
+        }
+    }
+    if let Err(e) = serde_yaml::from_value::<Configuration>(layer.clone()) {
+        // The key and location are only known when parsing the text (which
+        // fails the same unless the wrong value was migrated)
+        let (line, message) = match serde_yaml::from_str::<Configuration>(content) {
+            Err(text_error) => (
+                text_error.location().map(|location| location.line()),
+                text_error.to_string(),
+            ),
+            Ok(_) => (None, e.to_string()),
+        };
+        diagnostics.push(ConfigDiagnostic::error(origin.clone(), line, message));
+        return None;
+    }
+    Some(layer)
+}
+
+/// Checks the values of the merged config, pointing at the layer (and line)
+/// that set each wrong one
+pub fn validate_configuration(
+    config: &LayeredConfiguration,
+    settings: &Configuration,
+) -> Vec<ConfigDiagnostic> {
+    let mut problems = Vec::new();
+    for (i, path) in settings.wallpapers_paths.iter().enumerate() {
+        if !path.is_dir() {
+            let severity = if i == 0 {
+                Severity::Error
+            } else {
+                Severity::Warning
+            };
+            let message = format!("`{}` is not a folder", path.display());
+            problems.push((severity, "wallpapers_paths".to_string(), message));
+        }
+    }
+    if settings.slideshow_interval == Some(0) {
+        let message = "must be greater than 0".to_string();
+        problems.push((Severity::Error, "slideshow_interval".into(), message));
+    }
+    for (key, hooks) in [
+        ("hooks.pre_apply", &settings.hooks.pre_apply),
+        ("hooks.post_apply", &settings.hooks.post_apply),
+    ] {
+        for hook in hooks {
+            if hook.command.trim().is_empty() {
+                problems.push((Severity::Error, key.into(), "empty command".into()));
+            }
+            if hook.timeout == Some(0) {
+                let message = format!("`{}`: timeout must be greater than 0", hook.command);
+                problems.push((Severity::Error, key.into(), message));
+            }
+        }
+    }
+    for provider in &settings.providers {
+        let Some(interval) = provider.min_interval else {
+            continue;
+        };
+        if Duration::try_from_secs_f64(interval).is_err() {
+            let message = format!(
+                "`{}`: min_interval must be a number of seconds, not {}",
+                provider.name, interval
+            );
+            problems.push((Severity::Error, "providers".into(), message));
+        }
+    }
+    for rule in &settings.workspaces.rules {
+        if !expand_home_dir(rule.wallpaper.to_string_lossy()).is_file() {
+            let message = format!("`{}` is not a file", rule.wallpaper.display());
+            problems.push((Severity::Error, "workspaces.rules".into(), message));
+        }
+    }
+
+    let mut contents = HashMap::<PathBuf, Option<String>>::new();
+    problems
+        .into_iter()
+        .map(|(severity, key, message)| {
+            let origin = config.origin(&key);
+            let line = match &origin {
+                ConfigOrigin::System(path) | ConfigOrigin::User(path) => contents
+                    .entry(path.clone())
+                    .or_insert_with(|| std::fs::read_to_string(path).ok())
+                    .as_deref()
+                    .and_then(|content| file_key_line(content, &key)),
+                _ => None,
+            };
+            ConfigDiagnostic {
+                severity,
+                origin,
+                line,
+                message: format!("{}: {}", key, message),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1: &str = "\
+wallpapers_path: /nowhere/wallpapers
+extra_wallpapers_paths:
+  - /nowhere/other
+max_images: 0
+slideshow_interval: 0
+hooks:
+  pre_apply:
+    - command: ''
+      timeout: 0
+colour: red
+providers:
+  - name: pictures
+    kind: url_list
+    url: http://localhost/list.txt
+    min_interval: -1
+";
+
+    fn origin() -> ConfigOrigin {
+        ConfigOrigin::User("/home/me/config.yaml".into())
+    }
+
+    #[test]
+    fn old_configs_are_migrated() {
+        let mut diagnostics = Vec::new();
+        let layer = check_config_file(&origin(), V1, &mut diagnostics).unwrap();
+        assert_eq!(layer["version"].as_u64(), Some(CONFIG_VERSION as u64));
+        let lines = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.severity, diagnostic.line))
+            .collect::<Vec<_>>();
+        // `max_images` (removed, but 0 was never valid) and `colour`
+        assert_eq!(
+            lines,
+            vec![(Severity::Error, Some(4)), (Severity::Warning, Some(10))]
+        );
+        let mut config = LayeredConfiguration::default();
+        config.push(origin(), layer);
+        let settings = config.configuration().unwrap();
+        assert_eq!(
+            settings.wallpapers_paths,
+            vec![
+                PathBuf::from("/nowhere/wallpapers"),
+                "/nowhere/other".into()
+            ]
+        );
+    }
+
+    #[test]
+    fn removed_keys_are_warnings() {
+        let mut diagnostics = Vec::new();
+        check_config_file(&origin(), "max_images: 50\n", &mut diagnostics).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn wrong_values_are_errors() {
+        let layer = check_config_file(&origin(), V1, &mut Vec::new()).unwrap();
+        let mut config = LayeredConfiguration::default();
+        config.push(origin(), layer);
+        let settings = config.configuration().unwrap();
+        let problems = validate_configuration(&config, &settings);
+        let errors = problems
+            .iter()
+            .filter(|problem| problem.severity == Severity::Error)
+            .map(|problem| problem.message.split(':').next().unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                "wallpapers_paths",
+                "slideshow_interval",
+                "hooks.pre_apply",
+                "hooks.pre_apply",
+                "providers"
+            ]
+        );
+        // The file can't be read on the test, so there is no line
+        assert_eq!(problems[0].origin, origin());
+        assert_eq!(problems[0].line, None);
+    }
+
+    #[test]
+    fn key_lines_follow_the_indentation() {
+        assert_eq!(key_line(V1, "wallpapers_path"), Some(1));
+        assert_eq!(key_line(V1, "hooks.pre_apply"), Some(7));
+        assert_eq!(key_line(V1, "pre_apply"), None);
+        assert_eq!(file_key_line(V1, "wallpapers_paths"), Some(1));
+        let nested = "\
+workspaces:
+  debounce:
+    rules: 1
+  rules:
+    - workspace: 1
+";
+        assert_eq!(key_line(nested, "workspaces.rules"), Some(4));
+        assert_eq!(key_line(nested, "workspaces.debounce.rules"), Some(3));
+        assert_eq!(key_line(nested, "workspaces.workspace"), None);
+    }
+
+    #[test]
+    fn unreadable_files_are_errors() {
+        let mut diagnostics = Vec::new();
+        assert!(check_config_file(&origin(), "version: 9\n", &mut diagnostics).is_none());
+        assert!(
+            check_config_file(&origin(), "slideshow_interval: soon\n", &mut diagnostics).is_none()
+        );
+        assert!(check_config_file(&origin(), "hooks: [\n", &mut diagnostics).is_none());
+        let lines = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![Some(1), Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn out_of_range_versions_are_errors() {
+        for content in ["version: 4294967298\n", "# old\nversion: -1\n"] {
+            let mut diagnostics = Vec::new();
+            assert!(check_config_file(&origin(), content, &mut diagnostics).is_none());
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0].severity, Severity::Error);
+            assert_eq!(diagnostics[0].line, Some(content.lines().count()));
+        }
+        let mut diagnostics = Vec::new();
+        check_config_file(&origin(), "version: -1\n", &mut diagnostics);
+        assert_eq!(
+            diagnostics[0].message,
+            "version: expected a version number, not `-1`"
+        );
+    }
+}
@@ -1,54 +1,522 @@
 use super::Result;
-use crate::Error;
+use crate::{
+    check_config_file, has_config_errors, migrate_config, validate_configuration, write_atomic,
+    Brightness, ConfigDiagnostic, Error, Hooks, ProviderConfig, Resolution, WorkspaceWallpapers,
+    CONFIG_VERSION,
+};
 use directories::BaseDirs;
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
 use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
     fs::{create_dir_all, read_to_string, write},
     path::{Path, PathBuf},
+    sync::OnceLock,
 };
 
+/// Seconds between wallpaper changes of the daemon and the GUI slideshow
+pub const DEFAULT_INTERVAL: u64 = 1800;
+
+/// Config shared by the users of the machine, overridden by their own
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/xdg/regolith-wallpaper/config.yaml";
+
+/// Prefix of the environment variables overriding the config, e.g.:
+/// `REGOLITH_WALLPAPER_SLIDESHOW_INTERVAL`. `__` separates nested keys.
+pub const CONFIG_ENV_PREFIX: &str = "REGOLITH_WALLPAPER_";
+
+/// Written when there is no user config yet, so the system one applies
+const USER_CONFIG_TEMPLATE: &str = "\
+# Settings of regolith-wallpaper (see the README), overriding the ones of
+# /etc/xdg/regolith-wallpaper/config.yaml
+version: 2
+";
+
+/// `key=value` pairs given on the command line, see [`set_config_overrides`]
+static CONFIG_OVERRIDES: OnceLock<Vec<(String, String)>> = OnceLock::new();
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Configuration {
-    pub wallpapers_path: Option<PathBuf>,
-    pub max_images: Option<usize>,
+    /// Format of the file, see [`CONFIG_VERSION`]
+    #[serde(default)]
+    pub version: u32,
+    /// Wallpaper folders: the first one is the library, the others are
+    /// offered as destinations when moving images
+    #[serde(default)]
+    pub wallpapers_paths: Vec<PathBuf>,
+    /// Brightness of the Regolith looks, for the ones that can't be guessed
+    /// from their names
+    #[serde(default)]
+    pub look_brightness: BTreeMap<String, Brightness>,
+    /// Files skipped when loading the library (ignored from the errors panel)
+    #[serde(default)]
+    pub ignored_files: BTreeSet<PathBuf>,
+    /// Width of the gallery thumbnails, changed with the zoom slider
+    #[serde(default)]
+    pub thumbnail_size: Option<u32>,
+    /// Screen resolution (e.g.: `2560x1440`), detected with `xrandr` if not set
+    #[serde(default)]
+    pub screen_resolution: Option<Resolution>,
+    /// Seconds between wallpaper changes of the GUI slideshow
+    #[serde(default)]
+    pub slideshow_interval: Option<u64>,
+    /// Remote sources of wallpapers, shown on the "Browse online" tab
+    #[serde(default)]
+    pub providers: Vec<ProviderConfig>,
+    /// Seed of the `daily` pick, machines sharing the library and the seed
+    /// show the same image of the day
+    #[serde(default)]
+    pub daily_seed: Option<String>,
+    /// Shell commands run before and after a wallpaper is applied
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// Wallpapers of the i3 workspaces, applied by the daemon
+    #[serde(default)]
+    pub workspaces: WorkspaceWallpapers,
 }
 
 impl Configuration {
-    fn from_file(path: impl AsRef<Path>) -> Result<Self> {
-        let content = read_to_string(path).map_err(|e| Error::UnexpectedError(e.to_string()))?;
-        serde_yaml::from_str(&content).map_err(|e| Error::UnexpectedError(e.to_string()))
+    /// Folder of the library, the first of `wallpapers_paths`
+    pub fn wallpapers_path(&self) -> Option<&Path> {
+        self.wallpapers_paths.first().map(PathBuf::as_path)
+    }
+
+    /// Other wallpaper folders, offered as destinations when moving images
+    pub fn extra_wallpapers_paths(&self) -> &[PathBuf] {
+        self.wallpapers_paths.get(1..).unwrap_or_default()
+    }
+
+    /// Changes the folder of the library, keeping the other ones
+    pub fn set_wallpapers_path(&mut self, path: PathBuf) {
+        match self.wallpapers_paths.first_mut() {
+            Some(root) => *root = path,
+            None => self.wallpapers_paths.push(path),
+        }
     }
 }
 
-pub fn get_configuration_path() -> Result<PathBuf> {
+/// Where a config value comes from
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ConfigOrigin {
+    #[default]
+    Default,
+    System(PathBuf),
+    User(PathBuf),
+    /// Name of the environment variable
+    Env(String),
+    Cli,
+}
+
+impl fmt::Display for ConfigOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigOrigin::Default => write!(f, "default"),
+            ConfigOrigin::System(path) => write!(f, "system ({})", path.display()),
+            ConfigOrigin::User(path) => write!(f, "user ({})", path.display()),
+            ConfigOrigin::Env(var) => write!(f, "env ({})", var),
+            ConfigOrigin::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+/// Config merged from the layers: built-in defaults, system file, user file,
+/// environment variables and command line. Mappings are merged key by key,
+/// other values (lists included) replace the lower ones, and `null` leaves
+/// them unchanged.
+#[derive(Clone, Debug)]
+pub struct LayeredConfiguration {
+    pub value: Value,
+    /// Layer that set each key (dotted path), the unlisted ones are defaults
+    origins: BTreeMap<String, ConfigOrigin>,
+}
+
+impl Default for LayeredConfiguration {
+    fn default() -> Self {
+        let configuration = Configuration {
+            version: CONFIG_VERSION,
+            ..Default::default()
+        };
+        Self {
+            value: serde_yaml::to_value(configuration).unwrap_or_default(),
+            origins: BTreeMap::new(),
+        }
+    }
+}
+
+impl LayeredConfiguration {
+    pub fn push(&mut self, origin: ConfigOrigin, layer: Value) {
+        merge_layer(&mut self.value, layer, "", &origin, &mut self.origins);
+    }
+
+    pub fn configuration(&self) -> Result<Configuration> {
+        serde_yaml::from_value(self.value.clone()).map_err(|e| {
+            tracing::error!(error.cause_chain=?e, error.message=%e, "Invalid configuration.");
+            Error::UnexpectedError(e.to_string())
+        })
+    }
+
+    /// Layer of the value at the dotted path, or of the closest parent set
+    pub fn origin(&self, key: &str) -> ConfigOrigin {
+        let mut key = key;
+        loop {
+            if let Some(origin) = self.origins.get(key) {
+                return origin.clone();
+            }
+            match key.rsplit_once('.') {
+                Some((parent, _)) => key = parent,
+                None => return ConfigOrigin::Default,
+            }
+        }
+    }
+
+    /// Every value that isn't a mapping, with its dotted path
+    pub fn entries(&self) -> Vec<(String, Value)> {
+        let mut entries = Vec::new();
+        flatten_value(&self.value, String::new(), &mut entries);
+        entries
+    }
+
+    /// Whether the top level key is a setting
+    fn is_known(&self, key: &str) -> bool {
+        self.value.get(key).is_some()
+    }
+}
+
+fn join_key(parent: &str, key: &str) -> String {
+    if parent.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", parent, key)
+    }
+}
+
+fn merge_layer(
+    base: &mut Value,
+    layer: Value,
+    key: &str,
+    origin: &ConfigOrigin,
+    origins: &mut BTreeMap<String, ConfigOrigin>,
+) {
+    match (base, layer) {
+        (_, Value::Null) => {}
+        (Value::Mapping(base), Value::Mapping(layer)) => {
+            for (name, value) in layer {
+                let Some(child) = name.as_str().map(|name| join_key(key, name)) else {
+                    continue;
+                };
+                let entry = base.entry(name).or_insert(Value::Null);
+                merge_layer(entry, value, &child, origin, origins);
+            }
+        }
+        (base, layer) => {
+            *base = layer;
+            let prefix = format!("{}.", key);
+            origins.retain(|child, _| !child.starts_with(&prefix));
+            origins.insert(key.to_string(), origin.clone());
+        }
+    }
+}
+
+fn flatten_value(value: &Value, key: String, entries: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Mapping(mapping) if !mapping.is_empty() => {
+            for (name, value) in mapping {
+                if let Some(name) = name.as_str() {
+                    flatten_value(value, join_key(&key, name), entries);
+                }
+            }
+        }
+        value => entries.push((key, value.clone())),
+    }
+}
+
+/// Layer setting only the value at the dotted path, parsed as YAML (or kept
+/// as a string if it isn't valid)
+pub fn override_layer(key: &str, value: &str) -> Value {
+    let value = serde_yaml::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+    key.rsplit('.').fold(value, |value, name| {
+        let mut mapping = Mapping::new();
+        mapping.insert(Value::String(name.to_string()), value);
+        Value::Mapping(mapping)
+    })
+}
+
+/// Dotted path set by an environment variable, e.g.:
+/// `REGOLITH_WALLPAPER_WORKSPACES__DEBOUNCE` sets `workspaces.debounce`
+pub fn env_config_key(var: &str) -> Option<String> {
+    let key = var.strip_prefix(CONFIG_ENV_PREFIX)?;
+    Some(key.to_lowercase().replace("__", "."))
+}
+
+/// Parses a `key=value` command line override
+pub fn parse_config_override(s: &str) -> std::result::Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("Invalid setting `{}`, expected `key=value`.", s)),
+    }
+}
+
+/// Sets the command line overrides, applied over every other layer each time
+/// the config is loaded (reloads included)
+pub fn set_config_overrides(overrides: Vec<(String, String)>) {
+    let _ = CONFIG_OVERRIDES.set(overrides);
+}
+
+fn read_file_layer(path: &Path) -> Result<Value> {
+    let content = read_to_string(path).map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to read file.");
+        Error::FailedToRead(path.into())
+    })?;
+    let mut layer = serde_yaml::from_str(&content)
+        .map_err(|e| Error::InvalidConfig(format!("{}: {}", path.display(), e)))?;
+    migrate_config(&mut layer)
+        .map_err(|e| Error::InvalidConfig(format!("{}: {}", path.display(), e)))?;
+    Ok(layer)
+}
+
+/// Pushes the config file, if it exists and can be used
+fn push_file_layer(
+    config: &mut LayeredConfiguration,
+    origin: ConfigOrigin,
+    path: &Path,
+    diagnostics: &mut Vec<ConfigDiagnostic>,
+) {
+    if !path.exists() {
+        return;
+    }
+    match read_to_string(path) {
+        Ok(content) => {
+            if let Some(layer) = check_config_file(&origin, &content, diagnostics) {
+                config.push(origin, layer);
+            }
+        }
+        Err(e) => {
+            tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to read file.");
+            let message = format!("failed to read: {}", e);
+            diagnostics.push(ConfigDiagnostic::error(origin, None, message));
+        }
+    }
+}
+
+/// Pushes a layer setting one key, if its value has the right type
+fn push_override_layer(
+    config: &mut LayeredConfiguration,
+    origin: ConfigOrigin,
+    key: &str,
+    value: &str,
+    diagnostics: &mut Vec<ConfigDiagnostic>,
+) {
+    let layer = override_layer(key, value);
+    match serde_yaml::from_value::<Configuration>(layer.clone()) {
+        Ok(_) => config.push(origin, layer),
+        Err(e) => {
+            let message = format!("{}: {}", key, e);
+            diagnostics.push(ConfigDiagnostic::error(origin, None, message));
+        }
+    }
+}
+
+/// Loads every layer of the config (see [`LayeredConfiguration`]), skipping
+/// the invalid ones
+pub fn load_layered_configuration() -> Result<(LayeredConfiguration, Vec<ConfigDiagnostic>)> {
+    let mut config = LayeredConfiguration::default();
+    let mut diagnostics = Vec::new();
+    let system = PathBuf::from(SYSTEM_CONFIG_PATH);
+    push_file_layer(
+        &mut config,
+        ConfigOrigin::System(system.clone()),
+        &system,
+        &mut diagnostics,
+    );
+    let user = get_configuration_path()?;
+    push_file_layer(
+        &mut config,
+        ConfigOrigin::User(user.clone()),
+        &user,
+        &mut diagnostics,
+    );
+    let mut vars = std::env::vars().collect::<Vec<_>>();
+    vars.sort();
+    for (var, value) in vars {
+        // The hooks get variables with the same prefix, e.g. `REGOLITH_WALLPAPER_SOURCE`
+        let Some(key) = env_config_key(&var) else {
+            continue;
+        };
+        if config.is_known(key.split('.').next().unwrap_or_default()) {
+            let origin = ConfigOrigin::Env(var);
+            push_override_layer(&mut config, origin, &key, &value, &mut diagnostics);
+        }
+    }
+    for (key, value) in CONFIG_OVERRIDES.get().into_iter().flatten() {
+        push_override_layer(&mut config, ConfigOrigin::Cli, key, value, &mut diagnostics);
+    }
+    Ok((config, diagnostics))
+}
+
+pub fn get_config_dir() -> Result<PathBuf> {
     let path = BaseDirs::new()
         .ok_or(Error::NoHomeDir)?
         .config_dir()
-        .join("regolith-wallpaper/config.yaml");
+        .join("regolith-wallpaper");
     Ok(path)
 }
 
-pub fn get_configuration() -> Result<Configuration> {
+pub fn get_cache_dir() -> Result<PathBuf> {
+    let path = BaseDirs::new()
+        .ok_or(Error::NoHomeDir)?
+        .cache_dir()
+        .join("regolith-wallpaper");
+    Ok(path)
+}
+
+pub fn get_configuration_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("config.yaml"))
+}
+
+/// Loads the config, along with the problems found on it. Invalid layers are
+/// skipped, so the returned config is usable even when there are errors.
+pub fn load_configuration() -> Result<(Configuration, Vec<ConfigDiagnostic>)> {
     let path = get_configuration_path()?;
 
-    if path.exists() {
-        return Configuration::from_file(&path);
+    if !path.exists() {
+        tracing::info!("Config not found, creating a default one...");
+        create_dir_all(path.parent().unwrap()).map_err(|e| {
+            tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to create folder.");
+            Error::UnexpectedError(e.to_string())
+        })?;
+        write(&path, USER_CONFIG_TEMPLATE).map_err(|e| {
+            tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to path file.");
+            Error::FailedToWriteFile(path.clone())
+        })?;
+        tracing::info!("Wrote config file to: {:?}", path);
     }
 
-    tracing::info!("Config not found, creating a default one...");
-    create_dir_all(path.parent().unwrap()).map_err(|e| {
-        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to create folder.");
+    let (config, mut diagnostics) = load_layered_configuration()?;
+    let configuration = match config.configuration() {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            let origin = ConfigOrigin::Default;
+            diagnostics.push(ConfigDiagnostic::error(origin, None, e.to_string()));
+            Configuration::default()
+        }
+    };
+    diagnostics.extend(validate_configuration(&config, &configuration));
+    Ok((configuration, diagnostics))
+}
+
+/// Loads the config, failing if it has errors
+pub fn get_configuration() -> Result<Configuration> {
+    let (configuration, diagnostics) = load_configuration()?;
+    if has_config_errors(&diagnostics) {
+        let errors = diagnostics
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n");
+        return Err(Error::InvalidConfig(errors));
+    }
+    for diagnostic in diagnostics {
+        tracing::warn!(%diagnostic, "Configuration problem.");
+    }
+    Ok(configuration)
+}
+
+/// Saves a setting changed from the GUI on the user config file, leaving the
+/// other ones as they are (so the values of the other layers aren't copied)
+pub async fn save_user_setting(key: &str, value: impl Serialize) -> Result<()> {
+    let value = serde_yaml::to_value(value).map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to serialize content.");
         Error::UnexpectedError(e.to_string())
     })?;
-    let config = Configuration::default();
-    let content = serde_yaml::to_string(&config).map_err(|e| {
+    let path = get_configuration_path()?;
+    // Files of older versions are upgraded on the first change
+    let mut layer = if path.exists() {
+        read_file_layer(&path)?
+    } else {
+        Value::Null
+    };
+    if !layer.is_mapping() {
+        layer = Value::Mapping(Mapping::new());
+    }
+    if let Value::Mapping(mapping) = &mut layer {
+        if !mapping.contains_key("version") {
+            mapping.insert("version".into(), CONFIG_VERSION.into());
+        }
+        mapping.insert(Value::String(key.to_string()), value);
+    }
+    let content = serde_yaml::to_string(&layer).map_err(|e| {
         tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to serialize content.");
         Error::UnexpectedError(e.to_string())
     })?;
-    write(&path, content).map_err(|e| {
-        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to path file.");
+    if let Some(folder) = path.parent() {
+        tokio::fs::create_dir_all(folder)
+            .await
+            .map_err(|_| Error::FailedToWriteFile(folder.into()))?;
+    }
+    write_atomic(&path, content).await.map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to write file.");
         Error::FailedToWriteFile(path.clone())
     })?;
-    tracing::info!("Wrote config file to: {:?}", path);
-    Ok(config)
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_override_each_other() {
+        let mut config = LayeredConfiguration::default();
+        let system = serde_yaml::from_str(
+            "wallpapers_paths: [/srv/wallpapers]\nslideshow_interval: 600\nhooks:\n  post_apply:\n    - command: notify-send hi\n",
+        )
+        .unwrap();
+        config.push(ConfigOrigin::System(SYSTEM_CONFIG_PATH.into()), system);
+        // Files saved by older versions have every key, `null` ones included
+        let user =
+            serde_yaml::from_str("wallpapers_paths: null\nslideshow_interval: 300\n").unwrap();
+        config.push(ConfigOrigin::User("/home/me/config.yaml".into()), user);
+        let var = "REGOLITH_WALLPAPER_WORKSPACES__DEBOUNCE";
+        let key = env_config_key(var).unwrap();
+        assert_eq!(key, "workspaces.debounce");
+        config.push(ConfigOrigin::Env(var.into()), override_layer(&key, "500"));
+        let (key, value) = parse_config_override("daily_seed=team").unwrap();
+        config.push(ConfigOrigin::Cli, override_layer(&key, &value));
+        assert!(parse_config_override("daily_seed").is_err());
+
+        let settings = config.configuration().unwrap();
+        assert_eq!(
+            settings.wallpapers_path(),
+            Some(Path::new("/srv/wallpapers"))
+        );
+        assert_eq!(settings.slideshow_interval, Some(300));
+        assert_eq!(settings.hooks.post_apply.len(), 1);
+        assert_eq!(settings.workspaces.debounce, Some(500));
+        assert_eq!(settings.daily_seed.as_deref(), Some("team"));
+
+        assert!(matches!(
+            config.origin("wallpapers_paths"),
+            ConfigOrigin::System(_)
+        ));
+        assert!(matches!(
+            config.origin("slideshow_interval"),
+            ConfigOrigin::User(_)
+        ));
+        assert!(matches!(
+            config.origin("hooks.post_apply"),
+            ConfigOrigin::System(_)
+        ));
+        assert_eq!(
+            config.origin("workspaces.debounce"),
+            ConfigOrigin::Env(var.into())
+        );
+        assert_eq!(config.origin("workspaces.rules"), ConfigOrigin::Default);
+        assert_eq!(config.origin("daily_seed"), ConfigOrigin::Cli);
+        assert!(config
+            .entries()
+            .iter()
+            .any(|(key, value)| key == "workspaces.debounce" && value.as_u64() == Some(500)));
+    }
 }
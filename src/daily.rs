@@ -0,0 +1,141 @@
+use crate::{get_cache_dir, write_atomic, Error, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use tokio::fs::create_dir_all;
+
+/// Seed used when none is set on the config file or the CLI
+pub const DEFAULT_DAILY_SEED: &str = "regolith-wallpaper";
+
+/// Image of the day: the one with the highest hash of the seed, the date and
+/// its path relative to the library root. Machines sharing the same folder
+/// and seed pick the same image whatever the order of the paths, and adding an
+/// image only changes the pick if the new one wins.
+pub fn daily_pick<'a>(
+    paths: impl IntoIterator<Item = &'a Path>,
+    root: Option<&Path>,
+    seed: &str,
+    date: NaiveDate,
+) -> Option<&'a Path> {
+    paths.into_iter().max_by_key(|path| {
+        let relative = root
+            .and_then(|root| path.strip_prefix(root).ok())
+            .unwrap_or(path);
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(seed.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(date.to_string().as_bytes());
+        hasher.update(b"\0");
+        hasher.update(relative.to_string_lossy().as_bytes());
+        *hasher.finalize().as_bytes()
+    })
+}
+
+/// Last image of the day applied, stored on
+/// `~/.cache/regolith-wallpaper/daily.yaml`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyState {
+    /// E.g.: `2024-05-17`
+    pub date: String,
+    /// Seed and filters used for the pick, so changing them picks again on
+    /// the same day
+    pub key: String,
+    pub path: PathBuf,
+}
+
+impl DailyState {
+    /// Whether the image of `date` was already applied with the same options
+    pub fn is_applied(&self, date: NaiveDate, key: &str) -> bool {
+        self.date == date.to_string() && self.key == key
+    }
+}
+
+fn get_daily_state_path() -> Result<PathBuf> {
+    Ok(get_cache_dir()?.join("daily.yaml"))
+}
+
+pub fn load_daily_state() -> Result<Option<DailyState>> {
+    let path = get_daily_state_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = read_to_string(&path).map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to read file.");
+        Error::FailedToRead(path.clone())
+    })?;
+    // A broken state only means applying the image of the day again
+    Ok(serde_yaml::from_str(&content).ok())
+}
+
+pub async fn save_daily_state(state: DailyState) -> Result<()> {
+    let content = serde_yaml::to_string(&state).map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to serialize content.");
+        Error::UnexpectedError(e.to_string())
+    })?;
+    let path = get_daily_state_path()?;
+    if let Some(folder) = path.parent() {
+        create_dir_all(folder)
+            .await
+            .map_err(|_| Error::FailedToWriteFile(folder.into()))?;
+    }
+    write_atomic(&path, content).await.map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to write file.");
+        Error::FailedToWriteFile(path.clone())
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn daily_pick_is_deterministic() {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 17).unwrap();
+        let paths = (0..20)
+            .map(|i| PathBuf::from(format!("/home/a/wp/{}.png", i)))
+            .collect::<Vec<_>>();
+        let other_machine = paths
+            .iter()
+            .rev()
+            .map(|path| Path::new("/mnt/b").join(path.strip_prefix("/home/a/wp").unwrap()))
+            .collect::<Vec<_>>();
+        let pick = daily_pick(
+            paths.iter().map(PathBuf::as_path),
+            Some(Path::new("/home/a/wp")),
+            "team",
+            date,
+        )
+        .unwrap();
+        let other_pick = daily_pick(
+            other_machine.iter().map(PathBuf::as_path),
+            Some(Path::new("/mnt/b")),
+            "team",
+            date,
+        )
+        .unwrap();
+        assert_eq!(pick.file_name(), other_pick.file_name());
+
+        let picks = (0..10)
+            .map(|day| {
+                daily_pick(
+                    paths.iter().map(PathBuf::as_path),
+                    None,
+                    "team",
+                    date + chrono::Days::new(day),
+                )
+            })
+            .collect::<std::collections::HashSet<_>>();
+        assert!(picks.len() > 1);
+
+        let state = DailyState {
+            date: "2024-05-17".into(),
+            key: "team".into(),
+            path: pick.into(),
+        };
+        assert!(state.is_applied(date, "team"));
+        assert!(!state.is_applied(date, "other"));
+        assert!(!state.is_applied(date.succ_opt().unwrap(), "team"));
+    }
+}
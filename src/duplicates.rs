@@ -0,0 +1,300 @@
+use crate::{histogram_distance, ImageEntry};
+use image::{imageops::FilterType, DynamicImage};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+/// Default max number of different bits between two perceptual hashes for the
+/// images to be considered duplicates
+pub const DUPLICATE_MAX_DISTANCE: u32 = 6;
+
+/// Max color histogram distance between two images with close perceptual
+/// hashes for them to be duplicates. The hash of flat images (e.g. solid colors
+/// or smooth gradients) is almost 0 whatever their colors.
+const DUPLICATE_MAX_HISTOGRAM_DISTANCE: f32 = 0.2;
+
+/// 64 bits difference hash (dHash): compares the brightness of adjacent pixels
+/// on a 9x8 grayscale version of the image, so it survives resizing and
+/// re-encoding
+pub fn dhash(img: &DynamicImage) -> u64 {
+    let small = img.resize_exact(9, 8, FilterType::Triangle).into_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = small.get_pixel(x, y).0[0];
+            let right = small.get_pixel(x + 1, y).0[0];
+            hash = (hash << 1) | u64::from(left > right);
+        }
+    }
+    hash
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Whether the colors of two images with close hashes are also alike (images
+/// without histogram never are)
+fn similar_colors(a: &ImageEntry, b: &ImageEntry) -> bool {
+    !a.histogram.is_empty()
+        && !b.histogram.is_empty()
+        && histogram_distance(&a.histogram, &b.histogram) <= DUPLICATE_MAX_HISTOGRAM_DISTANCE
+}
+
+/// Near-identical images, sorted from highest to lowest resolution
+#[derive(Clone, Debug)]
+pub struct DuplicateGroup {
+    pub entries: Vec<ImageEntry>,
+}
+
+impl DuplicateGroup {
+    /// The suggested image to keep: the one with the highest resolution
+    pub fn keep(&self) -> &ImageEntry {
+        &self.entries[0]
+    }
+
+    /// The images suggested to be removed
+    pub fn others(&self) -> impl Iterator<Item = &ImageEntry> {
+        self.entries.iter().skip(1)
+    }
+}
+
+/// BK-tree of perceptual hashes: finds the ones close to a hash without
+/// comparing it with every other
+#[derive(Default)]
+struct HashTree {
+    nodes: Vec<HashNode>,
+}
+
+struct HashNode {
+    phash: u64,
+    /// Indexes of the images with this hash
+    items: Vec<usize>,
+    /// Child nodes by their distance to this one
+    children: BTreeMap<u32, usize>,
+}
+
+impl HashTree {
+    fn insert(&mut self, phash: u64, item: usize) {
+        let mut node = 0;
+        loop {
+            let next = self.nodes.len();
+            let Some(current) = self.nodes.get_mut(node) else {
+                self.nodes.push(HashNode {
+                    phash,
+                    items: vec![item],
+                    children: BTreeMap::new(),
+                });
+                return;
+            };
+            let distance = hamming_distance(current.phash, phash);
+            if distance == 0 {
+                current.items.push(item);
+                return;
+            }
+            match current.children.get(&distance) {
+                Some(child) => node = *child,
+                None => {
+                    current.children.insert(distance, next);
+                    node = next;
+                }
+            }
+        }
+    }
+
+    /// Items whose hashes differ from `phash` on at most `max_distance` bits
+    fn find(&self, phash: u64, max_distance: u32) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut pending = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+        while let Some(node) = pending.pop() {
+            let node = &self.nodes[node];
+            let distance = hamming_distance(node.phash, phash);
+            if distance <= max_distance {
+                found.extend(&node.items);
+            }
+            let range = distance.saturating_sub(max_distance)..=distance + max_distance;
+            pending.extend(node.children.range(range).map(|(_, child)| *child));
+        }
+        found
+    }
+}
+
+/// Groups the images whose perceptual hashes differ on at most `max_distance`
+/// bits and whose colors are alike (or have the same content) from the one
+/// kept. Each group is built
+/// around the highest resolution image left, so every image of a group is
+/// close to the kept one (not only to another image of the group).
+pub fn find_duplicates<'a>(
+    entries: impl IntoIterator<Item = &'a ImageEntry>,
+    max_distance: u32,
+) -> Vec<DuplicateGroup> {
+    let mut entries = entries.into_iter().collect::<Vec<_>>();
+    entries.sort_by_key(|entry| {
+        (
+            std::cmp::Reverse(u64::from(entry.width) * u64::from(entry.height)),
+            std::cmp::Reverse(entry.size),
+        )
+    });
+    let mut tree = HashTree::default();
+    let mut same_content = HashMap::<&str, Vec<usize>>::new();
+    for (i, entry) in entries.iter().enumerate() {
+        tree.insert(entry.phash, i);
+        same_content.entry(&entry.hash).or_default().push(i);
+    }
+    let mut grouped = vec![false; entries.len()];
+    let mut groups = Vec::new();
+    for (i, keep) in entries.iter().enumerate() {
+        if grouped[i] {
+            continue;
+        }
+        let mut members = tree.find(keep.phash, max_distance);
+        members.retain(|j| similar_colors(keep, entries[*j]));
+        members.extend(&same_content[keep.hash.as_str()]);
+        members.sort_unstable();
+        members.dedup();
+        // Images sorted after `i` are smaller, the ones before are grouped
+        members.retain(|j| *j != i && !grouped[*j]);
+        if members.is_empty() {
+            continue;
+        }
+        grouped[i] = true;
+        let mut group = vec![(*keep).clone()];
+        for j in members {
+            grouped[j] = true;
+            group.push(entries[j].clone());
+        }
+        groups.push(DuplicateGroup { entries: group });
+    }
+    groups
+}
+
+/// [`find_duplicates`] on a blocking thread, for the GUI
+pub async fn scan_duplicates(entries: Vec<ImageEntry>, max_distance: u32) -> Vec<DuplicateGroup> {
+    tokio::task::spawn_blocking(move || find_duplicates(&entries, max_distance))
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!(error.cause_chain=?e, error.message=%e, "Task failed.");
+            Vec::new()
+        })
+}
+
+/// Paths suggested to be removed from all the groups
+pub fn suggested_removals(groups: &[DuplicateGroup]) -> Vec<PathBuf> {
+    groups
+        .iter()
+        .flat_map(|group| group.others().map(|entry| entry.path.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color_histogram;
+    use image::{Rgb, RgbImage};
+
+    fn gradient(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            let v = ((x * 255 / width + y * 50 / height) % 256) as u8;
+            Rgb([v, v / 2, 255 - v])
+        }))
+    }
+
+    fn entry(path: &str, width: u32, phash: u64) -> ImageEntry {
+        ImageEntry {
+            path: PathBuf::from(path),
+            size: 0,
+            mtime: 0,
+            width,
+            height: width,
+            format: "png".into(),
+            hash: path.into(),
+            phash,
+            colors: Vec::new(),
+            histogram: color_histogram(&gradient(16, 16).to_rgba8()),
+            luminance: Default::default(),
+            brightness: Default::default(),
+        }
+    }
+
+    #[test]
+    fn dhash_survives_resizing() {
+        let big = dhash(&gradient(640, 360));
+        let small = dhash(&gradient(160, 90));
+        assert!(hamming_distance(big, small) <= DUPLICATE_MAX_DISTANCE);
+        let flipped = dhash(&gradient(640, 360).fliph());
+        assert!(hamming_distance(big, flipped) > DUPLICATE_MAX_DISTANCE);
+    }
+
+    #[test]
+    fn groups_keep_highest_resolution() {
+        let entries = [
+            entry("/small.png", 100, 0b1111),
+            entry("/other.png", 100, u64::MAX),
+            entry("/big.png", 400, 0b1110),
+        ];
+        let groups = find_duplicates(&entries, DUPLICATE_MAX_DISTANCE);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].keep().path, PathBuf::from("/big.png"));
+        assert_eq!(
+            suggested_removals(&groups),
+            vec![PathBuf::from("/small.png")]
+        );
+    }
+
+    #[test]
+    fn flat_images_of_other_colors_are_not_grouped() {
+        let flat = |path: &str, color: [u8; 3]| {
+            let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 64, Rgb(color)));
+            ImageEntry {
+                phash: dhash(&img),
+                histogram: color_histogram(&img.to_rgba8()),
+                ..entry(path, 64, 0)
+            }
+        };
+        let black = flat("/black.png", [0, 0, 0]);
+        let white = flat("/white.png", [255, 255, 255]);
+        assert_eq!(black.phash, white.phash);
+        assert!(find_duplicates(&[black.clone(), white], DUPLICATE_MAX_DISTANCE).is_empty());
+        let groups = find_duplicates(
+            &[
+                black.clone(),
+                ImageEntry {
+                    path: "/black2.png".into(),
+                    hash: "/black2.png".into(),
+                    ..black
+                },
+            ],
+            DUPLICATE_MAX_DISTANCE,
+        );
+        assert_eq!(groups.len(), 1);
+    }
+
+    #[test]
+    fn groups_are_not_transitive() {
+        // a is close to b and b to c, but a and c are 8 bits apart
+        let entries = [
+            entry("/a.png", 400, 0),
+            entry("/b.png", 300, 0xf),
+            entry("/c.png", 200, 0xff),
+            entry("/d.png", 100, 0xff),
+        ];
+        let groups = find_duplicates(&entries, DUPLICATE_MAX_DISTANCE);
+        let paths = groups
+            .iter()
+            .map(|group| {
+                group
+                    .entries
+                    .iter()
+                    .map(|entry| entry.path.to_str().unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![vec!["/a.png", "/b.png"], vec!["/c.png", "/d.png"]]
+        );
+    }
+}
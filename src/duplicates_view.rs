@@ -0,0 +1,141 @@
+use crate::{suggested_removals, DuplicateGroup, Message, ThumbnailCache};
+use iced::widget::{button, checkbox, column, container, image, row, scrollable, text, Column};
+use iced::{theme, Color, Element, Length};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub enum DuplicatesMessage {
+    ToggleMark(PathBuf, bool),
+    TrashMarked,
+    Rescan,
+}
+
+/// Groups of near-identical images, the images marked are sent to the trash
+#[derive(Default)]
+pub struct Duplicates {
+    pub groups: Vec<DuplicateGroup>,
+    pub marked: HashSet<PathBuf>,
+    /// Looking for duplicates on a background thread
+    pub scanning: bool,
+}
+
+impl Duplicates {
+    /// Marks every image except the highest resolution one of each group
+    pub fn new(groups: Vec<DuplicateGroup>) -> Self {
+        let marked = suggested_removals(&groups).into_iter().collect();
+        Self {
+            groups,
+            marked,
+            scanning: false,
+        }
+    }
+
+    /// Takes out the removed images, dropping the groups left with one image
+    pub fn remove(&mut self, is_removed: impl Fn(&Path) -> bool) {
+        for group in &mut self.groups {
+            group.entries.retain(|entry| !is_removed(&entry.path));
+        }
+        self.groups.retain(|group| group.entries.len() > 1);
+        self.marked.retain(|path| !is_removed(path));
+    }
+
+    pub fn update(&mut self, message: DuplicatesMessage) -> Option<Message> {
+        match message {
+            DuplicatesMessage::ToggleMark(path, marked) => {
+                if marked {
+                    self.marked.insert(path);
+                } else {
+                    self.marked.remove(&path);
+                }
+                None
+            }
+            DuplicatesMessage::TrashMarked => {
+                let paths = self.marked.iter().cloned().collect::<Vec<_>>();
+                (!paths.is_empty()).then_some(Message::TrashImages(paths))
+            }
+            DuplicatesMessage::Rescan => Some(Message::FindDuplicates),
+        }
+    }
+
+    pub fn view<'a>(&'a self, thumbnails: &'a ThumbnailCache) -> Element<'a, DuplicatesMessage> {
+        if self.scanning {
+            return text("Looking for duplicates...").into();
+        }
+        let rescan_btn = button(text("Scan again").size(14))
+            .padding([2, 6])
+            .style(theme::Button::Secondary)
+            .on_press(DuplicatesMessage::Rescan);
+        if self.groups.is_empty() {
+            return row!(text("No duplicates found."), rescan_btn)
+                .spacing(20)
+                .into();
+        }
+        let groups = self.groups.iter().map(|group| {
+            let tiles = group.entries.iter().enumerate().map(|(i, entry)| {
+                let thumbnail: Element<_> = match thumbnails.get(&entry.path) {
+                    Some(handle) => image(handle.clone()).width(180).height(100).into(),
+                    None => container(text("")).width(180).height(100).into(),
+                };
+                let label = if i == 0 { "keep" } else { "trash" };
+                let label_color = if i == 0 {
+                    Color::from_rgb(0.4, 0.8, 0.4)
+                } else {
+                    Color::from_rgb(0.9, 0.2, 0.2)
+                };
+                column!(
+                    thumbnail,
+                    text(entry.file_name()).size(12),
+                    text(format!(
+                        "{}x{} · {} KB",
+                        entry.width,
+                        entry.height,
+                        entry.size / 1024
+                    ))
+                    .size(12),
+                    row!(
+                        checkbox("Trash", self.marked.contains(&entry.path), |marked| {
+                            DuplicatesMessage::ToggleMark(entry.path.clone(), marked)
+                        })
+                        .size(14)
+                        .text_size(12),
+                        text(format!("(suggested: {})", label))
+                            .size(12)
+                            .style(label_color)
+                    )
+                    .spacing(6)
+                )
+                .spacing(2)
+                .width(200)
+                .into()
+            });
+            container(row(tiles.collect()).spacing(10))
+                .padding(10)
+                .style(|_: &_| container::Appearance {
+                    border_width: 1.0,
+                    border_color: Color::from_rgb(0.3, 0.3, 0.3),
+                    ..Default::default()
+                })
+                .into()
+        });
+
+        let trash_btn =
+            button(text(format!("Move {} marked to trash", self.marked.len())).size(14))
+                .padding([2, 6])
+                .style(theme::Button::Destructive)
+                .on_press_maybe(
+                    (!self.marked.is_empty()).then_some(DuplicatesMessage::TrashMarked),
+                );
+        column!(
+            row!(
+                text(format!("{} groups of duplicates", self.groups.len())).size(16),
+                trash_btn,
+                rescan_btn
+            )
+            .spacing(20),
+            scrollable(Column::with_children(groups.collect()).spacing(10)).height(Length::Fill)
+        )
+        .spacing(10)
+        .into()
+    }
+}
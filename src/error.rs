@@ -10,10 +10,46 @@ pub enum Error {
     FailedReadRegConfigFile,
     #[error("No wallpaper setting (`regolith.wallpaper.file`) on config file (~/.config/regolith3/Xresources).")]
     NoWallpaperOnRegConfigFile,
+    #[error("No look found on config file (~/.config/regolith3/Xresources).")]
+    NoLookOnRegConfigFile,
     #[error("Failed to read file: {0}")]
     FailedToRead(PathBuf),
     #[error("Failed to write file: {0}")]
     FailedToWriteFile(PathBuf),
+    #[error("Failed to move file to trash: {0}")]
+    FailedToTrash(PathBuf),
+    #[error("Failed to move file: {0}")]
+    FailedToMove(PathBuf),
+    #[error("A file with the same name already exists: {0}")]
+    FileExists(PathBuf),
+    #[error("Invalid file name: `{0}`")]
+    InvalidFileName(String),
+    #[error("Not a supported image: {0}")]
+    UnsupportedImage(PathBuf),
+    #[error("Not a valid archive: {0}")]
+    InvalidArchive(PathBuf),
+    #[error("Already on the library: {0}")]
+    DuplicateImage(PathBuf),
+    #[error("Images inside archives are read-only.")]
+    ReadOnlyArchive,
+    #[error("Failed to download: {0}")]
+    FailedToDownload(String),
+    #[error("Unexpected response from: {0}")]
+    InvalidProviderResponse(String),
+    #[error("Failed to talk to the running instance: {0}")]
+    IpcFailed(String),
+    #[error("Another instance is already running.")]
+    AlreadyRunning,
+    #[error("No backup of the regolith config file for {0}.")]
+    NoBackup(String),
+    #[error("Hook `{0}` failed: {1}")]
+    HookFailed(String, String),
+    #[error("Wallpaper change cancelled. {0}")]
+    WallpaperVetoed(Box<Error>),
+    #[error("Failed to talk to i3: {0}")]
+    I3IpcFailed(String),
+    #[error("Invalid configuration:\n{0}")]
+    InvalidConfig(String),
     #[error("Invalid path: {0}")]
     InvalidPath(PathBuf),
     #[error("No home directory found.")]
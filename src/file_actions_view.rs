@@ -0,0 +1,199 @@
+use crate::Message;
+use iced::widget::{button, column, container, pick_list, row, text, text_input};
+use iced::{theme, Alignment, Background, Color, Element};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Actions of the image context menu, applied to the selection when the image
+/// is selected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileAction {
+    Rename,
+    Move,
+    CopyPath,
+    Reveal,
+    Trash,
+}
+
+/// Context menu shown when right clicking an image
+pub fn context_menu_view<'a>(id: usize) -> Element<'a, Message> {
+    let item = |label: &'a str, message: Message| {
+        button(text(label).size(14))
+            .padding([3, 10])
+            .width(200)
+            .style(theme::Button::Text)
+            .on_press(message)
+    };
+    container(column!(
+        item("More like this", Message::MoreLikeThis(id)),
+        item("Rename…", Message::FileAction(id, FileAction::Rename)),
+        item("Move to…", Message::FileAction(id, FileAction::Move)),
+        item("Copy path", Message::FileAction(id, FileAction::CopyPath)),
+        item(
+            "Reveal in file manager",
+            Message::FileAction(id, FileAction::Reveal)
+        ),
+        item("Move to trash", Message::FileAction(id, FileAction::Trash)),
+    ))
+    .padding(4)
+    .style(|_: &_| container::Appearance {
+        background: Some(Background::Color(Color::from_rgb(0.16, 0.17, 0.2))),
+        border_width: 1.0,
+        border_radius: 4.0.into(),
+        border_color: Color::from_rgb(0.3, 0.3, 0.3),
+        ..Default::default()
+    })
+    .into()
+}
+
+/// Folder where images can be moved to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Destination {
+    pub path: PathBuf,
+    /// Path relative to the library root, if inside it
+    label: String,
+}
+
+impl Destination {
+    pub fn new(path: PathBuf, root: Option<&Path>) -> Self {
+        let label = match root.and_then(|root| Some((root, path.strip_prefix(root).ok()?))) {
+            Some((root, relative)) if relative.as_os_str().is_empty() => {
+                format!("{} (library root)", root.display())
+            }
+            Some((_, relative)) => relative.display().to_string(),
+            None => path.display().to_string(),
+        };
+        Self { path, label }
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.label)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum FileActionsMessage {
+    InputEdit(String),
+    DestinationSelected(Destination),
+    Confirm,
+    Cancel,
+}
+
+enum PendingAction {
+    Rename(PathBuf),
+    Move {
+        paths: Vec<PathBuf>,
+        destinations: Vec<Destination>,
+        destination: Option<Destination>,
+    },
+}
+
+/// Asks for the new name or the destination folder of the images
+#[derive(Default)]
+pub struct FileActions {
+    pending: Option<PendingAction>,
+    /// New name, or new sub-folder inside the destination when moving
+    input: String,
+}
+
+impl FileActions {
+    pub fn start_rename(&mut self, path: PathBuf) {
+        self.input = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.pending = Some(PendingAction::Rename(path));
+    }
+
+    pub fn start_move(&mut self, paths: Vec<PathBuf>, destinations: Vec<Destination>) {
+        self.input.clear();
+        self.pending = Some(PendingAction::Move {
+            paths,
+            destinations,
+            destination: None,
+        });
+    }
+
+    pub fn update(&mut self, message: FileActionsMessage) -> Option<Message> {
+        match message {
+            FileActionsMessage::InputEdit(input) => {
+                self.input = input;
+                None
+            }
+            FileActionsMessage::DestinationSelected(selected) => {
+                if let Some(PendingAction::Move { destination, .. }) = &mut self.pending {
+                    *destination = Some(selected);
+                }
+                None
+            }
+            FileActionsMessage::Confirm => match self.pending.take()? {
+                PendingAction::Rename(path) => {
+                    Some(Message::RenameFile(path, std::mem::take(&mut self.input)))
+                }
+                PendingAction::Move {
+                    paths, destination, ..
+                } => {
+                    let mut folder = destination?.path;
+                    let sub_folder = std::mem::take(&mut self.input);
+                    if !sub_folder.trim().is_empty() {
+                        folder.push(sub_folder.trim());
+                    }
+                    Some(Message::MoveFiles(paths, folder))
+                }
+            },
+            FileActionsMessage::Cancel => {
+                self.pending = None;
+                None
+            }
+        }
+    }
+
+    /// Rename or move bar, nothing if there is no pending action
+    pub fn view(&self) -> Option<Element<'_, FileActionsMessage>> {
+        let cancel_btn = button(text("Cancel").size(14))
+            .padding([2, 10])
+            .style(theme::Button::Secondary)
+            .on_press(FileActionsMessage::Cancel);
+        let content = match self.pending.as_ref()? {
+            PendingAction::Rename(path) => row!(
+                text(format!("Rename {}", path.display())).size(14),
+                text_input("New name", &self.input)
+                    .on_input(FileActionsMessage::InputEdit)
+                    .on_submit(FileActionsMessage::Confirm)
+                    .size(14)
+                    .width(250),
+                button(text("Rename").size(14))
+                    .padding([2, 10])
+                    .on_press(FileActionsMessage::Confirm),
+                cancel_btn
+            ),
+            PendingAction::Move {
+                paths,
+                destinations,
+                destination,
+            } => row!(
+                text(format!("Move {} images to", paths.len())).size(14),
+                pick_list(
+                    destinations.as_slice(),
+                    destination.clone(),
+                    FileActionsMessage::DestinationSelected
+                )
+                .placeholder("Folder...")
+                .text_size(14)
+                .padding([2, 8]),
+                text_input("New sub-folder (optional)", &self.input)
+                    .on_input(FileActionsMessage::InputEdit)
+                    .on_submit(FileActionsMessage::Confirm)
+                    .size(14)
+                    .width(200),
+                button(text("Move").size(14))
+                    .padding([2, 10])
+                    .on_press_maybe(destination.is_some().then_some(FileActionsMessage::Confirm)),
+                cancel_btn
+            ),
+        };
+        Some(content.spacing(10).align_items(Alignment::Center).into())
+    }
+}
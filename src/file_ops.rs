@@ -0,0 +1,122 @@
+use crate::{Error, Result};
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+use tokio::fs::{copy, create_dir_all, read_link, remove_file, rename, symlink, symlink_metadata};
+
+/// Moves a file, copying it when the destination is on another filesystem (a
+/// symlink is recreated there, not replaced by a copy of its target)
+pub async fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    match rename(from, to).await {
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            if symlink_metadata(from).await?.file_type().is_symlink() {
+                symlink(read_link(from).await?, to).await?;
+            } else {
+                copy(from, to).await?;
+            }
+            remove_file(from).await
+        }
+        result => result,
+    }
+}
+
+/// Checks a new file name, adding the extension of the original file if it
+/// has none
+pub fn validate_file_name(original: &Path, name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(Error::InvalidFileName(name.to_string()));
+    }
+    match original.extension() {
+        Some(ext) if Path::new(name).extension().is_none() => {
+            Ok(format!("{}.{}", name, ext.to_string_lossy()))
+        }
+        _ => Ok(name.to_string()),
+    }
+}
+
+/// Renames a file inside its folder, returns the new path
+#[tracing::instrument]
+pub async fn rename_file(path: PathBuf, name: String) -> Result<PathBuf> {
+    let name = validate_file_name(&path, &name)?;
+    let new_path = path.with_file_name(name);
+    if new_path == path {
+        return Ok(path);
+    }
+    if new_path.exists() {
+        return Err(Error::FileExists(new_path));
+    }
+    rename(&path, &new_path).await.map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to rename file.");
+        Error::FailedToMove(path.clone())
+    })?;
+    Ok(new_path)
+}
+
+/// Moves the files into `folder` (created if missing), without overwriting
+/// files with the same name. Returns the new path of each file.
+pub async fn move_files(paths: Vec<PathBuf>, folder: PathBuf) -> Vec<(PathBuf, Result<PathBuf>)> {
+    if let Err(e) = create_dir_all(&folder).await {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?folder, "Failed to create folder.");
+        return paths
+            .into_iter()
+            .map(|path| (path, Err(Error::FailedToWriteFile(folder.clone()))))
+            .collect();
+    }
+    let mut results = Vec::with_capacity(paths.len());
+    for path in paths {
+        let result = match path.file_name() {
+            Some(name) => {
+                let new_path = folder.join(name);
+                if new_path == path {
+                    Ok(new_path)
+                } else if new_path.exists() {
+                    Err(Error::FileExists(new_path))
+                } else {
+                    match move_file(&path, &new_path).await {
+                        Ok(()) => Ok(new_path),
+                        Err(e) => {
+                            tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to move file.");
+                            Err(Error::FailedToMove(path.clone()))
+                        }
+                    }
+                }
+            }
+            None => Err(Error::InvalidPath(path.clone())),
+        };
+        results.push((path, result));
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_keep_the_extension() {
+        let original = Path::new("/wallpapers/old.png");
+        assert_eq!(validate_file_name(original, " new ").unwrap(), "new.png");
+        assert_eq!(validate_file_name(original, "new.jpg").unwrap(), "new.jpg");
+        assert!(validate_file_name(original, "a/b").is_err());
+        assert!(validate_file_name(original, "").is_err());
+    }
+
+    #[tokio::test]
+    async fn moving_does_not_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        let folder = root.join("space");
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(root.join("a.png"), b"a").unwrap();
+        std::fs::write(root.join("b.png"), b"b").unwrap();
+        std::fs::write(folder.join("b.png"), b"other").unwrap();
+        let results =
+            move_files(vec![root.join("a.png"), root.join("b.png")], folder.clone()).await;
+        let renamed = rename_file(folder.join("a.png"), "c".into()).await;
+        assert_eq!(results[0].1.as_ref().unwrap(), &folder.join("a.png"));
+        assert!(matches!(results[1].1, Err(Error::FileExists(_))));
+        assert_eq!(renamed.unwrap(), folder.join("c.png"));
+    }
+}
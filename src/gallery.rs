@@ -0,0 +1,184 @@
+use crate::{
+    thumbnail_height, Message, Resolution, Selection, ThumbnailCache, TileState, WallpaperImage,
+};
+use iced::widget::{column, responsive, row, scrollable, vertical_space, Column};
+use iced::{Element, Length};
+use std::ops::Range;
+
+/// Thumbnail sizes (widths) available with the zoom slider
+pub const MIN_THUMBNAIL_SIZE: u32 = 160;
+pub const MAX_THUMBNAIL_SIZE: u32 = 720;
+pub const THUMBNAIL_SIZE_STEP: u32 = 40;
+/// Rows created (and thumbnails loaded) above and below the visible ones, so
+/// they are ready when scrolling
+const OVERSCAN_ROWS: usize = 2;
+
+/// Rows of the gallery that need widgets for the current scroll position
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GalleryLayout {
+    pub tile_width: f32,
+    pub tile_height: f32,
+    pub columns: usize,
+    pub rows: usize,
+    pub first_row: usize,
+    /// Exclusive
+    pub last_row: usize,
+}
+
+/// Space taken by each image tile with the given thumbnail size, margins
+/// included
+pub fn tile_size(thumbnail_size: u32) -> (f32, f32) {
+    (
+        (thumbnail_size + 24) as f32,
+        (thumbnail_height(thumbnail_size) + 30) as f32,
+    )
+}
+
+/// Clamps and rounds the thumbnail size to the slider steps
+pub fn clamp_thumbnail_size(size: u32) -> u32 {
+    let size = size.clamp(MIN_THUMBNAIL_SIZE, MAX_THUMBNAIL_SIZE);
+    size - (size - MIN_THUMBNAIL_SIZE) % THUMBNAIL_SIZE_STEP
+}
+
+/// Id of the gallery scrollable, to keep the scroll position when zooming
+pub fn gallery_id() -> scrollable::Id {
+    scrollable::Id::new("gallery")
+}
+
+impl GalleryLayout {
+    pub fn new(len: usize, width: f32, height: f32, offset_y: f32, thumbnail_size: u32) -> Self {
+        let (tile_width, tile_height) = tile_size(thumbnail_size);
+        let columns = ((width / tile_width) as usize).max(1);
+        let rows = len.div_ceil(columns);
+        let visible_rows = (height.max(0.0) / tile_height).ceil() as usize + 1;
+        // The offset may be past the end after the list shrinks
+        let first_row = ((offset_y.max(0.0) / tile_height) as usize)
+            .saturating_sub(OVERSCAN_ROWS)
+            .min(rows.saturating_sub(visible_rows));
+        let last_row = (first_row + visible_rows + 2 * OVERSCAN_ROWS).min(rows);
+        Self {
+            tile_width,
+            tile_height,
+            columns,
+            rows,
+            first_row,
+            last_row,
+        }
+    }
+
+    /// Indexes of the images on the created rows
+    pub fn visible(&self, len: usize) -> Range<usize> {
+        (self.first_row * self.columns).min(len)..(self.last_row * self.columns).min(len)
+    }
+
+    /// Index of the first image on the row at the scroll position
+    pub fn first_visible(&self, offset_y: f32) -> usize {
+        (offset_y.max(0.0) / self.tile_height) as usize * self.columns
+    }
+
+    /// Scroll position of the row with the image at `index`
+    pub fn offset_of(&self, index: usize) -> f32 {
+        (index / self.columns) as f32 * self.tile_height
+    }
+
+    fn space_above(&self) -> f32 {
+        self.first_row as f32 * self.tile_height
+    }
+
+    fn space_below(&self) -> f32 {
+        (self.rows - self.last_row) as f32 * self.tile_height
+    }
+}
+
+/// Grid of image tiles where only the rows around the scroll position are
+/// created, the others are replaced by empty space of the same height
+pub fn gallery_view<'a>(
+    images: Vec<&'a WallpaperImage>,
+    thumbnails: &'a ThumbnailCache,
+    screen: Option<Resolution>,
+    offset_y: f32,
+    thumbnail_size: u32,
+    selection: &'a Selection,
+) -> Element<'a, Message> {
+    responsive(move |size| {
+        let layout = GalleryLayout::new(
+            images.len(),
+            size.width,
+            size.height,
+            offset_y,
+            thumbnail_size,
+        );
+        let rows = images[layout.visible(images.len())]
+            .chunks(layout.columns)
+            .map(|chunk| {
+                row(chunk
+                    .iter()
+                    .map(|image| {
+                        let thumbnail = thumbnails.get(&image.path);
+                        let state = TileState {
+                            selected: selection.contains(&image.path),
+                            focused: selection.cursor() == Some(image.path.as_path()),
+                        };
+                        iced::widget::container(image.view(
+                            thumbnail,
+                            screen,
+                            thumbnail_size,
+                            state,
+                        ))
+                        .width(layout.tile_width)
+                        .height(layout.tile_height)
+                        .center_x()
+                        .into()
+                    })
+                    .collect())
+                .into()
+            })
+            .collect();
+        let content = column!(
+            vertical_space(layout.space_above()),
+            Column::with_children(rows),
+            vertical_space(layout.space_below())
+        );
+        scrollable(
+            iced::widget::container(content)
+                .width(Length::Fill)
+                .center_x(),
+        )
+        .height(Length::Fill)
+        .id(gallery_id())
+        .on_scroll(Message::GalleryScrolled)
+        .into()
+    })
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_rows_around_the_viewport_are_visible() {
+        let layout = GalleryLayout::new(50_000, 1200.0, 700.0, 100.0 * 230.0, 360);
+        assert_eq!(layout.columns, 3);
+        assert_eq!(layout.rows, 16_667);
+        assert_eq!(layout.first_row, 98);
+        assert_eq!(layout.last_row, 98 + 5 + 4);
+        assert_eq!(layout.visible(50_000), 294..321);
+        let end = GalleryLayout::new(10, 1200.0, 700.0, 10_000.0, 360);
+        assert_eq!(end.visible(10), 0..10);
+        let empty = GalleryLayout::new(0, 1200.0, 700.0, 0.0, 360);
+        assert_eq!(empty.visible(0), 0..0);
+    }
+
+    #[test]
+    fn zooming_keeps_the_first_visible_image() {
+        let small = GalleryLayout::new(1000, 1200.0, 700.0, 0.0, 160);
+        let big = GalleryLayout::new(1000, 1200.0, 700.0, 0.0, 560);
+        assert_eq!((small.columns, big.columns), (6, 2));
+        let index = small.first_visible(10.0 * small.tile_height);
+        assert_eq!(index, 60);
+        assert_eq!(big.offset_of(index), 30.0 * big.tile_height);
+        assert_eq!(clamp_thumbnail_size(385), 360);
+        assert_eq!(clamp_thumbnail_size(5000), MAX_THUMBNAIL_SIZE);
+    }
+}
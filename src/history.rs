@@ -0,0 +1,139 @@
+use crate::{
+    extract_if_archived, get_cache_dir, load_regolith_config, run_post_apply_hooks,
+    run_pre_apply_hooks, send_command, set_wallpaper_on_config, write_atomic, ApplySource, Error,
+    HookEnv, Hooks, IpcCommand, Result, Rgb,
+};
+use serde::{Deserialize, Serialize};
+use std::{fs::read_to_string, path::PathBuf};
+use tokio::fs::create_dir_all;
+
+/// Number of applied wallpapers kept on the history
+pub const MAX_HISTORY: usize = 200;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub path: PathBuf,
+    /// E.g.: `2024-05-17T08:30:00`
+    pub applied_at: String,
+}
+
+/// Wallpapers applied, oldest first, stored on
+/// `~/.cache/regolith-wallpaper/history.yaml`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct WallpaperHistory {
+    #[serde(default)]
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl WallpaperHistory {
+    /// Adds the wallpaper, dropping the oldest ones past [`MAX_HISTORY`]
+    pub fn push(&mut self, path: PathBuf, applied_at: String) {
+        self.entries.push(HistoryEntry { path, applied_at });
+        if self.entries.len() > MAX_HISTORY {
+            self.entries.drain(..self.entries.len() - MAX_HISTORY);
+        }
+    }
+
+    /// Last applied wallpaper
+    pub fn last(&self) -> Option<&HistoryEntry> {
+        self.entries.last()
+    }
+}
+
+fn get_history_path() -> Result<PathBuf> {
+    Ok(get_cache_dir()?.join("history.yaml"))
+}
+
+pub fn load_history() -> Result<WallpaperHistory> {
+    let path = get_history_path()?;
+    if !path.exists() {
+        return Ok(WallpaperHistory::default());
+    }
+    let content = read_to_string(&path).map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to read file.");
+        Error::FailedToRead(path.clone())
+    })?;
+    serde_yaml::from_str(&content).map_err(|e| Error::UnexpectedError(e.to_string()))
+}
+
+pub async fn save_history(history: WallpaperHistory) -> Result<()> {
+    let content = serde_yaml::to_string(&history).map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to serialize content.");
+        Error::UnexpectedError(e.to_string())
+    })?;
+    let path = get_history_path()?;
+    if let Some(folder) = path.parent() {
+        create_dir_all(folder)
+            .await
+            .map_err(|_| Error::FailedToWriteFile(folder.into()))?;
+    }
+    write_atomic(&path, content).await.map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to write file.");
+        Error::FailedToWriteFile(path.clone())
+    })?;
+    Ok(())
+}
+
+/// How a wallpaper is applied, for the hooks
+#[derive(Clone, Debug, Default)]
+pub struct ApplyOptions {
+    pub source: ApplySource,
+    /// Dominant colors of the image, from the index
+    pub colors: Vec<Rgb>,
+    pub hooks: Hooks,
+}
+
+/// Sets the image as wallpaper and adds it to the history, running the hooks
+/// before and after. Failing to update the history doesn't fail the apply.
+pub async fn apply_wallpaper(path: PathBuf, options: ApplyOptions) -> Result<PathBuf> {
+    let env = HookEnv {
+        path: extract_if_archived(path.clone()).await?,
+        previous: load_regolith_config().await.ok(),
+        source: options.source,
+        colors: options.colors,
+    };
+    run_pre_apply_hooks(&options.hooks, &env).await?;
+    let applied = set_wallpaper_on_config(env.path.clone()).await?;
+    let applied_at = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+    let result = match load_history() {
+        Ok(mut history) => {
+            history.push(path, applied_at);
+            save_history(history).await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        tracing::warn!(error.message=%e, "Failed to update the history.");
+    }
+    run_post_apply_hooks(&options.hooks, &env).await;
+    Ok(applied)
+}
+
+/// Sets the wallpaper through the running GUI or daemon if there is one (so
+/// they don't both write the Regolith config), or else applies it here
+pub async fn apply_or_forward(path: PathBuf, options: ApplyOptions) -> Result<PathBuf> {
+    match send_command(IpcCommand::Set { path: path.clone() }).await? {
+        Some(response) if !response.ok => Err(Error::IpcFailed(response.message)),
+        Some(_) => Ok(path),
+        None => apply_wallpaper(path, options).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_keeps_the_latest_wallpapers() {
+        let mut history = WallpaperHistory::default();
+        for i in 0..MAX_HISTORY + 5 {
+            history.push(PathBuf::from(format!("{}.png", i)), String::new());
+        }
+        assert_eq!(history.entries.len(), MAX_HISTORY);
+        assert_eq!(history.entries[0].path, PathBuf::from("5.png"));
+        assert_eq!(
+            history.last().map(|entry| entry.path.clone()),
+            Some(PathBuf::from(format!("{}.png", MAX_HISTORY + 4)))
+        );
+    }
+}
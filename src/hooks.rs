@@ -0,0 +1,187 @@
+use crate::{to_hex, Error, Result, Rgb};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Seconds a hook can run before being killed
+pub const DEFAULT_HOOK_TIMEOUT: u64 = 30;
+
+/// Shell command run when a wallpaper is applied, with the environment
+/// variables set by [`HookEnv`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hook {
+    pub command: String,
+    /// Seconds before the command is killed, [`DEFAULT_HOOK_TIMEOUT`] if not set
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// Cancel the change if this pre-apply hook fails (or times out)
+    #[serde(default)]
+    pub veto: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hooks {
+    #[serde(default)]
+    pub pre_apply: Vec<Hook>,
+    #[serde(default)]
+    pub post_apply: Vec<Hook>,
+}
+
+/// What applied the wallpaper
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ApplySource {
+    Gui,
+    #[default]
+    Cli,
+    Daemon,
+    Tui,
+}
+
+impl fmt::Display for ApplySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = match self {
+            ApplySource::Gui => "gui",
+            ApplySource::Cli => "cli",
+            ApplySource::Daemon => "daemon",
+            ApplySource::Tui => "tui",
+        };
+        write!(f, "{}", source)
+    }
+}
+
+/// Environment variables of the hooks
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HookEnv {
+    /// `REGOLITH_WALLPAPER`
+    pub path: PathBuf,
+    /// `REGOLITH_WALLPAPER_PREVIOUS`, empty if unknown
+    pub previous: Option<PathBuf>,
+    /// `REGOLITH_WALLPAPER_SOURCE`: `gui`, `cli`, `daemon` or `tui`
+    pub source: ApplySource,
+    /// `REGOLITH_WALLPAPER_COLORS`: dominant colors, e.g.: `#1e90ff #202020`
+    pub colors: Vec<Rgb>,
+}
+
+impl HookEnv {
+    fn vars(&self) -> Vec<(&'static str, String)> {
+        let path = |path: &Path| path.to_string_lossy().to_string();
+        vec![
+            ("REGOLITH_WALLPAPER", path(&self.path)),
+            (
+                "REGOLITH_WALLPAPER_PREVIOUS",
+                self.previous.as_deref().map(path).unwrap_or_default(),
+            ),
+            ("REGOLITH_WALLPAPER_SOURCE", self.source.to_string()),
+            (
+                "REGOLITH_WALLPAPER_COLORS",
+                self.colors.iter().map(to_hex).collect::<Vec<_>>().join(" "),
+            ),
+        ]
+    }
+}
+
+/// Runs the hook with `sh -c`, logging its output. Fails if it exits with an
+/// error or doesn't finish in time (it is killed then).
+pub async fn run_hook(hook: &Hook, env: &HookEnv) -> Result<()> {
+    let timeout = Duration::from_secs(hook.timeout.unwrap_or(DEFAULT_HOOK_TIMEOUT));
+    let child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(&hook.command)
+        .envs(env.vars())
+        .kill_on_drop(true)
+        .output();
+    let output = match tokio::time::timeout(timeout, child).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => {
+            tracing::error!(error.cause_chain=?e, error.message=%e, hook.command, "Failed to run hook.");
+            return Err(Error::HookFailed(hook.command.clone(), e.to_string()));
+        }
+        Err(_) => {
+            tracing::error!(hook.command, ?timeout, "Hook timed out.");
+            return Err(Error::HookFailed(
+                hook.command.clone(),
+                format!("timed out after {:?}", timeout),
+            ));
+        }
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    tracing::info!(hook.command, %output.status, %stdout, %stderr, "Hook finished.");
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(Error::HookFailed(
+            hook.command.clone(),
+            output.status.to_string(),
+        ))
+    }
+}
+
+/// Runs the pre-apply hooks in order. A failing hook with `veto` cancels the
+/// change, the others are only logged.
+pub async fn run_pre_apply_hooks(hooks: &Hooks, env: &HookEnv) -> Result<()> {
+    for hook in &hooks.pre_apply {
+        match run_hook(hook, env).await {
+            Ok(()) => {}
+            Err(e) if hook.veto => return Err(Error::WallpaperVetoed(Box::new(e))),
+            Err(e) => tracing::warn!(error.message=%e, "Pre-apply hook failed."),
+        }
+    }
+    Ok(())
+}
+
+/// Runs the post-apply hooks in order, failures are only logged
+pub async fn run_post_apply_hooks(hooks: &Hooks, env: &HookEnv) {
+    for hook in &hooks.post_apply {
+        if let Err(e) = run_hook(hook, env).await {
+            tracing::warn!(error.message=%e, "Post-apply hook failed.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(command: &str, veto: bool) -> Hook {
+        Hook {
+            command: command.into(),
+            timeout: Some(1),
+            veto,
+        }
+    }
+
+    #[tokio::test]
+    async fn hooks_get_the_env_and_can_veto() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("hook.out");
+        let env = HookEnv {
+            path: PathBuf::from("/wp/moon.png"),
+            previous: None,
+            source: ApplySource::Daemon,
+            colors: vec![[30, 144, 255], [0, 0, 0]],
+        };
+        let write_env = format!(
+            "echo \"$REGOLITH_WALLPAPER|$REGOLITH_WALLPAPER_PREVIOUS|$REGOLITH_WALLPAPER_SOURCE|$REGOLITH_WALLPAPER_COLORS\" > {}",
+            out.display()
+        );
+        run_hook(&hook(&write_env, false), &env).await.unwrap();
+        let written = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(written, "/wp/moon.png||daemon|#1e90ff #000000\n");
+
+        let failing = Hooks {
+            pre_apply: vec![hook("exit 3", false)],
+            ..Default::default()
+        };
+        assert!(run_pre_apply_hooks(&failing, &env).await.is_ok());
+        let vetoing = Hooks {
+            pre_apply: vec![hook("sleep 5", true)],
+            ..Default::default()
+        };
+        let started = std::time::Instant::now();
+        let result = run_pre_apply_hooks(&vetoing, &env).await;
+        assert!(matches!(result, Err(Error::WallpaperVetoed(_))));
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+}
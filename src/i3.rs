@@ -0,0 +1,351 @@
+use crate::{expand_home_dir, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::sync::mpsc;
+
+/// Every i3 IPC message starts with it, followed by the payload length and the
+/// message type (native endian `u32`s) and the JSON payload
+const I3_MAGIC: &[u8; 6] = b"i3-ipc";
+pub const I3_GET_WORKSPACES: u32 = 1;
+pub const I3_SUBSCRIBE: u32 = 2;
+/// Events have the highest bit of the type set
+pub const I3_EVENT_WORKSPACE: u32 = 0x8000_0000;
+/// Bigger payloads are refused instead of allocated
+const I3_MAX_PAYLOAD: u32 = 16 * 1024 * 1024;
+
+/// Delays between the attempts to connect to i3 again (e.g. while it
+/// restarts), doubled after each failed one
+const I3_RECONNECT_MIN_DELAY: Duration = Duration::from_secs(1);
+const I3_RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Milliseconds to wait after a workspace change before applying its
+/// wallpaper, so switching quickly through workspaces applies only the last one
+pub const DEFAULT_WORKSPACE_DEBOUNCE: u64 = 300;
+
+/// Wallpaper of the workspaces whose name matches the pattern
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceRule {
+    /// Workspace name, `*` and `?` match any characters (e.g.: `2: *`)
+    pub workspace: String,
+    pub wallpaper: PathBuf,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceWallpapers {
+    /// Checked in order, the first match wins
+    #[serde(default)]
+    pub rules: Vec<WorkspaceRule>,
+    /// Milliseconds, [`DEFAULT_WORKSPACE_DEBOUNCE`] if not set
+    #[serde(default)]
+    pub debounce: Option<u64>,
+}
+
+impl WorkspaceWallpapers {
+    /// Wallpaper mapped to the workspace, if any
+    pub fn wallpaper(&self, workspace: &str) -> Option<PathBuf> {
+        self.rules
+            .iter()
+            .find(|rule| glob_match(&rule.workspace, workspace))
+            .map(|rule| expand_home_dir(rule.wallpaper.to_string_lossy()))
+    }
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any characters and `?`
+/// a single one
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    // Positions after the last `*` seen on both, to backtrack to
+    let (mut p, mut n, mut star) = (0, 0, None);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Workspace {
+    pub name: String,
+    #[serde(default)]
+    pub focused: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkspaceEvent {
+    change: String,
+    current: Option<Workspace>,
+}
+
+fn i3_error(e: impl std::fmt::Display) -> Error {
+    tracing::error!(error.message=%e, "i3 IPC failed.");
+    Error::I3IpcFailed(e.to_string())
+}
+
+pub fn encode_i3_message(kind: u32, payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(14 + payload.len());
+    message.extend_from_slice(I3_MAGIC);
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&kind.to_ne_bytes());
+    message.extend_from_slice(payload);
+    message
+}
+
+/// Socket of the running i3 (or sway): `$I3SOCK`, `$SWAYSOCK`, or else asked
+/// to `i3 --get-socketpath`
+pub async fn get_i3_socket_path() -> Result<PathBuf> {
+    for var in ["I3SOCK", "SWAYSOCK"] {
+        if let Some(path) = std::env::var_os(var).filter(|path| !path.is_empty()) {
+            return Ok(PathBuf::from(path));
+        }
+    }
+    let output = tokio::process::Command::new("i3")
+        .arg("--get-socketpath")
+        .output()
+        .await
+        .map_err(i3_error)?;
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || path.is_empty() {
+        return Err(Error::I3IpcFailed("i3 socket not found".into()));
+    }
+    Ok(PathBuf::from(path))
+}
+
+/// Client of the i3/sway IPC
+pub struct I3Client {
+    stream: UnixStream,
+}
+
+impl I3Client {
+    pub async fn connect(path: &Path) -> Result<Self> {
+        let stream = UnixStream::connect(path).await.map_err(i3_error)?;
+        Ok(Self { stream })
+    }
+
+    async fn send(&mut self, kind: u32, payload: &[u8]) -> Result<()> {
+        self.stream
+            .write_all(&encode_i3_message(kind, payload))
+            .await
+            .map_err(i3_error)
+    }
+
+    /// Next message (a reply or an event): its type and payload
+    pub async fn read(&mut self) -> Result<(u32, Vec<u8>)> {
+        let mut header = [0; 14];
+        self.stream
+            .read_exact(&mut header)
+            .await
+            .map_err(i3_error)?;
+        if &header[..6] != I3_MAGIC {
+            return Err(Error::I3IpcFailed("invalid message".into()));
+        }
+        let len = u32::from_ne_bytes(header[6..10].try_into().unwrap_or_default());
+        let kind = u32::from_ne_bytes(header[10..14].try_into().unwrap_or_default());
+        if len > I3_MAX_PAYLOAD {
+            return Err(Error::I3IpcFailed(format!(
+                "message too big ({} bytes)",
+                len
+            )));
+        }
+        let mut payload = vec![0; len as usize];
+        self.stream
+            .read_exact(&mut payload)
+            .await
+            .map_err(i3_error)?;
+        Ok((kind, payload))
+    }
+
+    /// Reply to the last request, skipping the events sent meanwhile
+    async fn reply(&mut self, kind: u32) -> Result<Vec<u8>> {
+        loop {
+            let (reply_kind, payload) = self.read().await?;
+            if reply_kind == kind {
+                return Ok(payload);
+            }
+        }
+    }
+
+    pub async fn get_workspaces(&mut self) -> Result<Vec<Workspace>> {
+        self.send(I3_GET_WORKSPACES, b"").await?;
+        let payload = self.reply(I3_GET_WORKSPACES).await?;
+        serde_json::from_slice(&payload).map_err(i3_error)
+    }
+
+    pub async fn subscribe(&mut self, events: &[&str]) -> Result<()> {
+        let payload = serde_json::to_vec(events).map_err(i3_error)?;
+        self.send(I3_SUBSCRIBE, &payload).await?;
+        let reply: serde_json::Value =
+            serde_json::from_slice(&self.reply(I3_SUBSCRIBE).await?).map_err(i3_error)?;
+        if reply["success"] != true {
+            return Err(Error::I3IpcFailed(format!(
+                "subscription refused: {}",
+                reply
+            )));
+        }
+        Ok(())
+    }
+
+    /// Name of the next focused workspace, once subscribed to `workspace`
+    /// events
+    pub async fn next_focused_workspace(&mut self) -> Result<String> {
+        loop {
+            let (kind, payload) = self.read().await?;
+            if kind != I3_EVENT_WORKSPACE {
+                continue;
+            }
+            let event: WorkspaceEvent = serde_json::from_slice(&payload).map_err(i3_error)?;
+            if let Some(current) = event.current.filter(|_| event.change == "focus") {
+                return Ok(current.name);
+            }
+        }
+    }
+}
+
+/// Sends the name of the focused workspace, first the current one and then
+/// each time the focus changes. The connection to i3 is opened again when lost
+/// (e.g. when i3 restarts). Stops when the receiver is dropped.
+pub fn watch_workspaces(path: PathBuf) -> mpsc::Receiver<String> {
+    let (tx, rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let mut delay = I3_RECONNECT_MIN_DELAY;
+        loop {
+            let mut connected = false;
+            tokio::select! {
+                _ = tx.closed() => return,
+                result = forward_workspaces(&path, &tx, &mut connected) => {
+                    if let Err(e) = result {
+                        tracing::warn!(error.message=%e, ?delay, "Lost the connection to i3, retrying.");
+                    }
+                }
+            }
+            delay = match connected {
+                true => I3_RECONNECT_MIN_DELAY,
+                false => (delay * 2).min(I3_RECONNECT_MAX_DELAY),
+            };
+            tokio::time::sleep(delay).await;
+        }
+    });
+    rx
+}
+
+/// Sends the focused workspaces until the connection to i3 is lost
+async fn forward_workspaces(
+    path: &Path,
+    tx: &mpsc::Sender<String>,
+    connected: &mut bool,
+) -> Result<()> {
+    let mut client = I3Client::connect(path).await?;
+    let focused = client
+        .get_workspaces()
+        .await?
+        .into_iter()
+        .find(|workspace| workspace.focused);
+    client.subscribe(&["workspace"]).await?;
+    *connected = true;
+    if let Some(workspace) = focused {
+        let _ = tx.send(workspace.name).await;
+    }
+    loop {
+        let name = client.next_focused_workspace().await?;
+        let _ = tx.send(name).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::UnixListener;
+
+    #[test]
+    fn workspaces_are_matched_by_pattern() {
+        assert!(glob_match("1", "1"));
+        assert!(!glob_match("1", "10"));
+        assert!(glob_match("2: *", "2: web"));
+        assert!(glob_match("*web*", "3: web dev"));
+        assert!(glob_match("?", "4"));
+        assert!(!glob_match("?", "42"));
+        let workspaces = WorkspaceWallpapers {
+            rules: vec![
+                WorkspaceRule {
+                    workspace: "1*".into(),
+                    wallpaper: "/wp/one.png".into(),
+                },
+                WorkspaceRule {
+                    workspace: "*".into(),
+                    wallpaper: "/wp/other.png".into(),
+                },
+            ],
+            debounce: None,
+        };
+        assert_eq!(workspaces.wallpaper("1: term"), Some("/wp/one.png".into()));
+        assert_eq!(workspaces.wallpaper("9"), Some("/wp/other.png".into()));
+    }
+
+    /// Fake i3 answering the workspaces and the subscription, then sending
+    /// workspace events and closing the connection
+    async fn fake_i3(listener: &UnixListener) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut i3 = I3Client { stream };
+        let (kind, _) = i3.read().await.unwrap();
+        assert_eq!(kind, I3_GET_WORKSPACES);
+        let workspaces = br#"[{"name":"1","focused":false},{"name":"2: web","focused":true}]"#;
+        i3.send(I3_GET_WORKSPACES, workspaces).await.unwrap();
+        let (kind, payload) = i3.read().await.unwrap();
+        assert_eq!(
+            (kind, payload.as_slice()),
+            (I3_SUBSCRIBE, &br#"["workspace"]"#[..])
+        );
+        i3.send(I3_SUBSCRIBE, br#"{"success":true}"#).await.unwrap();
+        for event in [
+            &br#"{"change":"init","current":{"name":"3"}}"#[..],
+            br#"{"change":"focus","current":{"name":"3"},"old":{"name":"2: web"}}"#,
+        ] {
+            i3.send(I3_EVENT_WORKSPACE, event).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn workspace_focus_is_watched() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("i3.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let mut workspaces = watch_workspaces(path.clone());
+        fake_i3(&listener).await;
+        assert_eq!(workspaces.recv().await.as_deref(), Some("2: web"));
+        assert_eq!(workspaces.recv().await.as_deref(), Some("3"));
+        // As if i3 restarted
+        fake_i3(&listener).await;
+        assert_eq!(workspaces.recv().await.as_deref(), Some("2: web"));
+        assert_eq!(workspaces.recv().await.as_deref(), Some("3"));
+    }
+
+    #[tokio::test]
+    async fn big_messages_are_refused() {
+        let (client, mut server) = UnixStream::pair().unwrap();
+        let mut header = I3_MAGIC.to_vec();
+        header.extend(u32::MAX.to_ne_bytes());
+        header.extend(I3_EVENT_WORKSPACE.to_ne_bytes());
+        server.write_all(&header).await.unwrap();
+        let mut client = I3Client { stream: client };
+        assert!(client.read().await.is_err());
+    }
+}
@@ -0,0 +1,456 @@
+use crate::{
+    archive_stem, for_each_image, is_archive, load_image_files, load_index, move_file, Error,
+    Resolution, Result,
+};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{io::Reader, DynamicImage, ImageFormat};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Cursor};
+use std::path::{Path, PathBuf};
+use tokio::fs::{copy, create_dir_all, read, remove_file};
+
+/// Quality of the JPEG files written when converting or downscaling
+const JPEG_QUALITY: u8 = 90;
+
+/// Whether imported files are copied or moved into the library
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImportMode {
+    #[default]
+    Copy,
+    Move,
+}
+
+impl ImportMode {
+    pub const ALL: [ImportMode; 2] = [ImportMode::Copy, ImportMode::Move];
+}
+
+impl fmt::Display for ImportMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportMode::Copy => write!(f, "Copy"),
+            ImportMode::Move => write!(f, "Move"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    pub mode: ImportMode,
+    /// Convert formats other than PNG and JPEG (to PNG if they have
+    /// transparency, or else to JPEG)
+    pub convert: bool,
+    /// Downscale larger images to fit inside this resolution
+    pub max_resolution: Option<Resolution>,
+    /// Sub-folder the images of archives are extracted to, the archive name
+    /// by default
+    pub collection: Option<String>,
+}
+
+/// Image validated by decoding it, re-encoded when it has to be converted or
+/// downscaled
+struct PreparedImport {
+    format: ImageFormat,
+    image: Option<DynamicImage>,
+}
+
+impl PreparedImport {
+    /// Free path on `folder` for the image, with the extension of its format
+    fn destination(&self, path: &Path, folder: &Path) -> Result<PathBuf> {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .ok_or(Error::InvalidPath(path.to_path_buf()))?;
+        Ok(unique_path(folder, &stem, self.format.extensions_str()[0]))
+    }
+}
+
+/// Format the image is written with when it has to be re-encoded
+fn target_format(format: ImageFormat, image: &DynamicImage) -> ImageFormat {
+    match format {
+        ImageFormat::Png | ImageFormat::Jpeg => format,
+        _ if image.color().has_alpha() => ImageFormat::Png,
+        _ => ImageFormat::Jpeg,
+    }
+}
+
+/// Decodes the image (guessing the format from its content), converting or
+/// downscaling it if needed
+fn prepare_import(bytes: &[u8], path: &Path, options: &ImportOptions) -> Result<PreparedImport> {
+    let reader = Reader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|_| Error::FailedToRead(path.to_path_buf()))?;
+    let format = reader
+        .format()
+        .ok_or(Error::UnsupportedImage(path.to_path_buf()))?;
+    let image = reader
+        .decode()
+        .map_err(|_| Error::UnsupportedImage(path.to_path_buf()))?;
+    let too_large = options
+        .max_resolution
+        .is_some_and(|max| image.width() > max.width || image.height() > max.height);
+    let convert = options.convert && target_format(format, &image) != format;
+    if !too_large && !convert {
+        return Ok(PreparedImport {
+            format,
+            image: None,
+        });
+    }
+    let format = target_format(format, &image);
+    let image = match options.max_resolution {
+        Some(max) if too_large => image.resize(max.width, max.height, FilterType::Lanczos3),
+        _ => image,
+    };
+    Ok(PreparedImport {
+        format,
+        image: Some(image),
+    })
+}
+
+fn save_image(image: &DynamicImage, format: ImageFormat, path: &Path) -> Result<()> {
+    let result = match format {
+        ImageFormat::Jpeg => File::create(path)
+            .map_err(image::ImageError::IoError)
+            .and_then(|file| {
+                JpegEncoder::new_with_quality(BufWriter::new(file), JPEG_QUALITY)
+                    .encode_image(&image.to_rgb8())
+            }),
+        _ => image.save_with_format(path, format),
+    };
+    result.map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to save image.");
+        Error::FailedToWriteFile(path.to_path_buf())
+    })
+}
+
+/// First free path on `folder` for a file named `stem.extension`, adding a
+/// number on conflicts: `stem (1).extension`, `stem (2).extension`...
+pub fn unique_path(folder: &Path, stem: &str, extension: &str) -> PathBuf {
+    let path = folder.join(format!("{}.{}", stem, extension));
+    if !path.exists() {
+        return path;
+    }
+    (1..)
+        .map(|n| folder.join(format!("{} ({}).{}", stem, n, extension)))
+        .find(|path| !path.exists())
+        .expect("Some numbered name is free")
+}
+
+/// Imports an image into `folder`, returns its path on the library. The file
+/// extension is fixed if it doesn't match the content.
+#[tracing::instrument(skip(options))]
+pub async fn import_file(
+    path: PathBuf,
+    folder: PathBuf,
+    options: ImportOptions,
+) -> Result<PathBuf> {
+    let bytes = read(&path)
+        .await
+        .map_err(|_| Error::FailedToRead(path.clone()))?;
+    let prepared = {
+        let path = path.clone();
+        let options = options.clone();
+        tokio::task::spawn_blocking(move || prepare_import(&bytes, &path, &options))
+            .await
+            .map_err(|e| Error::UnexpectedError(e.to_string()))??
+    };
+    let to = prepared.destination(&path, &folder)?;
+    match prepared.image {
+        Some(image) => {
+            let to = to.clone();
+            tokio::task::spawn_blocking(move || save_image(&image, prepared.format, &to))
+                .await
+                .map_err(|e| Error::UnexpectedError(e.to_string()))??;
+            if options.mode == ImportMode::Move {
+                remove_file(&path)
+                    .await
+                    .map_err(|_| Error::FailedToMove(path.clone()))?;
+            }
+        }
+        None => {
+            let result = match options.mode {
+                ImportMode::Copy => copy(&path, &to).await.map(|_| ()),
+                ImportMode::Move => move_file(&path, &to).await,
+            };
+            result.map_err(|e| {
+                tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to import file.");
+                Error::FailedToWriteFile(to.clone())
+            })?;
+        }
+    }
+    tracing::info!(?to, "Image imported.");
+    Ok(to)
+}
+
+/// Writes an image read from an archive (or downloaded) into `folder`
+pub fn import_bytes(
+    bytes: &[u8],
+    name: &Path,
+    folder: &Path,
+    options: &ImportOptions,
+) -> Result<PathBuf> {
+    let prepared = prepare_import(bytes, name, options)?;
+    let to = prepared.destination(name, folder)?;
+    match &prepared.image {
+        Some(image) => save_image(image, prepared.format, &to)?,
+        None => std::fs::write(&to, bytes).map_err(|_| Error::FailedToWriteFile(to.clone()))?,
+    }
+    Ok(to)
+}
+
+/// Folder of a collection inside `folder`. The name has to be a single folder
+/// name, so the images can't end up outside of the library.
+pub fn collection_folder(folder: &Path, name: &str) -> Result<PathBuf> {
+    let name = name.trim();
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(Error::InvalidFileName(name.to_string()));
+    }
+    Ok(folder.join(name))
+}
+
+/// Extracts the images of the archive into its collection folder inside
+/// `folder`, skipping the ones whose content is on `known` (content hash to
+/// path)
+fn import_archive(
+    archive: &Path,
+    folder: &Path,
+    options: &ImportOptions,
+    known: &mut HashMap<String, PathBuf>,
+) -> Vec<(PathBuf, Result<PathBuf>)> {
+    let collection = options
+        .collection
+        .clone()
+        .unwrap_or_else(|| archive_stem(archive));
+    let folder = match collection_folder(folder, &collection) {
+        Ok(folder) => folder,
+        Err(e) => return vec![(archive.to_path_buf(), Err(e))],
+    };
+    let mut results = Vec::new();
+    let extracted = for_each_image(archive, |name, bytes| {
+        let hash = blake3::hash(&bytes).to_hex().to_string();
+        let result = match known.get(&hash) {
+            Some(existing) => Err(Error::DuplicateImage(existing.clone())),
+            // The folder is only created if some image is new
+            None => std::fs::create_dir_all(&folder)
+                .map_err(|_| Error::FailedToWriteFile(folder.clone()))
+                .and_then(|_| import_bytes(&bytes, &name, &folder, options)),
+        };
+        if let Ok(to) = &result {
+            known.insert(hash, to.clone());
+        }
+        results.push((archive.join(name), result));
+        true
+    });
+    if let Err(e) = extracted {
+        results.push((archive.to_path_buf(), Err(e)));
+    }
+    results
+}
+
+/// Imports the files, the images inside the folders (on the same sub-folders
+/// inside `folder`, as they are used as tags) and the images inside archives
+/// (on a sub-folder per archive, skipping the ones already on the library).
+/// Returns the new path of each file.
+pub async fn import_files(
+    paths: Vec<PathBuf>,
+    folder: PathBuf,
+    options: ImportOptions,
+) -> Vec<(PathBuf, Result<PathBuf>)> {
+    // Files with the folder they go to
+    let mut files = Vec::new();
+    let mut archives = Vec::new();
+    let mut results = Vec::new();
+    for path in paths {
+        if is_archive(&path) {
+            archives.push(path);
+        } else if path.is_dir() {
+            match load_image_files(path.clone()).await {
+                Ok(images) => files.extend(images.into_iter().map(|image| {
+                    let sub_folder = image
+                        .parent()
+                        .and_then(|parent| parent.strip_prefix(&path).ok())
+                        .unwrap_or(Path::new(""));
+                    (folder.join(sub_folder), image)
+                })),
+                Err(e) => results.push((path, Err(e))),
+            }
+        } else {
+            files.push((folder.clone(), path));
+        }
+    }
+    if let Err(e) = create_dir_all(&folder).await {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?folder, "Failed to create folder.");
+        results.extend(
+            files
+                .into_iter()
+                .map(|(_, path)| (path, Err(Error::FailedToWriteFile(folder.clone())))),
+        );
+        return results;
+    }
+    for (to, path) in files {
+        let result = if path.starts_with(&folder) && options.mode == ImportMode::Move {
+            // Already on the library
+            Ok(path.clone())
+        } else if let Err(e) = create_dir_all(&to).await {
+            tracing::error!(error.cause_chain=?e, error.message=%e, ?to, "Failed to create folder.");
+            Err(Error::FailedToWriteFile(to))
+        } else {
+            import_file(path.clone(), to, options.clone()).await
+        };
+        results.push((path, result));
+    }
+    if !archives.is_empty() {
+        let task = tokio::task::spawn_blocking(move || {
+            // Content hash of the images on the library
+            let mut known = load_index()
+                .map(|index| {
+                    index
+                        .entries
+                        .into_values()
+                        .map(|entry| (entry.hash, entry.path))
+                        .collect::<HashMap<_, _>>()
+                })
+                .unwrap_or_default();
+            archives
+                .iter()
+                .flat_map(|archive| import_archive(archive, &folder, &options, &mut known))
+                .collect::<Vec<_>>()
+        });
+        match task.await {
+            Ok(extracted) => results.extend(extracted),
+            Err(e) => tracing::error!(error.cause_chain=?e, error.message=%e, "Task failed."),
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+    use tempfile::TempDir;
+
+    /// Temporary `source` and `library` folders, removed when dropped
+    fn folders() -> (TempDir, PathBuf, PathBuf) {
+        let root = tempfile::tempdir().unwrap();
+        let (source, library) = (root.path().join("source"), root.path().join("library"));
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::create_dir_all(&library).unwrap();
+        (root, source, library)
+    }
+
+    fn png(color: [u8; 3]) -> Vec<u8> {
+        let mut png = Vec::new();
+        RgbImage::from_pixel(8, 8, Rgb(color))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    async fn import_one(path: PathBuf, library: &Path, options: ImportOptions) -> Result<PathBuf> {
+        let mut results = import_files(vec![path], library.to_path_buf(), options).await;
+        assert_eq!(results.len(), 1);
+        results.remove(0).1
+    }
+
+    #[tokio::test]
+    async fn large_images_are_converted_and_downscaled() {
+        let (_root, source, library) = folders();
+        let path = source.join("big.bmp");
+        RgbImage::from_pixel(400, 200, Rgb([10, 20, 30]))
+            .save_with_format(&path, ImageFormat::Bmp)
+            .unwrap();
+        let options = ImportOptions {
+            convert: true,
+            max_resolution: Some(Resolution::new(100, 100)),
+            ..Default::default()
+        };
+        let big = import_one(path, &library, options).await.unwrap();
+        assert_eq!(big, library.join("big.jpg"));
+        assert_eq!(image::image_dimensions(&big).unwrap(), (100, 50));
+    }
+
+    #[tokio::test]
+    async fn extensions_are_fixed_without_overwriting() {
+        let (_root, source, library) = folders();
+        // A PNG with the wrong extension
+        let path = source.join("named.jpg");
+        std::fs::write(&path, png([0, 0, 0])).unwrap();
+        std::fs::write(library.join("named.png"), b"taken").unwrap();
+        let named = import_one(path, &library, ImportOptions::default()).await;
+        assert_eq!(named.unwrap(), library.join("named (1).png"));
+    }
+
+    #[tokio::test]
+    async fn files_that_are_not_images_are_rejected() {
+        let (_root, source, library) = folders();
+        let path = source.join("notes.png");
+        std::fs::write(&path, b"not an image").unwrap();
+        let notes = import_one(path, &library, ImportOptions::default()).await;
+        assert!(matches!(notes, Err(Error::UnsupportedImage(_))));
+    }
+
+    #[tokio::test]
+    async fn folders_keep_their_sub_folders() {
+        let (_root, source, library) = folders();
+        std::fs::create_dir_all(source.join("space/nebula")).unwrap();
+        std::fs::write(source.join("space/nebula/red.png"), png([200, 0, 0])).unwrap();
+        std::fs::write(source.join("blue.png"), png([0, 0, 200])).unwrap();
+        let results = import_files(vec![source], library.clone(), ImportOptions::default()).await;
+        let mut paths = results
+            .into_iter()
+            .map(|(_, result)| result.unwrap())
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                library.join("blue.png"),
+                library.join("space/nebula/red.png")
+            ]
+        );
+    }
+
+    fn zip_archive(path: &Path, names: &[&str]) {
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        for name in names {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            std::io::Write::write_all(&mut zip, &png([200, 0, 0])).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn archives_are_extracted_without_duplicates() {
+        let (_root, source, library) = folders();
+        let archive = source.join("Space Pack.zip");
+        zip_archive(&archive, &["a/red.png", "b/red copy.png", "notes.txt"]);
+        let results = import_files(vec![archive], library.clone(), ImportOptions::default()).await;
+        assert_eq!(results.len(), 2);
+        let red = library.join("Space Pack").join("red.png");
+        assert_eq!(results[0].1.as_ref().unwrap(), &red);
+        assert!(matches!(&results[1].1, Err(Error::DuplicateImage(path)) if path == &red));
+    }
+
+    #[tokio::test]
+    async fn collections_stay_inside_the_library() {
+        let (_root, source, library) = folders();
+        let archive = source.join("pack.zip");
+        zip_archive(&archive, &["red.png"]);
+        for name in ["..", "../outside", "/tmp/outside", " "] {
+            let options = ImportOptions {
+                collection: Some(name.into()),
+                ..Default::default()
+            };
+            let result = import_one(archive.clone(), &library, options).await;
+            assert!(matches!(result, Err(Error::InvalidFileName(_))), "{}", name);
+        }
+        assert_eq!(
+            collection_folder(&library, " space ").unwrap(),
+            library.join("space")
+        );
+    }
+}
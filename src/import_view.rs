@@ -0,0 +1,141 @@
+use crate::{Destination, ImportMode, ImportOptions, Message, Resolution};
+use iced::widget::{button, checkbox, pick_list, row, text};
+use iced::{theme, Alignment, Element};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub enum ImportMessage {
+    DestinationSelected(Destination),
+    ModeSelected(ImportMode),
+    ConvertToogle(bool),
+    CapToogle(bool),
+    Confirm,
+    Cancel,
+}
+
+/// Files dropped on the window, waiting for the destination and options
+/// before being imported
+#[derive(Default)]
+pub struct Import {
+    paths: Vec<PathBuf>,
+    destinations: Vec<Destination>,
+    destination: Option<Destination>,
+    mode: ImportMode,
+    convert: bool,
+    /// Screen resolution, offered to cap the imported images
+    screen: Option<Resolution>,
+    cap: bool,
+}
+
+impl Import {
+    /// Adds a dropped file or folder, the first destination is selected by
+    /// default
+    pub fn add(
+        &mut self,
+        path: PathBuf,
+        destinations: Vec<Destination>,
+        screen: Option<Resolution>,
+    ) {
+        if self.paths.is_empty() {
+            self.destination = destinations.first().cloned();
+        }
+        if !self.paths.contains(&path) {
+            self.paths.push(path);
+        }
+        self.destinations = destinations;
+        self.screen = screen;
+    }
+
+    pub fn update(&mut self, message: ImportMessage) -> Option<Message> {
+        match message {
+            ImportMessage::DestinationSelected(destination) => {
+                self.destination = Some(destination);
+                None
+            }
+            ImportMessage::ModeSelected(mode) => {
+                self.mode = mode;
+                None
+            }
+            ImportMessage::ConvertToogle(convert) => {
+                self.convert = convert;
+                None
+            }
+            ImportMessage::CapToogle(cap) => {
+                self.cap = cap;
+                None
+            }
+            ImportMessage::Confirm => {
+                let folder = self.destination.take()?.path;
+                let options = ImportOptions {
+                    mode: self.mode,
+                    convert: self.convert,
+                    max_resolution: self.screen.filter(|_| self.cap),
+                    collection: None,
+                };
+                let paths = std::mem::take(&mut self.paths);
+                Some(Message::ImportFiles(paths, folder, options))
+            }
+            ImportMessage::Cancel => {
+                self.paths.clear();
+                None
+            }
+        }
+    }
+
+    /// Import bar, nothing if no files were dropped
+    pub fn view(&self) -> Option<Element<'_, ImportMessage>> {
+        if self.paths.is_empty() {
+            return None;
+        }
+        let label = match self.paths.as_slice() {
+            [path] => format!("Import {} into", path.display()),
+            paths => format!("Import {} files into", paths.len()),
+        };
+        let mut content = row!(
+            text(label).size(14),
+            pick_list(
+                self.destinations.as_slice(),
+                self.destination.clone(),
+                ImportMessage::DestinationSelected
+            )
+            .placeholder("Folder...")
+            .text_size(14)
+            .padding([2, 8]),
+            pick_list(
+                &ImportMode::ALL[..],
+                Some(self.mode),
+                ImportMessage::ModeSelected
+            )
+            .text_size(14)
+            .padding([2, 8]),
+            checkbox(
+                "Convert to PNG/JPEG",
+                self.convert,
+                ImportMessage::ConvertToogle
+            )
+            .size(14)
+            .text_size(14),
+        )
+        .spacing(10)
+        .align_items(Alignment::Center);
+        if let Some(screen) = self.screen {
+            content = content.push(
+                checkbox(
+                    format!("Cap to {}", screen),
+                    self.cap,
+                    ImportMessage::CapToogle,
+                )
+                .size(14)
+                .text_size(14),
+            );
+        }
+        let import_btn = button(text("Import").size(14))
+            .padding([2, 10])
+            .on_press_maybe(self.destination.is_some().then_some(ImportMessage::Confirm));
+        let cancel_btn = button(text("Cancel").size(14))
+            .padding([2, 10])
+            .style(theme::Button::Secondary)
+            .on_press(ImportMessage::Cancel);
+        Some(content.push(import_btn).push(cancel_btn).into())
+    }
+}
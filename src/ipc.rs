@@ -0,0 +1,344 @@
+use crate::{get_cache_dir, Error, PickOptions, Result};
+use directories::BaseDirs;
+#[cfg(feature = "gui")]
+use futures::channel::oneshot;
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "gui")]
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::net::{UnixListener, UnixStream};
+
+/// Version of the JSON-lines protocol, requests with another version are
+/// refused
+pub const IPC_VERSION: u32 = 1;
+
+/// Time given to a client to send its request, so a silent one doesn't block
+/// the next ones
+const IPC_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time given to the running instance to answer a command (applying a
+/// wallpaper refreshes the Regolith look), it is considered wedged after it
+const IPC_ANSWER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Command sent to the running GUI or daemon
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum IpcCommand {
+    /// Change to the next wallpaper (of the slideshow, or a random one)
+    Next,
+    Set {
+        path: PathBuf,
+    },
+    Random(PickOptions),
+    /// Read the config file again
+    Reload,
+    /// Bring the GUI window to the front
+    Focus,
+}
+
+/// One line of JSON, e.g.: `{"version":1,"command":"set","path":"/a.png"}`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IpcRequest {
+    pub version: u32,
+    #[serde(flatten)]
+    pub command: IpcCommand,
+}
+
+/// Answer to a request, one line of JSON
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IpcResponse {
+    pub version: u32,
+    pub ok: bool,
+    #[serde(default)]
+    pub message: String,
+}
+
+impl IpcResponse {
+    pub fn new(result: Result<String>) -> Self {
+        let (ok, message) = match result {
+            Ok(message) => (true, message),
+            Err(e) => (false, e.to_string()),
+        };
+        Self {
+            version: IPC_VERSION,
+            ok,
+            message,
+        }
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/regolith-wallpaper.sock`, or inside the cache folder if
+/// there is no runtime folder
+pub fn get_socket_path() -> Result<PathBuf> {
+    let base_dirs = BaseDirs::new().ok_or(Error::NoHomeDir)?;
+    match base_dirs.runtime_dir() {
+        Some(folder) => Ok(folder.join("regolith-wallpaper.sock")),
+        None => Ok(get_cache_dir()?.join("regolith-wallpaper.sock")),
+    }
+}
+
+fn ipc_error(e: std::io::Error) -> Error {
+    tracing::error!(error.cause_chain=?e, error.message=%e, "IPC failed.");
+    Error::IpcFailed(e.to_string())
+}
+
+/// Sends the command to the running instance, `None` if there is none (or it
+/// doesn't answer in time)
+pub async fn send_command(command: IpcCommand) -> Result<Option<IpcResponse>> {
+    let timeout = match command {
+        IpcCommand::Focus => IPC_READ_TIMEOUT,
+        _ => IPC_ANSWER_TIMEOUT,
+    };
+    send_command_to(&get_socket_path()?, command, timeout).await
+}
+
+async fn send_command_to(
+    path: &Path,
+    command: IpcCommand,
+    timeout: Duration,
+) -> Result<Option<IpcResponse>> {
+    match tokio::time::timeout(timeout, exchange(path, command)).await {
+        Ok(result) => result,
+        Err(_) => {
+            tracing::warn!(
+                ?path,
+                "The running instance didn't answer in time, ignoring it."
+            );
+            Ok(None)
+        }
+    }
+}
+
+async fn exchange(path: &Path, command: IpcCommand) -> Result<Option<IpcResponse>> {
+    let stream = match UnixStream::connect(path).await {
+        Ok(stream) => stream,
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => {
+            return Ok(None);
+        }
+        Err(e) => return Err(ipc_error(e)),
+    };
+    let (reader, mut writer) = stream.into_split();
+    let request = IpcRequest {
+        version: IPC_VERSION,
+        command,
+    };
+    let mut line = serde_json::to_string(&request).map_err(|e| Error::IpcFailed(e.to_string()))?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await.map_err(ipc_error)?;
+    let mut answer = String::new();
+    BufReader::new(reader)
+        .read_line(&mut answer)
+        .await
+        .map_err(ipc_error)?;
+    let response = serde_json::from_str(&answer).map_err(|e| Error::IpcFailed(e.to_string()))?;
+    Ok(Some(response))
+}
+
+/// Listens on the socket, failing if another instance is already listening.
+/// A socket left behind by an instance that crashed is replaced.
+pub async fn bind_socket() -> Result<UnixListener> {
+    let path = get_socket_path()?;
+    if path.exists() {
+        if UnixStream::connect(&path).await.is_ok() {
+            return Err(Error::AlreadyRunning);
+        }
+        tokio::fs::remove_file(&path).await.map_err(ipc_error)?;
+    }
+    if let Some(folder) = path.parent() {
+        tokio::fs::create_dir_all(folder).await.map_err(ipc_error)?;
+    }
+    UnixListener::bind(&path).map_err(ipc_error)
+}
+
+/// Sends the answer to a request
+pub struct IpcResponder {
+    writer: OwnedWriteHalf,
+}
+
+impl IpcResponder {
+    pub async fn respond(mut self, result: Result<String>) {
+        let response = IpcResponse::new(result);
+        let Ok(mut answer) = serde_json::to_string(&response) else {
+            return;
+        };
+        answer.push('\n');
+        if let Err(e) = self.writer.write_all(answer.as_bytes()).await {
+            tracing::warn!(error.message=%e, "Failed to answer IPC request.");
+        }
+    }
+}
+
+/// Waits for the next valid request. Requests that can't be parsed or of
+/// other protocol versions are refused.
+pub async fn accept_request(listener: &UnixListener) -> Result<(IpcCommand, IpcResponder)> {
+    loop {
+        let (stream, _) = listener.accept().await.map_err(ipc_error)?;
+        let (reader, writer) = stream.into_split();
+        let responder = IpcResponder { writer };
+        let mut line = String::new();
+        let mut reader = BufReader::new(reader);
+        match tokio::time::timeout(IPC_READ_TIMEOUT, reader.read_line(&mut line)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => {
+                tracing::warn!(error.message=%e, "Failed to read IPC request.");
+                continue;
+            }
+            Err(_) => {
+                tracing::warn!("No IPC request received in time.");
+                continue;
+            }
+        }
+        let error = match serde_json::from_str::<IpcRequest>(&line) {
+            Ok(request) if request.version == IPC_VERSION => {
+                tracing::info!(?request.command, "IPC command received.");
+                return Ok((request.command, responder));
+            }
+            Ok(request) => format!(
+                "unsupported protocol version {} (expected {})",
+                request.version, IPC_VERSION
+            ),
+            Err(e) => e.to_string(),
+        };
+        responder.respond(Err(Error::IpcFailed(error))).await;
+    }
+}
+
+/// Answer of the GUI to a command, sent back once the command has been handled
+#[cfg(feature = "gui")]
+#[derive(Clone, Debug)]
+pub struct IpcReply(Arc<Mutex<Option<oneshot::Sender<Result<String>>>>>);
+
+#[cfg(feature = "gui")]
+impl IpcReply {
+    /// Only the first answer is sent
+    pub fn send(&self, result: Result<String>) {
+        let sender = self.0.lock().ok().and_then(|mut sender| sender.take());
+        if let Some(sender) = sender {
+            let _ = sender.send(result);
+        }
+    }
+}
+
+/// Sends the commands received on the socket to the GUI, answering them once
+/// the GUI has handled them. Nothing is sent if another instance owns the
+/// socket.
+#[cfg(feature = "gui")]
+pub fn listen_ipc() -> iced::Subscription<(IpcCommand, IpcReply)> {
+    use futures::SinkExt;
+    iced::subscription::channel("ipc", 16, |mut output| async move {
+        let listener = match bind_socket().await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::warn!(error.message=%e, "IPC disabled.");
+                return futures::future::pending().await;
+            }
+        };
+        loop {
+            match accept_request(&listener).await {
+                Ok((command, responder)) => {
+                    let (sender, receiver) = oneshot::channel();
+                    let reply = IpcReply(Arc::new(Mutex::new(Some(sender))));
+                    let result = match output.send((command, reply)).await {
+                        Ok(()) => receiver.await.unwrap_or_else(|_| {
+                            Err(Error::IpcFailed("the command was not handled".into()))
+                        }),
+                        Err(_) => Err(Error::IpcFailed("the window is closing".into())),
+                    };
+                    responder.respond(result).await;
+                }
+                Err(e) => tracing::warn!(error.message=%e, "Failed to accept IPC request."),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_json_lines() {
+        let request = IpcRequest {
+            version: IPC_VERSION,
+            command: IpcCommand::Set {
+                path: PathBuf::from("/wp/moon.png"),
+            },
+        };
+        let line = serde_json::to_string(&request).unwrap();
+        assert_eq!(
+            line,
+            r#"{"version":1,"command":"set","path":"/wp/moon.png"}"#
+        );
+        assert_eq!(serde_json::from_str::<IpcRequest>(&line).unwrap(), request);
+        let random: IpcRequest =
+            serde_json::from_str(r#"{"version":1,"command":"random","collection":"space"}"#)
+                .unwrap();
+        assert_eq!(
+            random.command,
+            IpcCommand::Random(PickOptions {
+                collection: Some("space".into()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn commands_are_answered_by_the_listener() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ipc.sock");
+        assert_eq!(
+            send_command_to(&path, IpcCommand::Next, IPC_ANSWER_TIMEOUT)
+                .await
+                .unwrap(),
+            None
+        );
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = tokio::spawn(async move {
+            for _ in 0..2 {
+                let (command, responder) = accept_request(&listener).await.unwrap();
+                let result = match command {
+                    IpcCommand::Next => Ok("next".to_string()),
+                    _ => Err(Error::AlreadyRunning),
+                };
+                responder.respond(result).await;
+            }
+        });
+        let mut old_client = UnixStream::connect(&path).await.unwrap();
+        old_client
+            .write_all(b"{\"version\":2,\"command\":\"next\"}\n")
+            .await
+            .unwrap();
+        let mut answer = String::new();
+        BufReader::new(old_client)
+            .read_line(&mut answer)
+            .await
+            .unwrap();
+        let next = send_command_to(&path, IpcCommand::Next, IPC_ANSWER_TIMEOUT)
+            .await
+            .unwrap();
+        let reload = send_command_to(&path, IpcCommand::Reload, IPC_ANSWER_TIMEOUT)
+            .await
+            .unwrap();
+        server.await.unwrap();
+        assert_eq!(next, Some(IpcResponse::new(Ok("next".into()))));
+        assert!(reload.is_some_and(|response| !response.ok));
+        assert!(answer.contains("unsupported protocol version 2"));
+    }
+
+    #[tokio::test]
+    async fn wedged_instances_are_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ipc.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        // Accepts the connection but never answers
+        let server = tokio::spawn(async move { listener.accept().await.unwrap() });
+        let timeout = Duration::from_millis(100);
+        let response = send_command_to(&path, IpcCommand::Focus, timeout).await;
+        assert_eq!(response.unwrap(), None);
+        server.await.unwrap();
+    }
+}
@@ -1,18 +1,118 @@
+//! Wallpaper library of Regolith: scanning, indexing and tagging the images,
+//! and applying them through the Regolith Xresources. The iced GUI is behind
+//! the `gui` feature (enabled by default).
+
+#[cfg(feature = "gui")]
 mod app;
+mod archive;
+mod brightness;
+mod colors;
 pub mod commands;
+#[cfg(feature = "gui")]
+mod config_banner;
+mod config_schema;
 mod configuration;
+mod daily;
+mod duplicates;
+#[cfg(feature = "gui")]
+mod duplicates_view;
 mod error;
+#[cfg(feature = "gui")]
+mod file_actions_view;
+mod file_ops;
+#[cfg(feature = "gui")]
+mod gallery;
+mod history;
+mod hooks;
+mod i3;
+mod import;
+#[cfg(feature = "gui")]
+mod import_view;
+mod ipc;
+mod library;
+mod library_index;
+#[cfg(feature = "gui")]
+mod loading_view;
+mod menu;
+#[cfg(feature = "gui")]
+mod online_view;
+mod providers;
+mod regolith;
+mod resolution;
+mod selection;
+mod sources;
+#[cfg(feature = "gui")]
 mod status_bar;
+mod tags;
+#[cfg(feature = "tui")]
+mod terminal_image;
+#[cfg(feature = "gui")]
+mod thumbnail_cache;
+mod trash;
+#[cfg(feature = "tui")]
+mod tui;
 mod utils;
+#[cfg(feature = "gui")]
 mod wallpaper_image;
+#[cfg(feature = "gui")]
 mod wallpaper_path;
+#[cfg(feature = "gui")]
+mod watcher;
 
+#[cfg(feature = "gui")]
 pub use app::*;
+pub use archive::*;
+pub use brightness::*;
+pub use colors::*;
+#[cfg(feature = "gui")]
+pub use config_banner::*;
+pub use config_schema::*;
 pub use configuration::*;
+pub use daily::*;
+pub use duplicates::*;
+#[cfg(feature = "gui")]
+pub use duplicates_view::*;
 pub use error::*;
+#[cfg(feature = "gui")]
+pub use file_actions_view::*;
+pub use file_ops::*;
+#[cfg(feature = "gui")]
+pub use gallery::*;
+pub use history::*;
+pub use hooks::*;
+pub use i3::*;
+pub use import::*;
+#[cfg(feature = "gui")]
+pub use import_view::*;
+pub use ipc::*;
+pub use library::*;
+pub use library_index::*;
+#[cfg(feature = "gui")]
+pub use loading_view::*;
+pub use menu::*;
+#[cfg(feature = "gui")]
+pub use online_view::*;
+pub use providers::*;
+pub use regolith::*;
+pub use resolution::*;
+pub use selection::*;
+pub use sources::*;
+#[cfg(feature = "gui")]
 pub use status_bar::*;
+pub use tags::*;
+#[cfg(feature = "tui")]
+pub use terminal_image::*;
+#[cfg(feature = "gui")]
+pub use thumbnail_cache::*;
+pub use trash::*;
+#[cfg(feature = "tui")]
+pub use tui::*;
 pub use utils::*;
+#[cfg(feature = "gui")]
 pub use wallpaper_image::*;
+#[cfg(feature = "gui")]
 pub use wallpaper_path::*;
+#[cfg(feature = "gui")]
+pub use watcher::*;
 
 pub type Result<T> = std::result::Result<T, Error>;
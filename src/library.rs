@@ -0,0 +1,77 @@
+use crate::{is_archive, list_archive_images, Error, Result};
+use futures::StreamExt;
+use image::ImageFormat;
+use std::path::PathBuf;
+use tokio::fs::read_dir;
+use tokio_stream::wrappers::ReadDirStream;
+
+/// Loads the image files inside `path`, sub-folders included (their names are
+/// used as tags, linked ones are skipped). If `path` is an archive, the paths of the images inside it
+/// are returned (e.g.: `pack.zip/space/moon.jpg`).
+#[tracing::instrument]
+pub async fn load_image_files(path: PathBuf) -> Result<Vec<PathBuf>> {
+    tracing::info!("Loading files...");
+    if is_archive(&path) {
+        let archive = path.clone();
+        let names = tokio::task::spawn_blocking(move || list_archive_images(&archive))
+            .await
+            .map_err(|e| Error::UnexpectedError(e.to_string()))??;
+        tracing::info!("{} images inside the archive.", names.len());
+        return Ok(names.into_iter().map(|name| path.join(name)).collect());
+    }
+    let mut image_files = Vec::new();
+    let mut folders = vec![path];
+    while let Some(folder) = folders.pop() {
+        let entries = ReadDirStream::new(
+            read_dir(folder)
+                .await
+                .map_err(|e| Error::UnexpectedError(format!("Failed to read files {}", e)))?,
+        )
+        .filter_map(|res| async {
+            let entry = res.ok()?;
+            let file_type = entry.file_type().await.ok()?;
+            Some((entry.path(), file_type))
+        })
+        .collect::<Vec<_>>()
+        .await;
+        for (path, file_type) in entries {
+            // Linked folders are skipped, they could point back at a parent
+            if file_type.is_symlink() && path.is_dir() {
+                continue;
+            }
+            if file_type.is_dir() {
+                let hidden = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with('.'));
+                if !hidden {
+                    folders.push(path);
+                }
+            } else if path
+                .extension()
+                .and_then(ImageFormat::from_extension)
+                .is_some()
+            {
+                image_files.push(path);
+            }
+        }
+    }
+    tracing::info!("{} files loaded.", image_files.len());
+    Ok(image_files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn linked_folders_are_skipped() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("space")).unwrap();
+        std::fs::write(root.path().join("space/moon.png"), b"").unwrap();
+        std::fs::write(root.path().join("notes.txt"), b"").unwrap();
+        std::os::unix::fs::symlink(root.path(), root.path().join("space/loop")).unwrap();
+        let files = load_image_files(root.path().to_path_buf()).await.unwrap();
+        assert_eq!(files, vec![root.path().join("space/moon.png")]);
+    }
+}
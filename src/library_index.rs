@@ -0,0 +1,401 @@
+use crate::{
+    color_histogram, dhash, dominant_colors, get_cache_dir, read_image_bytes, split_archive_path,
+    write_atomic, Brightness, Error, LuminanceStats, Resolution, Result, Rgb, DOMINANT_COLORS,
+};
+use futures::StreamExt;
+use image::{imageops::FilterType, io::Reader, DynamicImage, ImageFormat, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{create_dir_all, read_to_string, Metadata},
+    io::Cursor,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+pub const THUMBNAIL_WIDTH: u32 = 360;
+pub const THUMBNAIL_HEIGHT: u32 = 200;
+
+/// Height of the thumbnails with the given width, keeping the default ratio
+pub fn thumbnail_height(width: u32) -> u32 {
+    width * THUMBNAIL_HEIGHT / THUMBNAIL_WIDTH
+}
+
+/// Bump when `ImageEntry` changes, so old indexes get rebuilt
+const INDEX_VERSION: u32 = 3;
+
+/// Metadata of an image file, as stored on the library index
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ImageEntry {
+    pub path: PathBuf,
+    /// File size in bytes
+    pub size: u64,
+    /// Modification time in seconds since the unix epoch
+    pub mtime: u64,
+    pub width: u32,
+    pub height: u32,
+    pub format: String,
+    /// blake3 hash of the file content
+    pub hash: String,
+    /// Perceptual hash (dHash), see [`crate::dhash`]
+    pub phash: u64,
+    pub colors: Vec<Rgb>,
+    /// Coarse color histogram, see [`crate::color_histogram`]
+    pub histogram: Vec<u8>,
+    pub luminance: LuminanceStats,
+    pub brightness: Brightness,
+}
+
+impl ImageEntry {
+    pub fn file_name(&self) -> &str {
+        self.path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+    }
+
+    /// Whether the entry still describes the file on disk
+    pub fn is_fresh(&self, metadata: &Metadata) -> bool {
+        self.size == metadata.len() && self.mtime == mtime(metadata)
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        Resolution::new(self.width, self.height)
+    }
+
+    pub fn thumbnail_path(&self) -> Result<PathBuf> {
+        Ok(get_thumbnails_dir()?.join(format!("{}.png", self.hash)))
+    }
+
+    /// Thumbnails with other than the default width are cached on a folder
+    /// per width
+    pub fn sized_thumbnail_path(&self, width: u32) -> Result<PathBuf> {
+        if width == THUMBNAIL_WIDTH {
+            return self.thumbnail_path();
+        }
+        Ok(get_thumbnails_dir()?
+            .join(width.to_string())
+            .join(format!("{}.png", self.hash)))
+    }
+}
+
+/// Image metadata index stored on `~/.cache/regolith-wallpaper/index.json`,
+/// used to avoid decoding every image on each launch
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LibraryIndex {
+    /// Indexes with a different version are rebuilt
+    #[serde(default)]
+    pub version: u32,
+    pub entries: BTreeMap<PathBuf, ImageEntry>,
+}
+
+impl Default for LibraryIndex {
+    fn default() -> Self {
+        Self {
+            version: INDEX_VERSION,
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl LibraryIndex {
+    /// Returns the entry for `path` if the file didn't change since it was
+    /// indexed
+    pub fn fresh_entry(&self, path: &Path) -> Option<&ImageEntry> {
+        let entry = self.entries.get(path)?;
+        match split_archive_path(path) {
+            // Images inside an archive change with it
+            Some((archive, _)) => {
+                let metadata = archive.metadata().ok()?;
+                (entry.mtime == mtime(&metadata)).then_some(entry)
+            }
+            None => {
+                let metadata = path.metadata().ok()?;
+                entry.is_fresh(&metadata).then_some(entry)
+            }
+        }
+    }
+
+    pub fn insert(&mut self, entry: ImageEntry) {
+        self.entries.insert(entry.path.clone(), entry);
+    }
+
+    /// Removes the entries inside `root` that are not on `paths`, returns the
+    /// number of removed entries
+    pub fn prune(&mut self, root: &Path, paths: &[PathBuf]) -> usize {
+        let before = self.entries.len();
+        let paths = paths.iter().collect::<HashSet<_>>();
+        self.entries
+            .retain(|path, _| !path.starts_with(root) || paths.contains(path));
+        before - self.entries.len()
+    }
+
+    /// Entries for the given paths, analyzing the images that are new or
+    /// changed since they were indexed. Returns the number of entries updated
+    /// and the images that failed.
+    pub async fn refresh(&mut self, paths: &[PathBuf]) -> (usize, Vec<(PathBuf, Error)>) {
+        let stale = paths
+            .iter()
+            .filter(|path| self.fresh_entry(path).is_none())
+            .cloned()
+            .collect::<Vec<_>>();
+        tracing::info!("Indexing {} images...", stale.len());
+        let workers = std::thread::available_parallelism().map_or(4, |n| n.get());
+        let mut tasks = futures::stream::iter(stale)
+            .map(|path| tokio::task::spawn_blocking(move || (path.clone(), analyze_image(&path))))
+            .buffer_unordered(workers);
+        let mut updated = 0;
+        let mut errors = Vec::new();
+        while let Some(task) = tasks.next().await {
+            match task {
+                Ok((_, Ok(analysis))) => {
+                    self.insert(analysis.entry);
+                    updated += 1;
+                }
+                Ok((path, Err(e))) => errors.push((path, e)),
+                Err(e) => tracing::error!(error.cause_chain=?e, error.message=%e, "Task failed."),
+            }
+        }
+        (updated, errors)
+    }
+}
+
+/// Result of decoding an image: its index entry and thumbnail
+pub struct Analysis {
+    pub entry: ImageEntry,
+    pub thumbnail: RgbaImage,
+}
+
+fn mtime(metadata: &Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Decodes the image on `path` (which can be inside an archive), computing its
+/// metadata and writing its thumbnail to the cache
+#[tracing::instrument]
+pub fn analyze_image(path: &Path) -> Result<Analysis> {
+    let source = split_archive_path(path).map_or(path, |(archive, _)| archive);
+    let metadata = source
+        .metadata()
+        .map_err(|_| Error::FailedToRead(path.to_path_buf()))?;
+    let bytes = read_image_bytes(path)?;
+    let hash = blake3::hash(&bytes).to_hex().to_string();
+    let reader = Reader::new(Cursor::new(&bytes))
+        .with_guessed_format()
+        .map_err(|e| Error::UnexpectedError(e.to_string()))?;
+    let format = reader
+        .format()
+        .map(|format| format!("{:?}", format).to_lowercase())
+        .unwrap_or_default();
+    let img = reader
+        .decode()
+        .map_err(|e| Error::UnexpectedError(e.to_string()))?;
+    let thumbnail = img
+        .resize(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT, FilterType::Gaussian)
+        .into_rgba8();
+    let luminance = LuminanceStats::from_image(&thumbnail);
+    let entry = ImageEntry {
+        path: path.to_path_buf(),
+        size: bytes.len() as u64,
+        mtime: mtime(&metadata),
+        width: img.width(),
+        height: img.height(),
+        format,
+        hash,
+        phash: dhash(&img),
+        colors: dominant_colors(&thumbnail, DOMINANT_COLORS),
+        histogram: color_histogram(&thumbnail),
+        luminance,
+        brightness: luminance.brightness(),
+    };
+    if let Err(e) = entry
+        .thumbnail_path()
+        .and_then(|path| save_thumbnail(path, &thumbnail))
+    {
+        tracing::warn!(error.message=%e, "Failed to cache thumbnail.");
+    }
+    Ok(Analysis { entry, thumbnail })
+}
+
+fn save_thumbnail(path: PathBuf, thumbnail: &RgbaImage) -> Result<()> {
+    create_dir_all(path.parent().unwrap()).map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to create folder.");
+        Error::UnexpectedError(e.to_string())
+    })?;
+    thumbnail
+        .save_with_format(&path, ImageFormat::Png)
+        .map_err(|_| Error::FailedToWriteFile(path))
+}
+
+/// Loads the cached thumbnail of an indexed image
+pub fn load_thumbnail(entry: &ImageEntry) -> Result<RgbaImage> {
+    let path = entry.thumbnail_path()?;
+    let img = image::open(&path).map_err(|_| Error::FailedToRead(path))?;
+    Ok(img.into_rgba8())
+}
+
+/// Loads the thumbnail of an indexed image with the given width, rendering it
+/// (from the default thumbnail when smaller, or else from the image) if it is
+/// not cached
+pub fn load_sized_thumbnail(entry: &ImageEntry, width: u32) -> Result<RgbaImage> {
+    let path = entry.sized_thumbnail_path(width)?;
+    if let Ok(img) = image::open(&path) {
+        return Ok(img.into_rgba8());
+    }
+    let source = match load_thumbnail(entry) {
+        Ok(thumbnail) if width < THUMBNAIL_WIDTH => DynamicImage::ImageRgba8(thumbnail),
+        _ => read_image_bytes(&entry.path).and_then(|bytes| {
+            image::load_from_memory(&bytes).map_err(|_| Error::FailedToRead(entry.path.clone()))
+        })?,
+    };
+    let thumbnail = source
+        .resize(width, thumbnail_height(width), FilterType::Gaussian)
+        .into_rgba8();
+    if let Err(e) = save_thumbnail(path, &thumbnail) {
+        tracing::warn!(error.message=%e, "Failed to cache thumbnail.");
+    }
+    Ok(thumbnail)
+}
+
+pub fn get_thumbnails_dir() -> Result<PathBuf> {
+    Ok(get_cache_dir()?.join("thumbnails"))
+}
+
+pub fn get_index_path() -> Result<PathBuf> {
+    Ok(get_cache_dir()?.join("index.json"))
+}
+
+pub fn load_index() -> Result<LibraryIndex> {
+    let path = get_index_path()?;
+    if !path.exists() {
+        return Ok(LibraryIndex::default());
+    }
+    let content = read_to_string(&path).map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to read file.");
+        Error::FailedToRead(path.clone())
+    })?;
+    match serde_json::from_str::<LibraryIndex>(&content) {
+        Ok(index) if index.version == INDEX_VERSION => Ok(index),
+        Ok(_) => {
+            tracing::info!("Outdated index file, rebuilding it.");
+            Ok(LibraryIndex::default())
+        }
+        Err(e) => {
+            tracing::warn!(error.message=%e, "Invalid index file, rebuilding it.");
+            Ok(LibraryIndex::default())
+        }
+    }
+}
+
+pub async fn save_index(index: LibraryIndex) -> Result<()> {
+    let content = serde_json::to_string(&index).map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to serialize content.");
+        Error::UnexpectedError(e.to_string())
+    })?;
+    let path = get_index_path()?;
+    tokio::fs::create_dir_all(path.parent().unwrap())
+        .await
+        .map_err(|e| Error::UnexpectedError(e.to_string()))?;
+    write_atomic(&path, content).await.map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to write file.");
+        Error::FailedToWriteFile(path.clone())
+    })?;
+    Ok(())
+}
+
+/// Order in which images are listed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortBy {
+    #[default]
+    Name,
+    Newest,
+    Largest,
+    Resolution,
+}
+
+impl SortBy {
+    pub const ALL: [SortBy; 4] = [
+        SortBy::Name,
+        SortBy::Newest,
+        SortBy::Largest,
+        SortBy::Resolution,
+    ];
+
+    pub fn sort(&self, entries: &mut [&ImageEntry]) {
+        match self {
+            SortBy::Name => entries.sort_by(|a, b| a.file_name().cmp(b.file_name())),
+            SortBy::Newest => entries.sort_by_key(|entry| std::cmp::Reverse(entry.mtime)),
+            SortBy::Largest => entries.sort_by_key(|entry| std::cmp::Reverse(entry.size)),
+            SortBy::Resolution => entries.sort_by_key(|entry| {
+                std::cmp::Reverse(u64::from(entry.width) * u64::from(entry.height))
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for SortBy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            SortBy::Name => "Sort by name",
+            SortBy::Newest => "Newest first",
+            SortBy::Largest => "Largest file first",
+            SortBy::Resolution => "Highest resolution first",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, width: u32) -> ImageEntry {
+        ImageEntry {
+            path: PathBuf::from(path),
+            size: 0,
+            mtime: 0,
+            width,
+            height: 100,
+            format: "png".into(),
+            hash: String::new(),
+            phash: 0,
+            colors: Vec::new(),
+            histogram: Vec::new(),
+            luminance: LuminanceStats::default(),
+            brightness: Brightness::default(),
+        }
+    }
+
+    #[test]
+    fn prune_removes_missing_files_inside_root() {
+        let mut index = LibraryIndex::default();
+        index.insert(entry("/root/a.png", 1));
+        index.insert(entry("/root/b.png", 1));
+        index.insert(entry("/other/c.png", 1));
+        let removed = index.prune(Path::new("/root"), &[PathBuf::from("/root/a.png")]);
+        assert_eq!(removed, 1);
+        assert!(index.entries.contains_key(Path::new("/root/a.png")));
+        assert!(index.entries.contains_key(Path::new("/other/c.png")));
+    }
+
+    #[test]
+    fn sort_by_resolution() {
+        let (a, b) = (entry("/a.png", 10), entry("/b.png", 20));
+        let mut entries = vec![&a, &b];
+        SortBy::Resolution.sort(&mut entries);
+        assert_eq!(entries[0].path, b.path);
+        // 70000x70000 overflows u32
+        let mut huge = entry("/huge.png", 70_000);
+        huge.height = 70_000;
+        let mut entries = vec![&a, &huge];
+        SortBy::Resolution.sort(&mut entries);
+        assert_eq!(entries[0].path, huge.path);
+        SortBy::Name.sort(&mut entries);
+        assert_eq!(entries[0].path, a.path);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::{format_count, format_duration, Message};
+use iced::widget::{button, column, container, progress_bar, row, scrollable, text, Column};
+use iced::{theme, Alignment, Color, Element, Length};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Progress of the images being analyzed (and their thumbnails rendered)
+#[derive(Debug, Default)]
+pub struct LoadProgress {
+    total: usize,
+    done: usize,
+    started: Option<Instant>,
+}
+
+impl LoadProgress {
+    /// Adds images to load, starting over if the previous batch was done
+    pub fn add(&mut self, count: usize, now: Instant) {
+        if count == 0 {
+            return;
+        }
+        if self.is_done() {
+            *self = Self {
+                total: 0,
+                done: 0,
+                started: Some(now),
+            };
+        }
+        self.total += count;
+    }
+
+    pub fn finish_one(&mut self) {
+        self.done = (self.done + 1).min(self.total);
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done >= self.total
+    }
+
+    /// Estimated time left, from the average time per image so far
+    pub fn eta(&self, now: Instant) -> Option<Duration> {
+        let started = self.started?;
+        if self.done == 0 {
+            return None;
+        }
+        let per_image = now.duration_since(started) / self.done as u32;
+        Some(per_image * (self.total - self.done) as u32)
+    }
+
+    pub fn label(&self, now: Instant) -> String {
+        let mut label = format!(
+            "{} / {} thumbnails",
+            format_count(self.done),
+            format_count(self.total)
+        );
+        if let Some(eta) = self.eta(now) {
+            label.push_str(&format!(", about {} left", format_duration(eta)));
+        }
+        label
+    }
+
+    pub fn view<'a, T: 'a>(&self) -> Element<'a, T> {
+        row!(
+            progress_bar(0.0..=self.total as f32, self.done as f32)
+                .height(8)
+                .width(200),
+            text(self.label(Instant::now())).size(14)
+        )
+        .spacing(10)
+        .align_items(Alignment::Center)
+        .into()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum LoadErrorsMessage {
+    ToggleShow,
+    Retry(PathBuf),
+    RetryAll,
+    Ignore(PathBuf),
+    OpenFolder(PathBuf),
+}
+
+/// Files that failed to load, with the reason
+#[derive(Debug, Default)]
+pub struct LoadErrors {
+    pub errors: BTreeMap<PathBuf, String>,
+    show: bool,
+}
+
+impl LoadErrors {
+    pub fn add(&mut self, path: PathBuf, reason: String) {
+        self.errors.insert(path, reason);
+    }
+
+    pub fn update(&mut self, message: LoadErrorsMessage) -> Option<Message> {
+        match message {
+            LoadErrorsMessage::ToggleShow => {
+                self.show = !self.show;
+                None
+            }
+            LoadErrorsMessage::Retry(path) => {
+                self.errors.remove(&path);
+                Some(Message::RetryImages(vec![path]))
+            }
+            LoadErrorsMessage::RetryAll => {
+                let paths = std::mem::take(&mut self.errors).into_keys().collect();
+                Some(Message::RetryImages(paths))
+            }
+            LoadErrorsMessage::Ignore(path) => {
+                self.errors.remove(&path);
+                Some(Message::IgnoreImage(path))
+            }
+            LoadErrorsMessage::OpenFolder(path) => Some(Message::OpenInFileManager(path)),
+        }
+    }
+
+    /// Collapsible list of the errors, nothing if there are none
+    pub fn view(&self) -> Option<Element<'_, LoadErrorsMessage>> {
+        if self.errors.is_empty() {
+            return None;
+        }
+        let arrow = if self.show { "▾" } else { "▸" };
+        let toggle_btn = button(
+            text(format!(
+                "{} {} files failed to load",
+                arrow,
+                format_count(self.errors.len())
+            ))
+            .size(14)
+            .style(Color::from_rgb(0.9, 0.2, 0.2)),
+        )
+        .padding([2, 6])
+        .style(theme::Button::Text)
+        .on_press(LoadErrorsMessage::ToggleShow);
+        let retry_all_btn = button(text("Retry all").size(14))
+            .padding([2, 6])
+            .on_press(LoadErrorsMessage::RetryAll);
+        let header = row!(toggle_btn, retry_all_btn)
+            .spacing(10)
+            .align_items(Alignment::Center);
+        if !self.show {
+            return Some(header.into());
+        }
+        let rows = self.errors.iter().map(|(path, reason)| {
+            let small_btn = |label| button(text(label).size(12)).padding([1, 6]);
+            row!(
+                column!(
+                    text(path.display()).size(12),
+                    text(reason).size(12).style(Color::from_rgb(0.9, 0.2, 0.2))
+                )
+                .width(Length::Fill),
+                small_btn("Retry").on_press(LoadErrorsMessage::Retry(path.clone())),
+                small_btn("Ignore")
+                    .style(theme::Button::Secondary)
+                    .on_press(LoadErrorsMessage::Ignore(path.clone())),
+                small_btn("Open folder")
+                    .style(theme::Button::Secondary)
+                    .on_press(LoadErrorsMessage::OpenFolder(path.clone())),
+            )
+            .spacing(6)
+            .align_items(Alignment::Center)
+            .into()
+        });
+        let list = container(scrollable(
+            Column::with_children(rows.collect()).spacing(4).padding(6),
+        ))
+        .max_height(200)
+        .style(|_: &_| container::Appearance {
+            border_width: 1.0,
+            border_color: Color::from_rgb(0.3, 0.3, 0.3),
+            ..Default::default()
+        });
+        Some(column!(header, list).spacing(4).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_estimates_time_left() {
+        let start = Instant::now();
+        let mut progress = LoadProgress::default();
+        assert!(progress.is_done());
+        progress.add(1204, start);
+        assert_eq!(progress.label(start), "0 / 1,204 thumbnails");
+        for _ in 0..301 {
+            progress.finish_one();
+        }
+        let now = start + Duration::from_secs(301);
+        assert_eq!(progress.eta(now), Some(Duration::from_secs(903)));
+        assert_eq!(
+            progress.label(now),
+            "301 / 1,204 thumbnails, about 15 min left"
+        );
+        // Images added while loading are part of the same batch
+        progress.add(96, now);
+        assert_eq!(
+            progress.label(now),
+            "301 / 1,300 thumbnails, about 16 min left"
+        );
+    }
+}
@@ -1,21 +1,235 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+#[cfg(feature = "gui")]
 use iced::{Application, Settings};
-use regolith_wallpaper::{commands, get_configuration, RegolithWallpaperApp};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+#[cfg(feature = "tui")]
+use regolith_wallpaper::GraphicsProtocol;
+#[cfg(feature = "gui")]
+use regolith_wallpaper::RegolithWallpaperApp;
+use regolith_wallpaper::{
+    commands::{self, DailyOptions, DedupeOptions, ListOptions, MenuOptions},
+    has_config_errors, load_configuration, parse_config_override, parse_hex, set_config_overrides,
+    Brightness, ImportMode, ImportOptions, MenuFormat, PickOptions, Resolution, Rgb, SortBy,
+    DEFAULT_INTERVAL, DUPLICATE_MAX_DISTANCE,
+};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing_subscriber::{
+    fmt::writer::BoxMakeWriter, layer::SubscriberExt, util::SubscriberInitExt,
+};
 
 /// regolith-wallpaper
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Max number of images to load
-    #[arg(short, long)]
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Deprecated: the gallery only loads the images shown
+    #[arg(short, long, hide = true)]
     max_images: Option<usize>,
     /// Pick a random wallpaper
     #[arg(short, long)]
     random_pick: bool,
+    /// Folder of the wallpapers, overriding the config files
+    #[arg(long, global = true)]
+    wallpapers_path: Option<PathBuf>,
+    /// Override a setting of the config files (e.g.: `workspaces.debounce=500`)
+    #[arg(long = "set", value_name = "KEY=VALUE", global = true, value_parser = parse_config_override)]
+    overrides: Vec<(String, String)>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Pick a random wallpaper
+    Random {
+        /// Only pick images from this collection (tag)
+        #[arg(short, long)]
+        collection: Option<String>,
+        /// Pick among the images closest to this color (e.g.: `#1e90ff`)
+        #[arg(long, value_parser = parse_hex)]
+        color: Option<Rgb>,
+        #[command(flatten)]
+        brightness: BrightnessArgs,
+    },
+    /// Change to the next wallpaper of the running GUI (or daemon), or to a
+    /// random one
+    Next,
+    /// Set the image as wallpaper
+    Set { path: PathBuf },
+    /// Make the running GUI (or daemon) read the config file again
+    Reload,
+    /// Keep running and pick a random wallpaper periodically
+    Daemon {
+        /// Seconds between wallpaper changes, 0 to only follow the workspaces
+        /// and the commands
+        #[arg(short, long, default_value_t = DEFAULT_INTERVAL)]
+        interval: u64,
+        /// Only pick images from this collection (tag)
+        #[arg(short, long)]
+        collection: Option<String>,
+        #[command(flatten)]
+        brightness: BrightnessArgs,
+    },
+    /// Apply the wallpaper of the day, the same one on every machine sharing
+    /// the library and the seed
+    Daily {
+        /// Only pick images from this collection (tag)
+        #[arg(short, long)]
+        collection: Option<String>,
+        /// Seed of the pick, `daily_seed` on the config file by default
+        #[arg(short, long)]
+        seed: Option<String>,
+        /// Keep running, changing the wallpaper when the day changes
+        #[arg(short, long)]
+        watch: bool,
+        /// Apply it even if it was already applied today
+        #[arg(short, long)]
+        force: bool,
+        #[command(flatten)]
+        brightness: BrightnessArgs,
+    },
+    /// List the images on the library
+    List {
+        /// Only list images from this collection (tag)
+        #[arg(short, long)]
+        collection: Option<String>,
+        #[arg(short, long, value_enum, default_value_t)]
+        sort: SortBy,
+        /// Sort by closeness to this color (e.g.: `#1e90ff`)
+        #[arg(long, value_parser = parse_hex)]
+        color: Option<Rgb>,
+        /// Print size, format, brightness and dominant colors of each image
+        #[arg(short, long)]
+        long: bool,
+    },
+    /// Show the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Browse the library on the terminal
+    #[cfg(feature = "tui")]
+    Tui {
+        /// How images are previewed, guessed from the terminal by default
+        #[arg(short, long, value_enum)]
+        graphics: Option<GraphicsProtocol>,
+    },
+    /// Menu for rofi's script mode (`rofi -show wallpaper -modes
+    /// "wallpaper:regolith-wallpaper rofi"`), or for dmenu
+    Rofi {
+        /// Label of the chosen row, passed by rofi
+        selection: Option<String>,
+        /// Only list images from this collection (tag)
+        #[arg(short, long)]
+        collection: Option<String>,
+        /// Print plain labels, without the rofi icon hints
+        #[arg(long)]
+        dmenu: bool,
+    },
+    /// Find near-duplicate images and move all but the highest resolution copy
+    /// to the trash
+    Dedupe {
+        /// Only print the duplicates
+        #[arg(short, long)]
+        dry_run: bool,
+        /// Max different bits between perceptual hashes (0-64)
+        #[arg(short, long, default_value_t = DUPLICATE_MAX_DISTANCE)]
+        threshold: u32,
+    },
+    /// Restore a daily backup of the Regolith config file (the latest one by
+    /// default)
+    RestoreBackup {
+        /// Date of the backup, e.g.: `2024-05-17`
+        date: Option<String>,
+        /// Only list the backups
+        #[arg(short, long)]
+        list: bool,
+    },
+    /// Print the wallpapers applied, the latest last
+    History {
+        /// Only print the latest ones
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+    },
+    /// Copy (or move) images, folders of images and archives (zip, tar,
+    /// tar.gz) into the library
+    Import {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Destination folder, the wallpapers path by default
+        #[arg(short, long)]
+        to: Option<PathBuf>,
+        /// Move the files instead of copying them
+        #[arg(long = "move")]
+        move_files: bool,
+        /// Convert formats other than PNG and JPEG
+        #[arg(long)]
+        convert: bool,
+        /// Downscale larger images to fit this resolution (e.g.: `3840x2160`)
+        #[arg(long)]
+        max_resolution: Option<Resolution>,
+        /// Sub-folder for the images of archives, the archive name by default
+        #[arg(short, long)]
+        collection: Option<String>,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+#[group(multiple = false)]
+struct BrightnessArgs {
+    /// Only pick dark images
+    #[arg(long)]
+    dark: bool,
+    /// Only pick light images
+    #[arg(long)]
+    light: bool,
+    /// Only pick images matching the active Regolith look (dark or light)
+    #[arg(long)]
+    match_look: bool,
+}
+
+impl BrightnessArgs {
+    fn pick_options(&self, collection: Option<String>, color: Option<Rgb>) -> PickOptions {
+        let brightness = if self.dark {
+            Some(Brightness::Dark)
+        } else if self.light {
+            Some(Brightness::Light)
+        } else {
+            None
+        };
+        PickOptions {
+            collection,
+            color,
+            brightness,
+            match_look: self.match_look,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommand {
+    /// Print the configuration merged from the defaults, the system and user
+    /// files, the environment variables and the command line
+    Show {
+        /// Print where each value comes from
+        #[arg(long)]
+        origin: bool,
+    },
+    /// Check the configuration, printing the problems found with their file
+    /// and line. Fails if there are errors.
+    Check,
 }
 
-fn main() -> iced::Result {
+/// Logs go to stderr, except while the terminal UI is drawn
+fn log_writer(args: &Args) -> BoxMakeWriter {
+    #[cfg(feature = "tui")]
+    if matches!(args.command, Some(Command::Tui { .. })) {
+        return BoxMakeWriter::new(std::io::sink);
+    }
+    let _ = args;
+    BoxMakeWriter::new(std::io::stderr)
+}
+
+fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
     tracing_subscriber::registry()
@@ -23,23 +237,184 @@ fn main() -> iced::Result {
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| "iced=warn,regolith_wallpaper=info".into()),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(log_writer(&args)))
         .init();
 
-    let mut configuration = get_configuration().expect("Failed to get configuration.");
-    if let Some(max_images) = args.max_images {
-        configuration.max_images = Some(max_images);
+    let mut overrides = args.overrides.clone();
+    if let Some(path) = &args.wallpapers_path {
+        let paths = serde_yaml::to_string(&[path]).unwrap_or_default();
+        overrides.insert(0, ("wallpapers_paths".into(), paths));
+    }
+    set_config_overrides(overrides);
+    let (configuration, diagnostics) = load_configuration()?;
+    // The GUI shows the problems itself, and `config` commands print them
+    let is_gui = args.command.is_none() && !args.random_pick;
+    if !is_gui && !matches!(args.command, Some(Command::Config { .. })) {
+        if has_config_errors(&diagnostics) {
+            for diagnostic in &diagnostics {
+                eprintln!("{}", diagnostic);
+            }
+            anyhow::bail!("Invalid configuration, see `regolith-wallpaper config check`.");
+        }
+        for diagnostic in &diagnostics {
+            tracing::warn!(%diagnostic, "Configuration problem.");
+        }
+    }
+    if args.max_images.is_some() {
+        tracing::warn!("`--max-images` is deprecated and ignored.");
     }
     tracing::info!("Loaded configuration: {:#?}", configuration);
 
+    match args.command {
+        Some(Command::Random {
+            collection,
+            color,
+            brightness,
+        }) => {
+            let options = brightness.pick_options(collection, color);
+            commands::pick_random_image(configuration, options)?;
+            return Ok(());
+        }
+        Some(Command::Daemon {
+            interval,
+            collection,
+            brightness,
+        }) => {
+            commands::run_daemon(
+                configuration,
+                brightness.pick_options(collection, None),
+                Duration::from_secs(interval),
+            )?;
+            return Ok(());
+        }
+        Some(Command::Daily {
+            collection,
+            seed,
+            watch,
+            force,
+            brightness,
+        }) => {
+            let options = DailyOptions {
+                pick: brightness.pick_options(collection, None),
+                seed,
+                watch,
+                force,
+            };
+            commands::daily(configuration, options)?;
+            return Ok(());
+        }
+        Some(Command::List {
+            collection,
+            sort,
+            color,
+            long,
+        }) => {
+            let options = ListOptions {
+                collection,
+                sort_by: sort,
+                color,
+                long,
+            };
+            commands::list_images(configuration, options)?;
+            return Ok(());
+        }
+        Some(Command::Config { command }) => {
+            match command {
+                ConfigCommand::Show { origin } => commands::show_config(origin)?,
+                ConfigCommand::Check => commands::check_config()?,
+            }
+            return Ok(());
+        }
+        #[cfg(feature = "tui")]
+        Some(Command::Tui { graphics }) => {
+            commands::tui(configuration, graphics)?;
+            return Ok(());
+        }
+        Some(Command::Rofi {
+            selection,
+            collection,
+            dmenu,
+        }) => {
+            let options = MenuOptions {
+                selection,
+                collection,
+                format: if dmenu {
+                    MenuFormat::Dmenu
+                } else {
+                    MenuFormat::Rofi
+                },
+            };
+            commands::menu(configuration, options)?;
+            return Ok(());
+        }
+        Some(Command::Import {
+            paths,
+            to,
+            move_files,
+            convert,
+            max_resolution,
+            collection,
+        }) => {
+            let options = ImportOptions {
+                mode: if move_files {
+                    ImportMode::Move
+                } else {
+                    ImportMode::Copy
+                },
+                convert,
+                max_resolution,
+                collection,
+            };
+            commands::import(configuration, paths, to, options)?;
+            return Ok(());
+        }
+        Some(Command::Next) => {
+            commands::next(configuration)?;
+            return Ok(());
+        }
+        Some(Command::Set { path }) => {
+            commands::set(configuration, path)?;
+            return Ok(());
+        }
+        Some(Command::Reload) => {
+            commands::reload()?;
+            return Ok(());
+        }
+        Some(Command::RestoreBackup { date, list }) => {
+            commands::restore_backup(date, list)?;
+            return Ok(());
+        }
+        Some(Command::History { limit }) => {
+            commands::history(limit)?;
+            return Ok(());
+        }
+        Some(Command::Dedupe { dry_run, threshold }) => {
+            let options = DedupeOptions {
+                dry_run,
+                max_distance: threshold,
+            };
+            commands::dedupe(configuration, options)?;
+            return Ok(());
+        }
+        None => {}
+    }
+
     if args.random_pick {
-        commands::pick_random_image(configuration).unwrap();
+        commands::pick_random_image(configuration, PickOptions::default())?;
         return Ok(());
     }
 
+    // A single window: launching again brings the existing one to the front
+    if commands::focus_running_instance().unwrap_or(false) {
+        return Ok(());
+    }
+    #[cfg(feature = "gui")]
     RegolithWallpaperApp::run(Settings {
-        flags: configuration,
+        flags: (configuration, diagnostics),
         ..Default::default()
     })?;
+    #[cfg(not(feature = "gui"))]
+    anyhow::bail!("Built without the GUI (`gui` feature), use a subcommand.");
+    #[cfg(feature = "gui")]
     Ok(())
 }
@@ -0,0 +1,134 @@
+use crate::{normalize_tag, Collection, ImageEntry, TagStore, FAVORITE_TAG};
+use std::path::{Path, PathBuf};
+
+/// Prefix of the rows opening a collection, e.g.: `#space`
+pub const MENU_COLLECTION_PREFIX: &str = "#";
+/// Prefix of the images marked as favorite
+pub const MENU_FAVORITE_PREFIX: &str = "★ ";
+/// Row going back from a collection to all the wallpapers
+pub const MENU_BACK: &str = "..";
+
+/// Output of the `rofi` command
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MenuFormat {
+    /// rofi script mode: rows with `\0icon\x1f<thumbnail>` hints
+    #[default]
+    Rofi,
+    /// Plain labels, one per line
+    Dmenu,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MenuRow {
+    pub label: String,
+    pub icon: Option<PathBuf>,
+}
+
+impl MenuRow {
+    pub fn format(&self, format: MenuFormat) -> String {
+        match (&self.icon, format) {
+            (Some(icon), MenuFormat::Rofi) => {
+                format!("{}\0icon\x1f{}", self.label, icon.display())
+            }
+            _ => self.label.clone(),
+        }
+    }
+}
+
+/// What a chosen row stands for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MenuSelection {
+    Back,
+    Collection(String),
+    Image(PathBuf),
+}
+
+impl MenuSelection {
+    /// Parses the label of a row, image labels are relative to the library root
+    pub fn parse(label: &str, root: &Path) -> Self {
+        let label = label.trim_end_matches('\n');
+        if label == MENU_BACK {
+            return Self::Back;
+        }
+        if let Some(name) = label.strip_prefix(MENU_COLLECTION_PREFIX) {
+            return Self::Collection(normalize_tag(name));
+        }
+        let label = label.strip_prefix(MENU_FAVORITE_PREFIX).unwrap_or(label);
+        Self::Image(root.join(label))
+    }
+}
+
+/// Rows of the menu: the collections (or the way back out of one) and then
+/// the images, labeled with their path relative to the library root
+pub fn menu_rows(
+    entries: &[ImageEntry],
+    root: &Path,
+    tags: &TagStore,
+    collection: &Collection,
+) -> Vec<MenuRow> {
+    let mut rows = match collection {
+        Collection::Named(_) => vec![MenuRow {
+            label: MENU_BACK.to_string(),
+            icon: None,
+        }],
+        Collection::All => tags
+            .collections(entries.iter().map(|entry| &entry.path), Some(root))
+            .into_iter()
+            .map(|name| MenuRow {
+                label: format!("{}{}", MENU_COLLECTION_PREFIX, name),
+                icon: None,
+            })
+            .collect(),
+    };
+    rows.extend(entries.iter().map(|entry| {
+        let relative = entry.path.strip_prefix(root).unwrap_or(&entry.path);
+        let prefix = if tags.has_tag(&entry.path, FAVORITE_TAG) {
+            MENU_FAVORITE_PREFIX
+        } else {
+            ""
+        };
+        // rofi can load the image itself when there is no thumbnail yet
+        let icon = entry
+            .thumbnail_path()
+            .ok()
+            .filter(|thumbnail| thumbnail.exists())
+            .unwrap_or_else(|| entry.path.clone());
+        MenuRow {
+            label: format!("{}{}", prefix, relative.display()),
+            icon: Some(icon),
+        }
+    }));
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn menu_labels_round_trip() {
+        let root = Path::new("/wp");
+        assert_eq!(MenuSelection::parse("..", root), MenuSelection::Back);
+        assert_eq!(
+            MenuSelection::parse("#Space", root),
+            MenuSelection::Collection("space".into())
+        );
+        assert_eq!(
+            MenuSelection::parse("★ space/moon.png\n", root),
+            MenuSelection::Image("/wp/space/moon.png".into())
+        );
+        assert_eq!(
+            MenuSelection::parse("/elsewhere/sun.png", root),
+            MenuSelection::Image("/elsewhere/sun.png".into())
+        );
+        let row = MenuRow {
+            label: "space/moon.png".into(),
+            icon: Some("/thumbs/abc.png".into()),
+        };
+        assert_eq!(
+            row.format(MenuFormat::Rofi),
+            "space/moon.png\0icon\x1f/thumbs/abc.png"
+        );
+        assert_eq!(row.format(MenuFormat::Dmenu), "space/moon.png");
+    }
+}
@@ -0,0 +1,294 @@
+use crate::{build_provider, Message, Provider, ProviderConfig, RemoteImage, SourceStore};
+use iced::widget::{
+    button, column, container, image::Handle, pick_list, row, scrollable, text, Column, Image,
+};
+use iced::{theme, Alignment, Color, Element, Length};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const PREVIEW_WIDTH: u16 = 160;
+
+/// Images waiting to be downloaded. Each provider downloads one image at a
+/// time, so its rate limit applies.
+#[derive(Debug, Default)]
+pub struct DownloadQueue {
+    pending: BTreeMap<usize, VecDeque<RemoteImage>>,
+    active: BTreeSet<usize>,
+    queued: HashSet<String>,
+    done: usize,
+    total: usize,
+}
+
+impl DownloadQueue {
+    /// Adds the images of the provider, skipping the ones already queued
+    pub fn push(&mut self, provider: usize, images: Vec<RemoteImage>) {
+        if self.is_done() {
+            self.done = 0;
+            self.total = 0;
+        }
+        for image in images {
+            if self.queued.insert(image.url.clone()) {
+                self.total += 1;
+                self.pending.entry(provider).or_default().push_back(image);
+            }
+        }
+    }
+
+    /// Next download of each provider that is not downloading
+    pub fn start(&mut self) -> Vec<(usize, RemoteImage)> {
+        let mut started = Vec::new();
+        for (provider, images) in &mut self.pending {
+            if !self.active.contains(provider) {
+                if let Some(image) = images.pop_front() {
+                    self.active.insert(*provider);
+                    started.push((*provider, image));
+                }
+            }
+        }
+        self.pending.retain(|_, images| !images.is_empty());
+        started
+    }
+
+    pub fn finish(&mut self, provider: usize, url: &str) {
+        self.active.remove(&provider);
+        self.queued.remove(url);
+        self.done += 1;
+    }
+
+    pub fn is_queued(&self, url: &str) -> bool {
+        self.queued.contains(url)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.queued.is_empty()
+    }
+
+    pub fn label(&self) -> String {
+        format!("Downloading {} / {}", self.done + 1, self.total)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum OnlineMessage {
+    ProviderSelected(String),
+    Refresh,
+    Download(usize),
+    DownloadAll,
+}
+
+/// "Browse online" tab: the images offered by the providers on the config
+/// file, downloaded into the library
+pub struct Online {
+    providers: Vec<(ProviderConfig, Arc<dyn Provider>)>,
+    selected: usize,
+    /// Images of the selected provider
+    images: Vec<RemoteImage>,
+    listed: Option<usize>,
+    loading: bool,
+    error: Option<String>,
+    previews: HashMap<String, Handle>,
+    pub queue: DownloadQueue,
+}
+
+impl Online {
+    pub fn new(configs: &[ProviderConfig]) -> Self {
+        let providers = configs
+            .iter()
+            .filter_map(|config| match build_provider(config) {
+                Ok(provider) => Some((config.clone(), provider)),
+                Err(e) => {
+                    tracing::error!(error.cause_chain=?e, error.message=%e, name = config.name, "Failed to create provider.");
+                    None
+                }
+            })
+            .collect();
+        Self {
+            providers,
+            selected: 0,
+            images: Vec::new(),
+            listed: None,
+            loading: false,
+            error: None,
+            previews: HashMap::new(),
+            queue: DownloadQueue::default(),
+        }
+    }
+
+    pub fn provider(&self, index: usize) -> Option<Arc<dyn Provider>> {
+        self.providers
+            .get(index)
+            .map(|(_, provider)| provider.clone())
+    }
+
+    /// Folder of the provider, relative to the library root
+    pub fn folder(&self, index: usize) -> Option<PathBuf> {
+        self.providers.get(index).map(|(config, _)| config.folder())
+    }
+
+    /// Provider to list, if the selected one was not listed yet
+    pub fn needs_listing(&self) -> Option<usize> {
+        (self.listed != Some(self.selected)
+            && !self.loading
+            && self.selected < self.providers.len())
+        .then_some(self.selected)
+    }
+
+    pub fn start_listing(&mut self) {
+        self.loading = true;
+        self.error = None;
+    }
+
+    /// Stores the images listed by the provider, returns the URLs of the
+    /// previews to load
+    pub fn listed(&mut self, index: usize, result: crate::Result<Vec<RemoteImage>>) -> Vec<String> {
+        self.loading = false;
+        self.listed = Some(index);
+        if index != self.selected {
+            return Vec::new();
+        }
+        match result {
+            Ok(images) => {
+                self.images = images;
+                self.images
+                    .iter()
+                    .filter_map(|image| image.thumbnail_url.clone())
+                    .filter(|url| !self.previews.contains_key(url))
+                    .collect()
+            }
+            Err(e) => {
+                self.images.clear();
+                self.error = Some(e.to_string());
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn preview_loaded(&mut self, url: String, bytes: Vec<u8>) {
+        self.previews.insert(url, Handle::from_memory(bytes));
+    }
+
+    pub fn update(&mut self, message: OnlineMessage) -> Option<Message> {
+        match message {
+            OnlineMessage::ProviderSelected(name) => {
+                self.selected = self
+                    .providers
+                    .iter()
+                    .position(|(config, _)| config.name == name)?;
+                self.images.clear();
+                self.listed = None;
+                None
+            }
+            OnlineMessage::Refresh => {
+                self.listed = None;
+                None
+            }
+            OnlineMessage::Download(index) => {
+                let image = self.images.get(index)?.clone();
+                Some(Message::DownloadImages(self.selected, vec![image]))
+            }
+            OnlineMessage::DownloadAll => {
+                Some(Message::DownloadImages(self.selected, self.images.clone()))
+            }
+        }
+    }
+
+    pub fn view<'a>(&'a self, sources: &'a SourceStore) -> Element<'a, OnlineMessage> {
+        if self.providers.is_empty() {
+            return text("No providers, add them to `providers` on the config file.")
+                .size(14)
+                .into();
+        }
+        let names = self
+            .providers
+            .iter()
+            .map(|(config, _)| config.name.clone())
+            .collect::<Vec<_>>();
+        let selected = names.get(self.selected).cloned();
+        let mut header = row!(
+            pick_list(names, selected, OnlineMessage::ProviderSelected)
+                .text_size(14)
+                .padding([2, 8]),
+            button(text("Refresh").size(14))
+                .padding([2, 10])
+                .on_press_maybe((!self.loading).then_some(OnlineMessage::Refresh)),
+            button(text(format!("Download all ({})", self.images.len())).size(14))
+                .padding([2, 10])
+                .on_press_maybe((!self.images.is_empty()).then_some(OnlineMessage::DownloadAll)),
+        )
+        .spacing(10)
+        .align_items(Alignment::Center);
+        if !self.queue.is_done() {
+            header = header.push(text(self.queue.label()).size(14));
+        }
+        let body: Element<_> = if self.loading {
+            text("Loading...").size(14).into()
+        } else if let Some(e) = &self.error {
+            text(e)
+                .size(14)
+                .style(Color::from_rgb(0.9, 0.2, 0.2))
+                .into()
+        } else {
+            let rows = self.images.iter().enumerate().map(|(i, image)| {
+                let preview: Element<_> = match image
+                    .thumbnail_url
+                    .as_ref()
+                    .and_then(|url| self.previews.get(url))
+                {
+                    Some(handle) => Image::new(handle.clone()).width(PREVIEW_WIDTH).into(),
+                    None => container(text(image.file_name()).size(12))
+                        .width(PREVIEW_WIDTH)
+                        .into(),
+                };
+                let mut details =
+                    column!(text(image.title.as_deref().unwrap_or(&image.url)).size(14));
+                if let Some(author) = &image.author {
+                    details = details.push(text(format!("by {}", author)).size(12));
+                }
+                if let Some(page_url) = &image.page_url {
+                    details = details.push(text(page_url).size(12));
+                }
+                let action: Element<_> = if sources.contains_url(&image.url) {
+                    text("Downloaded").size(14).into()
+                } else if self.queue.is_queued(&image.url) {
+                    text("Queued").size(14).into()
+                } else {
+                    button(text("Download").size(14))
+                        .padding([2, 10])
+                        .style(theme::Button::Secondary)
+                        .on_press(OnlineMessage::Download(i))
+                        .into()
+                };
+                row!(preview, details.width(Length::Fill).spacing(2), action)
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .into()
+            });
+            scrollable(Column::with_children(rows.collect()).spacing(8).padding(4))
+                .height(Length::Fill)
+                .into()
+        };
+        column!(header, body).spacing(10).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_downloads_one_image_per_provider() {
+        let mut queue = DownloadQueue::default();
+        queue.push(0, vec![RemoteImage::new("a"), RemoteImage::new("b")]);
+        queue.push(1, vec![RemoteImage::new("c"), RemoteImage::new("a")]);
+        let started = queue.start();
+        assert_eq!(started.len(), 2);
+        assert!(queue.start().is_empty());
+        assert_eq!(queue.label(), "Downloading 1 / 3");
+        queue.finish(0, "a");
+        assert_eq!(queue.start(), vec![(0, RemoteImage::new("b"))]);
+        queue.finish(0, "b");
+        queue.finish(1, "c");
+        assert!(queue.is_done());
+    }
+}
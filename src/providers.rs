@@ -0,0 +1,635 @@
+use crate::{import_bytes, Error, ImageSource, ImportOptions, Result};
+use futures::future::BoxFuture;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Seconds between requests to a provider, unless set on its config
+const DEFAULT_MIN_INTERVAL: f64 = 1.0;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Largest response read from a provider, bigger ones are dropped
+const MAX_RESPONSE_SIZE: u64 = 64 * 1024 * 1024;
+const MEDIA_RSS_NAMESPACE: &str = "http://search.yahoo.com/mrss/";
+
+/// How the images of a provider are listed
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ProviderKind {
+    /// Text file with an image URL per line
+    UrlList { url: String },
+    /// RSS or Atom feed with image enclosures (or `media:content`)
+    Feed { url: String },
+    /// JSON API, the fields are JSON pointers (e.g.: `/urls/full`) to the
+    /// array of images and to the fields of each image
+    Json {
+        url: String,
+        #[serde(default)]
+        items: String,
+        image_url: String,
+        #[serde(default)]
+        thumbnail_url: Option<String>,
+        #[serde(default)]
+        title: Option<String>,
+        #[serde(default)]
+        author: Option<String>,
+        #[serde(default)]
+        page_url: Option<String>,
+        /// Sent with every request (e.g.: `Authorization`)
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+}
+
+/// Remote source of wallpapers, as set on the config file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: ProviderKind,
+    /// Seconds between requests
+    #[serde(default)]
+    pub min_interval: Option<f64>,
+    /// Folder inside the library root the images are saved to, the provider
+    /// name by default
+    #[serde(default)]
+    pub folder: Option<PathBuf>,
+}
+
+impl ProviderConfig {
+    pub fn folder(&self) -> PathBuf {
+        self.folder
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(&self.name))
+    }
+}
+
+/// Image offered by a provider
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RemoteImage {
+    pub url: String,
+    pub thumbnail_url: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub page_url: Option<String>,
+}
+
+impl RemoteImage {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Last segment of the URL path, used as file name
+    pub fn file_name(&self) -> String {
+        let path = self.url.split(['?', '#']).next().unwrap_or_default();
+        path.rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty() && !name.contains(':'))
+            .unwrap_or("image")
+            .to_string()
+    }
+
+    pub fn source(&self, provider: &str) -> ImageSource {
+        ImageSource {
+            provider: provider.to_string(),
+            url: self.url.clone(),
+            title: self.title.clone(),
+            author: self.author.clone(),
+            page_url: self.page_url.clone(),
+        }
+    }
+}
+
+pub trait Provider: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Images offered by the provider
+    fn list(&self) -> BoxFuture<'_, Result<Vec<RemoteImage>>>;
+
+    /// Downloads a file (an image or its thumbnail), waiting for the rate
+    /// limit of the provider
+    fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>>;
+}
+
+/// Waits so the requests to a provider are at least `interval` apart
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub async fn wait(&self) {
+        let start = {
+            let mut next = self.next.lock().unwrap();
+            let start = (*next).max(Instant::now());
+            *next = start + self.interval;
+            start
+        };
+        tokio::time::sleep_until(start.into()).await;
+    }
+}
+
+/// Rate limited HTTP client of a provider
+struct Http {
+    client: Client,
+    limiter: RateLimiter,
+    headers: BTreeMap<String, String>,
+}
+
+impl Http {
+    async fn get(&self, url: &str) -> Result<Vec<u8>> {
+        self.limiter.wait().await;
+        tracing::info!(url, "Downloading...");
+        let mut request = self.client.get(url);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let failed = |e: reqwest::Error| {
+            tracing::error!(error.cause_chain=?e, error.message=%e, url, "Request failed.");
+            Error::FailedToDownload(url.to_string())
+        };
+        let response = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(failed)?;
+        read_body(response, url, MAX_RESPONSE_SIZE).await
+    }
+
+    async fn get_text(&self, url: &str) -> Result<String> {
+        let bytes = self.get(url).await?;
+        String::from_utf8(bytes).map_err(|_| Error::InvalidProviderResponse(url.to_string()))
+    }
+}
+
+/// Body of the response, failing if it is larger than `max_size` bytes (before
+/// reading it when the size is announced)
+async fn read_body(mut response: reqwest::Response, url: &str, max_size: u64) -> Result<Vec<u8>> {
+    let too_large = || {
+        tracing::error!(url, max_size, "Response too large.");
+        Error::FailedToDownload(url.to_string())
+    };
+    if response
+        .content_length()
+        .is_some_and(|size| size > max_size)
+    {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, url, "Request failed.");
+        Error::FailedToDownload(url.to_string())
+    })? {
+        if (body.len() + chunk.len()) as u64 > max_size {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+struct UrlListProvider {
+    name: String,
+    url: String,
+    http: Http,
+}
+
+impl Provider for UrlListProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<RemoteImage>>> {
+        Box::pin(async move { Ok(parse_url_list(&self.http.get_text(&self.url).await?)) })
+    }
+
+    fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(self.http.get(url))
+    }
+}
+
+struct FeedProvider {
+    name: String,
+    url: String,
+    http: Http,
+}
+
+impl Provider for FeedProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<RemoteImage>>> {
+        Box::pin(async move {
+            let content = self.http.get_text(&self.url).await?;
+            parse_feed(&content).ok_or(Error::InvalidProviderResponse(self.url.clone()))
+        })
+    }
+
+    fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(self.http.get(url))
+    }
+}
+
+struct JsonProvider {
+    name: String,
+    url: String,
+    fields: JsonFields,
+    http: Http,
+}
+
+impl Provider for JsonProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<RemoteImage>>> {
+        Box::pin(async move {
+            let content = self.http.get_text(&self.url).await?;
+            parse_json(&content, &self.fields)
+                .ok_or(Error::InvalidProviderResponse(self.url.clone()))
+        })
+    }
+
+    fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(self.http.get(url))
+    }
+}
+
+/// JSON pointers to the images of a JSON API response
+#[derive(Clone, Debug, Default)]
+pub struct JsonFields {
+    pub items: String,
+    pub image_url: String,
+    pub thumbnail_url: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub page_url: Option<String>,
+}
+
+/// Creates the provider described on the config
+pub fn build_provider(config: &ProviderConfig) -> Result<Arc<dyn Provider>> {
+    let client = Client::builder()
+        .user_agent(concat!("regolith-wallpaper/", env!("CARGO_PKG_VERSION")))
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| Error::UnexpectedError(e.to_string()))?;
+    let interval = config.min_interval.unwrap_or(DEFAULT_MIN_INTERVAL);
+    let interval = Duration::try_from_secs_f64(interval).map_err(|_| {
+        Error::InvalidConfig(format!(
+            "providers: `{}`: min_interval must be a number of seconds, not {}",
+            config.name, interval
+        ))
+    })?;
+    let http = |headers| Http {
+        client: client.clone(),
+        limiter: RateLimiter::new(interval),
+        headers,
+    };
+    let name = config.name.clone();
+    Ok(match &config.kind {
+        ProviderKind::UrlList { url } => Arc::new(UrlListProvider {
+            name,
+            url: url.clone(),
+            http: http(BTreeMap::new()),
+        }),
+        ProviderKind::Feed { url } => Arc::new(FeedProvider {
+            name,
+            url: url.clone(),
+            http: http(BTreeMap::new()),
+        }),
+        ProviderKind::Json {
+            url,
+            items,
+            image_url,
+            thumbnail_url,
+            title,
+            author,
+            page_url,
+            headers,
+        } => Arc::new(JsonProvider {
+            name,
+            url: url.clone(),
+            fields: JsonFields {
+                items: items.clone(),
+                image_url: image_url.clone(),
+                thumbnail_url: thumbnail_url.clone(),
+                title: title.clone(),
+                author: author.clone(),
+                page_url: page_url.clone(),
+            },
+            http: http(headers.clone()),
+        }),
+    })
+}
+
+/// An URL per line, skipping empty lines and `#` comments
+pub fn parse_url_list(content: &str) -> Vec<RemoteImage> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(RemoteImage::new)
+        .collect()
+}
+
+/// Images of the RSS items or Atom entries, from their enclosures or
+/// `media:content`. Returns `None` if the content is not valid XML.
+pub fn parse_feed(content: &str) -> Option<Vec<RemoteImage>> {
+    let document = roxmltree::Document::parse(content).ok()?;
+    let items = document
+        .descendants()
+        .filter(|node| node.is_element() && matches!(node.tag_name().name(), "item" | "entry"));
+    Some(items.filter_map(feed_item).collect())
+}
+
+fn feed_item(item: roxmltree::Node) -> Option<RemoteImage> {
+    let children = || item.children().filter(|node| node.is_element());
+    let child_text = |name: &str| {
+        children()
+            .find(|node| {
+                node.tag_name().name() == name
+                    && node.tag_name().namespace() != Some(MEDIA_RSS_NAMESPACE)
+            })
+            .and_then(|node| node.text())
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty())
+    };
+    let is_image = |node: &roxmltree::Node| {
+        node.attribute("type")
+            .is_none_or(|mime| mime.starts_with("image/"))
+    };
+    let media = |name: &str| {
+        item.descendants()
+            .find(|node| {
+                node.tag_name().namespace() == Some(MEDIA_RSS_NAMESPACE)
+                    && node.tag_name().name() == name
+                    && node
+                        .attribute("medium")
+                        .is_none_or(|medium| medium == "image")
+            })
+            .and_then(|node| node.attribute("url"))
+            .map(str::to_string)
+    };
+    let link = |rel: &str| {
+        children()
+            .find(|node| {
+                node.tag_name().name() == "link"
+                    && node.attribute("rel").unwrap_or("alternate") == rel
+            })
+            .and_then(|node| node.attribute("href"))
+            .map(str::to_string)
+    };
+    let url = children()
+        .find(|node| node.tag_name().name() == "enclosure" && is_image(node))
+        .and_then(|node| node.attribute("url").map(str::to_string))
+        .or_else(|| media("content"))
+        .or_else(|| {
+            children()
+                .find(|node| {
+                    node.tag_name().name() == "link"
+                        && node.attribute("rel") == Some("enclosure")
+                        && is_image(node)
+                })
+                .and_then(|node| node.attribute("href").map(str::to_string))
+        })?;
+    // Atom authors have a `name`, RSS ones are text
+    let author = children()
+        .find(|node| matches!(node.tag_name().name(), "author" | "creator"))
+        .and_then(|node| {
+            node.children()
+                .find(|node| node.tag_name().name() == "name")
+                .unwrap_or(node)
+                .text()
+        })
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty());
+    Some(RemoteImage {
+        url,
+        thumbnail_url: media("thumbnail"),
+        title: child_text("title"),
+        author,
+        page_url: link("alternate").or_else(|| child_text("link")),
+    })
+}
+
+/// Images of a JSON API response. Returns `None` if the content is not valid
+/// JSON or `items` doesn't point to an array.
+pub fn parse_json(content: &str, fields: &JsonFields) -> Option<Vec<RemoteImage>> {
+    let value = serde_json::from_str::<serde_json::Value>(content).ok()?;
+    let items = value.pointer(&fields.items)?.as_array()?;
+    let field = |item: &serde_json::Value, pointer: &Option<String>| {
+        pointer
+            .as_deref()
+            .and_then(|pointer| item.pointer(pointer))
+            .and_then(|value| value.as_str())
+            .map(str::to_string)
+    };
+    Some(
+        items
+            .iter()
+            .filter_map(|item| {
+                Some(RemoteImage {
+                    url: item.pointer(&fields.image_url)?.as_str()?.to_string(),
+                    thumbnail_url: field(item, &fields.thumbnail_url),
+                    title: field(item, &fields.title),
+                    author: field(item, &fields.author),
+                    page_url: field(item, &fields.page_url),
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Downloads the image into `folder`, validated (and named) like the imported
+/// files. Returns its path and source.
+pub async fn download_image(
+    provider: Arc<dyn Provider>,
+    image: RemoteImage,
+    folder: PathBuf,
+) -> Result<(PathBuf, ImageSource)> {
+    let bytes = provider.fetch(&image.url).await?;
+    let name = PathBuf::from(image.file_name());
+    let path = tokio::task::spawn_blocking(move || {
+        std::fs::create_dir_all(&folder).map_err(|_| Error::FailedToWriteFile(folder.clone()))?;
+        import_bytes(&bytes, &name, &folder, &ImportOptions::default())
+    })
+    .await
+    .map_err(|e| Error::UnexpectedError(e.to_string()))??;
+    tracing::info!(?path, url = image.url, "Image downloaded.");
+    Ok((path, image.source(provider.name())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Local HTTP server answering each path with its content (built from the
+    /// base URL), returns the base URL
+    async fn serve(routes: impl FnOnce(&str) -> Vec<(&'static str, Vec<u8>)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let routes = routes(&base);
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    return;
+                };
+                let mut request = vec![0; 4096];
+                let len = stream.read(&mut request).await.unwrap_or_default();
+                let request = String::from_utf8_lossy(&request[..len]);
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let response = match routes.iter().find(|(route, _)| *route == path) {
+                    Some((_, body)) => {
+                        let mut response = format!(
+                            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                            body.len()
+                        )
+                        .into_bytes();
+                        response.extend_from_slice(body);
+                        response
+                    }
+                    None => {
+                        b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                            .to_vec()
+                    }
+                };
+                let _ = stream.write_all(&response).await;
+            }
+        });
+        base
+    }
+
+    fn png() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::RgbImage::from_pixel(4, 4, image::Rgb([0, 0, 255]))
+            .write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn feeds_and_json_are_parsed() {
+        let rss = r#"<rss xmlns:media="http://search.yahoo.com/mrss/"><channel>
+            <item><title>Moon</title><link>https://example.com/moon</link>
+              <enclosure url="https://example.com/moon.jpg" type="image/jpeg"/></item>
+            <item><title>Podcast</title><enclosure url="https://example.com/a.mp3" type="audio/mpeg"/></item>
+            <item><title>Sun</title><media:content url="https://example.com/sun.png"/>
+              <media:thumbnail url="https://example.com/sun-small.png"/></item>
+            </channel></rss>"#;
+        let images = parse_feed(rss).unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(
+            images[0].page_url.as_deref(),
+            Some("https://example.com/moon")
+        );
+        assert_eq!(
+            images[1].thumbnail_url.as_deref(),
+            Some("https://example.com/sun-small.png")
+        );
+        let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom"><entry><title>Sea</title>
+            <author><name>Jane</name></author>
+            <link href="https://example.com/sea"/>
+            <link rel="enclosure" type="image/png" href="https://example.com/sea.png"/>
+            </entry></feed>"#;
+        let images = parse_feed(atom).unwrap();
+        assert_eq!(images[0].url, "https://example.com/sea.png");
+        assert_eq!(images[0].author.as_deref(), Some("Jane"));
+
+        let json = r#"{"results": [{"urls": {"full": "https://example.com/a.jpg"}, "user": {"name": "Joe"}}, {"id": 2}]}"#;
+        let fields = JsonFields {
+            items: "/results".into(),
+            image_url: "/urls/full".into(),
+            author: Some("/user/name".into()),
+            ..Default::default()
+        };
+        let images = parse_json(json, &fields).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].author.as_deref(), Some("Joe"));
+        assert_eq!(images[0].file_name(), "a.jpg");
+    }
+
+    #[tokio::test]
+    async fn providers_list_and_download_from_http() {
+        let base = serve(|base| {
+            let list = format!("# wallpapers\n{}/blue\n\n{}/missing.png\n", base, base);
+            vec![("/list.txt", list.into_bytes()), ("/blue", png())]
+        })
+        .await;
+        let config = ProviderConfig {
+            name: "test".into(),
+            kind: ProviderKind::UrlList {
+                url: format!("{}/list.txt", base),
+            },
+            min_interval: Some(0.2),
+            folder: None,
+        };
+        let provider = build_provider(&config).unwrap();
+        let images = provider.list().await.unwrap();
+        assert_eq!(images.len(), 2);
+
+        let root = tempfile::tempdir().unwrap();
+        let folder = root.path().join("downloads");
+        let start = Instant::now();
+        let downloaded = download_image(provider.clone(), images[0].clone(), folder.clone()).await;
+        let missing = download_image(provider, images[1].clone(), folder.clone()).await;
+        let elapsed = start.elapsed();
+        let (path, source) = downloaded.unwrap();
+        // No extension on the URL, it is taken from the content
+        assert_eq!(path, folder.join("blue.png"));
+        assert_eq!(source.provider, "test");
+        assert!(matches!(missing, Err(Error::FailedToDownload(_))));
+        // The downloads wait for the rate limit, 0.2 s after the list and
+        // after each other
+        assert!(elapsed >= Duration::from_millis(350), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn large_responses_are_dropped() {
+        let base = serve(|_| vec![("/big", vec![0; 2048])]).await;
+        let url = format!("{}/big", base);
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(read_body(response, &url, 4096).await.unwrap().len(), 2048);
+        let response = reqwest::get(&url).await.unwrap();
+        assert!(matches!(
+            read_body(response, &url, 1024).await,
+            Err(Error::FailedToDownload(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_intervals_are_config_errors() {
+        for interval in [-1.0, f64::NAN, f64::INFINITY] {
+            let config = ProviderConfig {
+                name: "test".into(),
+                kind: ProviderKind::UrlList {
+                    url: "http://localhost/list.txt".into(),
+                },
+                min_interval: Some(interval),
+                folder: None,
+            };
+            assert!(matches!(
+                build_provider(&config),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+    }
+}
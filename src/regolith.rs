@@ -0,0 +1,336 @@
+use crate::{expand_home_dir, extract_if_archived, get_cache_dir, Error, Result};
+use fs2::FileExt;
+use std::fs::{self, create_dir_all, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::fs::read_to_string;
+
+/// Number of daily backups of the Regolith config file kept
+pub const MAX_BACKUPS: usize = 10;
+
+/// Path of the current Regolith config file
+pub fn get_regolith_config_path() -> PathBuf {
+    expand_home_dir("~/.config/regolith3/Xresources")
+}
+
+async fn read_regolith_config() -> Result<String> {
+    let path = get_regolith_config_path();
+    if !path.exists() {
+        return Err(Error::NoRegConfigFile);
+    }
+    read_to_string(path).await.map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to read file.");
+        Error::FailedReadRegConfigFile
+    })
+}
+
+/// Current wallpaper, from the `regolith.wallpaper.file` setting
+pub async fn load_regolith_config() -> Result<PathBuf> {
+    read_regolith_config()
+        .await?
+        .lines()
+        .find(|line| line.starts_with("regolith.wallpaper.file:"))
+        .ok_or(Error::NoWallpaperOnRegConfigFile)?
+        .split(':')
+        .nth(1)
+        .ok_or(Error::NoWallpaperOnRegConfigFile)
+        .map(|path| expand_home_dir(path.trim()))
+}
+
+/// Name of the active Regolith look, from the `regolith.look.path` setting or
+/// the look `#include` on the config file
+pub async fn load_regolith_look() -> Result<String> {
+    let content = read_regolith_config().await?;
+    parse_regolith_look(&content).ok_or(Error::NoLookOnRegConfigFile)
+}
+
+fn parse_regolith_look(content: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        if let Some(look) = line.strip_prefix("regolith.look:") {
+            return Some(look.trim().to_string());
+        }
+        let (_, rest) = line.split_once("regolith-look/")?;
+        rest.split(['/', '"'])
+            .next()
+            .filter(|look| !look.is_empty())
+            .map(str::to_string)
+    })
+}
+
+/// Sets the path on the current regolith config file, if success returns the
+/// setted image path
+pub async fn set_wallpaper_on_config(path: PathBuf) -> Result<PathBuf> {
+    let path = extract_if_archived(path).await?;
+    let wallpaper = path.clone();
+    update_regolith_config(move |content| set_wallpaper_line(content, &wallpaper)).await?;
+    refresh_regolith().await?;
+    Ok(path)
+}
+
+/// Replaces the `regolith.wallpaper.file` setting, adding it if missing
+fn set_wallpaper_line(content: &str, path: &Path) -> String {
+    let setting = format!("regolith.wallpaper.file: {}", path.display());
+    let mut found = false;
+    let mut lines = content
+        .lines()
+        .map(|line| {
+            if !found && line.starts_with("regolith.wallpaper.file:") {
+                found = true;
+                setting.clone()
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+    if !found {
+        lines.push(setting);
+    }
+    let mut new_content = lines.join("\n");
+    if content.ends_with('\n') || !found {
+        new_content.push('\n');
+    }
+    new_content
+}
+
+async fn refresh_regolith() -> Result<()> {
+    let exit_status = tokio::process::Command::new("/usr/bin/regolith-look")
+        .arg("refresh")
+        .status()
+        .await
+        .map_err(|e| {
+            tracing::error!(error.cause_chain=?e, error.message=%e);
+            Error::FailedToRunRefresh
+        })?;
+    if exit_status.success() {
+        Ok(())
+    } else {
+        tracing::error!(
+            "Failed to run command, exited with code: {:?}",
+            exit_status.code()
+        );
+        Err(Error::FailedToRunRefresh)
+    }
+}
+
+/// Updates the Regolith config file through [`RegolithConfigFile`]
+async fn update_regolith_config(f: impl FnOnce(&str) -> String + Send + 'static) -> Result<()> {
+    let file = RegolithConfigFile::current()?;
+    tokio::task::spawn_blocking(move || file.update(f))
+        .await
+        .map_err(|e| Error::UnexpectedError(e.to_string()))?
+}
+
+/// Backups of the Regolith config file, oldest first
+pub fn list_regolith_backups() -> Result<Vec<PathBuf>> {
+    RegolithConfigFile::current()?.backups()
+}
+
+/// Restores a backup of the Regolith config file (the latest one, or the one
+/// of `date`, e.g.: `2024-05-17`), returns the restored backup
+pub async fn restore_regolith_backup(date: Option<String>) -> Result<PathBuf> {
+    let file = RegolithConfigFile::current()?;
+    let backups = file.backups()?;
+    let backup = match date {
+        Some(date) => backups
+            .into_iter()
+            .find(|backup| backup.extension().is_some_and(|ext| ext == date.as_str()))
+            .ok_or(Error::NoBackup(date))?,
+        None => backups
+            .into_iter()
+            .last()
+            .ok_or(Error::NoBackup("any date".into()))?,
+    };
+    let content = read_to_string(&backup)
+        .await
+        .map_err(|_| Error::FailedToRead(backup.clone()))?;
+    tokio::task::spawn_blocking(move || file.update(move |_| content))
+        .await
+        .map_err(|e| Error::UnexpectedError(e.to_string()))??;
+    refresh_regolith().await?;
+    tracing::info!(?backup, "Regolith config restored.");
+    Ok(backup)
+}
+
+/// The Regolith config file is shared with the user, other tools, and other
+/// instances of this program (e.g.: a cron `--random-pick` while the GUI
+/// applies an image). Updates hold an advisory lock, keep a backup of the
+/// previous content per day, and replace the file atomically, so a crash
+/// can't leave it truncated.
+#[derive(Debug, Clone)]
+pub struct RegolithConfigFile {
+    pub path: PathBuf,
+    pub lock_path: PathBuf,
+    pub backups_dir: PathBuf,
+}
+
+impl RegolithConfigFile {
+    pub fn current() -> Result<Self> {
+        let cache_dir = get_cache_dir()?;
+        Ok(Self {
+            path: get_regolith_config_path(),
+            lock_path: cache_dir.join("Xresources.lock"),
+            backups_dir: cache_dir.join("backups"),
+        })
+    }
+
+    /// Replaces the content with the result of `f`, holding the lock while
+    /// reading and writing.
+    pub fn update(&self, f: impl FnOnce(&str) -> String) -> Result<()> {
+        if let Some(folder) = self.lock_path.parent() {
+            create_dir_all(folder).map_err(|_| Error::FailedToWriteFile(folder.into()))?;
+        }
+        let lock = File::create(&self.lock_path)
+            .map_err(|_| Error::FailedToWriteFile(self.lock_path.clone()))?;
+        lock.lock_exclusive().map_err(|e| {
+            tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to lock the regolith config.");
+            Error::FailedToWriteFile(self.path.clone())
+        })?;
+        // A symlink (e.g.: from a dotfiles repository) is kept, its target is
+        // replaced instead
+        let path = fs::canonicalize(&self.path).map_err(|_| Error::NoRegConfigFile)?;
+        let content = fs::read_to_string(&path).map_err(|e| {
+            tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to read file.");
+            Error::FailedReadRegConfigFile
+        })?;
+        let new_content = f(&content);
+        if new_content == content {
+            return Ok(());
+        }
+        self.backup(&content)?;
+        let tmp_path = path.with_file_name(format!(
+            ".{}.tmp-{}",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            std::process::id()
+        ));
+        let write_tmp = || -> std::io::Result<()> {
+            let mut tmp = File::create(&tmp_path)?;
+            tmp.write_all(new_content.as_bytes())?;
+            tmp.set_permissions(fs::metadata(&path)?.permissions())?;
+            tmp.sync_all()?;
+            fs::rename(&tmp_path, &path)
+        };
+        write_tmp().map_err(|e| {
+            tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to write file.");
+            let _ = fs::remove_file(&tmp_path);
+            Error::FailedToWriteFile(path.clone())
+        })
+    }
+
+    /// Copies the content before the first change of the day to
+    /// `Xresources.<date>`, keeping the latest [`MAX_BACKUPS`]
+    fn backup(&self, content: &str) -> Result<()> {
+        create_dir_all(&self.backups_dir)
+            .map_err(|_| Error::FailedToWriteFile(self.backups_dir.clone()))?;
+        let date = chrono::Local::now().format("%Y-%m-%d");
+        let backup = self.backups_dir.join(format!("Xresources.{}", date));
+        if backup.exists() {
+            return Ok(());
+        }
+        fs::write(&backup, content).map_err(|_| Error::FailedToWriteFile(backup.clone()))?;
+        tracing::info!(?backup, "Regolith config backed up.");
+        let backups = self.backups()?;
+        for old in &backups[..backups.len().saturating_sub(MAX_BACKUPS)] {
+            let _ = fs::remove_file(old);
+        }
+        Ok(())
+    }
+
+    /// Backups, oldest first
+    pub fn backups(&self) -> Result<Vec<PathBuf>> {
+        if !self.backups_dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut backups = fs::read_dir(&self.backups_dir)
+            .map_err(|_| Error::FailedToRead(self.backups_dir.clone()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with("Xresources."))
+            })
+            .collect::<Vec<_>>();
+        backups.sort();
+        Ok(backups)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_regolith_look_works() {
+        let content = "#include \"/usr/share/regolith-look/nord/root\"\nfoo: bar";
+        assert_eq!(parse_regolith_look(content), Some("nord".to_string()));
+        let content = "regolith.look.path: /usr/share/regolith-look/ayu-light";
+        assert_eq!(parse_regolith_look(content), Some("ayu-light".to_string()));
+        assert_eq!(parse_regolith_look("foo: bar"), None);
+    }
+
+    #[test]
+    fn set_wallpaper_line_works() {
+        let content = "foo: bar\nregolith.wallpaper.file: /old.png\n";
+        assert_eq!(
+            set_wallpaper_line(content, Path::new("/new.png")),
+            "foo: bar\nregolith.wallpaper.file: /new.png\n"
+        );
+        assert_eq!(
+            set_wallpaper_line("foo: bar", Path::new("/new.png")),
+            "foo: bar\nregolith.wallpaper.file: /new.png\n"
+        );
+    }
+
+    /// Config file linked from a dotfiles folder, inside a temporary folder
+    fn linked_config() -> (tempfile::TempDir, RegolithConfigFile) {
+        let root = tempfile::tempdir().unwrap();
+        let dotfiles = root.path().join("dotfiles");
+        fs::create_dir_all(&dotfiles).unwrap();
+        fs::write(dotfiles.join("Xresources"), "a: 1\n").unwrap();
+        std::os::unix::fs::symlink(dotfiles.join("Xresources"), root.path().join("Xresources"))
+            .unwrap();
+        let file = RegolithConfigFile {
+            path: root.path().join("Xresources"),
+            lock_path: root.path().join("Xresources.lock"),
+            backups_dir: root.path().join("backups"),
+        };
+        (root, file)
+    }
+
+    #[test]
+    fn config_updates_keep_symlinks() {
+        let (root, file) = linked_config();
+        file.update(|content| content.replace('1', "2")).unwrap();
+        assert_eq!(fs::read_to_string(&file.path).unwrap(), "a: 2\n");
+        let is_symlink = fs::symlink_metadata(&file.path)
+            .unwrap()
+            .file_type()
+            .is_symlink();
+        assert!(is_symlink);
+        // The temporary file is renamed over the target
+        let leftovers = fs::read_dir(root.path().join("dotfiles")).unwrap().count();
+        assert_eq!(leftovers, 1);
+    }
+
+    #[test]
+    fn config_updates_are_backed_up_once_a_day() {
+        let (_root, file) = linked_config();
+        file.update(|content| content.replace('1', "2")).unwrap();
+        file.update(|content| content.replace('2', "3")).unwrap();
+        // Only the content before the first change of the day is kept
+        let backups = file.backups().unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), "a: 1\n");
+    }
+
+    #[test]
+    fn unchanged_config_is_not_backed_up() {
+        let (_root, file) = linked_config();
+        file.update(|content| content.to_string()).unwrap();
+        assert!(file.backups().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn load_regolith_config_works() {
+        let res = load_regolith_config().await;
+        println!("{:?}", res);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Max aspect ratio difference (relative) before warning about a mismatch
+const ASPECT_TOLERANCE: f32 = 0.1;
+
+/// Size in pixels, written as `WIDTHxHEIGHT` (e.g.: `2560x1440`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Resolution {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height.max(1) as f32
+    }
+
+    /// How much the image needs to be scaled up to fill `screen`, 1.0 or less
+    /// means there is no upscaling
+    pub fn fill_scale(&self, screen: &Resolution) -> f32 {
+        let x = screen.width as f32 / self.width.max(1) as f32;
+        let y = screen.height as f32 / self.height.max(1) as f32;
+        x.max(y)
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+impl FromStr for Resolution {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("Invalid resolution `{}`, expected `WIDTHxHEIGHT`.", s);
+        let (width, height) = s.trim().split_once('x').ok_or_else(invalid)?;
+        let width = width.trim().parse().map_err(|_| invalid())?;
+        let height = height.trim().parse().map_err(|_| invalid())?;
+        Ok(Self { width, height })
+    }
+}
+
+impl TryFrom<String> for Resolution {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Resolution> for String {
+    fn from(resolution: Resolution) -> Self {
+        resolution.to_string()
+    }
+}
+
+/// Information shown on the image tiles
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResolutionBadge {
+    FourK,
+    EightK,
+    /// The image needs to be scaled up by this factor to fill the screen
+    Upscaled(f32),
+    /// The image will be cropped a lot when filling the screen
+    AspectMismatch,
+}
+
+impl ResolutionBadge {
+    /// Whether the badge is a warning (shown in a different color)
+    pub fn is_warning(&self) -> bool {
+        !matches!(self, ResolutionBadge::FourK | ResolutionBadge::EightK)
+    }
+}
+
+impl fmt::Display for ResolutionBadge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolutionBadge::FourK => write!(f, "4K"),
+            ResolutionBadge::EightK => write!(f, "8K"),
+            ResolutionBadge::Upscaled(scale) => write!(f, "upscaled ×{:.1}", scale),
+            ResolutionBadge::AspectMismatch => write!(f, "aspect mismatch"),
+        }
+    }
+}
+
+pub fn resolution_badges(image: Resolution, screen: Option<Resolution>) -> Vec<ResolutionBadge> {
+    let mut badges = Vec::new();
+    let (long, short) = (image.width.max(image.height), image.width.min(image.height));
+    if long >= 7680 && short >= 4320 {
+        badges.push(ResolutionBadge::EightK);
+    } else if long >= 3840 && short >= 2160 {
+        badges.push(ResolutionBadge::FourK);
+    }
+    if let Some(screen) = screen {
+        let scale = image.fill_scale(&screen);
+        // Less than 5% is not noticeable
+        if scale > 1.05 {
+            badges.push(ResolutionBadge::Upscaled(scale));
+        }
+        let aspect_diff =
+            (image.aspect_ratio() - screen.aspect_ratio()).abs() / screen.aspect_ratio();
+        if aspect_diff > ASPECT_TOLERANCE {
+            badges.push(ResolutionBadge::AspectMismatch);
+        }
+    }
+    badges
+}
+
+/// Whether the image is smaller than the screen on any dimension
+pub fn is_smaller_than(image: Resolution, screen: Resolution) -> bool {
+    image.width < screen.width || image.height < screen.height
+}
+
+/// The configured screen resolution, or the one detected with `xrandr`
+pub async fn get_screen_resolution(configured: Option<Resolution>) -> Result<Resolution> {
+    match configured {
+        Some(resolution) => Ok(resolution),
+        None => detect_screen_resolution().await,
+    }
+}
+
+/// Detects the screen resolution from `xrandr`, using the primary output (or
+/// the largest one if there is no primary)
+pub async fn detect_screen_resolution() -> Result<Resolution> {
+    let output = tokio::process::Command::new("xrandr")
+        .arg("--current")
+        .output()
+        .await
+        .map_err(|e| {
+            tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to run xrandr.");
+            Error::UnexpectedError(format!("Failed to run xrandr: {}", e))
+        })?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_xrandr(&stdout).ok_or(Error::UnexpectedError(
+        "No connected output found on xrandr.".into(),
+    ))
+}
+
+/// Parses lines like `DP-1 connected primary 2560x1440+0+0 (...)`
+fn parse_xrandr(output: &str) -> Option<Resolution> {
+    let outputs = output
+        .lines()
+        .filter(|line| line.contains(" connected"))
+        .filter_map(|line| {
+            let primary = line.contains(" primary ");
+            line.split_whitespace()
+                .find_map(|word| {
+                    let (size, _) = word.split_once('+')?;
+                    size.parse::<Resolution>().ok()
+                })
+                .map(|resolution| (primary, resolution))
+        })
+        .collect::<Vec<_>>();
+    outputs
+        .iter()
+        .find(|(primary, _)| *primary)
+        .or_else(|| {
+            outputs
+                .iter()
+                .max_by_key(|(_, resolution)| resolution.width * resolution.height)
+        })
+        .map(|(_, resolution)| *resolution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_xrandr_prefers_primary() {
+        let output = "Screen 0: minimum 8 x 8, current 6400 x 2160, maximum 32767 x 32767
+HDMI-1 connected 3840x2160+2560+0 (normal left inverted right x axis y axis) 600mm x 340mm
+   3840x2160     60.00*+
+DP-1 connected primary 2560x1440+0+0 (normal left inverted right x axis y axis) 597mm x 336mm
+DP-2 disconnected (normal left inverted right x axis y axis)";
+        assert_eq!(parse_xrandr(output), Some(Resolution::new(2560, 1440)));
+        let output = output.replace(" primary", "");
+        assert_eq!(parse_xrandr(&output), Some(Resolution::new(3840, 2160)));
+    }
+
+    #[test]
+    fn badges() {
+        let screen = Some(Resolution::new(2560, 1440));
+        assert_eq!(
+            resolution_badges(Resolution::new(3840, 2160), screen),
+            vec![ResolutionBadge::FourK]
+        );
+        assert_eq!(
+            resolution_badges(Resolution::new(1280, 720), screen),
+            vec![ResolutionBadge::Upscaled(2.0)]
+        );
+        assert_eq!(
+            resolution_badges(Resolution::new(3000, 3000), screen),
+            vec![ResolutionBadge::AspectMismatch]
+        );
+        assert_eq!(ResolutionBadge::Upscaled(1.84).to_string(), "upscaled ×1.8");
+        assert_eq!("1920x1080".parse(), Ok(Resolution::new(1920, 1080)));
+    }
+}
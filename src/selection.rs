@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Selected images of the gallery, with the anchor for range selections and
+/// the cursor moved with the keyboard
+#[derive(Debug, Default)]
+pub struct Selection {
+    paths: HashSet<PathBuf>,
+    anchor: Option<PathBuf>,
+    cursor: Option<PathBuf>,
+}
+
+impl Selection {
+    pub fn contains(&self, path: &Path) -> bool {
+        self.paths.contains(path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn cursor(&self) -> Option<&Path> {
+        self.cursor.as_deref()
+    }
+
+    /// Selected paths, in the given order
+    pub fn paths_in<'a>(&self, order: &[&'a Path]) -> Vec<&'a Path> {
+        order
+            .iter()
+            .copied()
+            .filter(|path| self.paths.contains(*path))
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.paths.clear();
+        self.anchor = self.cursor.clone();
+    }
+
+    /// Moves the cursor (and the anchor) to the image
+    pub fn focus(&mut self, path: &Path) {
+        self.cursor = Some(path.to_path_buf());
+        self.anchor = self.cursor.clone();
+    }
+
+    /// Adds or removes an image (Ctrl + click)
+    pub fn toggle(&mut self, path: &Path) {
+        if !self.paths.remove(path) {
+            self.paths.insert(path.to_path_buf());
+        }
+        self.focus(path);
+    }
+
+    /// Selects the images between the anchor and `path` (Shift + click)
+    pub fn select_range(&mut self, order: &[&Path], path: &Path) {
+        let end = order.iter().position(|p| *p == path);
+        let start = self
+            .anchor
+            .as_deref()
+            .and_then(|anchor| order.iter().position(|p| *p == anchor))
+            .or(end);
+        if let (Some(start), Some(end)) = (start, end) {
+            let (from, to) = (start.min(end), start.max(end));
+            self.paths = order[from..=to].iter().map(|p| p.to_path_buf()).collect();
+        }
+        self.cursor = Some(path.to_path_buf());
+    }
+
+    pub fn select_all(&mut self, order: &[&Path]) {
+        self.paths = order.iter().map(|p| p.to_path_buf()).collect();
+    }
+
+    /// Moves the cursor by `delta` images, extending the selection from the
+    /// anchor if `extend` (Shift + arrows). Returns the index of the cursor.
+    pub fn move_cursor(&mut self, order: &[&Path], delta: isize, extend: bool) -> Option<usize> {
+        if order.is_empty() {
+            return None;
+        }
+        let index = match self
+            .cursor
+            .as_deref()
+            .and_then(|cursor| order.iter().position(|p| *p == cursor))
+        {
+            Some(index) => index.saturating_add_signed(delta).min(order.len() - 1),
+            None => 0,
+        };
+        if extend {
+            self.select_range(order, order[index]);
+        } else {
+            self.focus(order[index]);
+        }
+        Some(index)
+    }
+
+    /// Keeps the selection of a renamed or moved image
+    pub fn move_path(&mut self, from: &Path, to: &Path) {
+        if self.paths.remove(from) {
+            self.paths.insert(to.to_path_buf());
+        }
+        for path in [&mut self.anchor, &mut self.cursor].into_iter().flatten() {
+            if path == from {
+                *path = to.to_path_buf();
+            }
+        }
+    }
+
+    /// Drops the images that are no longer in the gallery
+    pub fn retain(&mut self, mut keep: impl FnMut(&Path) -> bool) {
+        self.paths.retain(|path| keep(path));
+    }
+}
+
+/// Applies the images one after the other, cycling back to the first
+#[derive(Debug, Clone)]
+pub struct Slideshow {
+    pub paths: Vec<PathBuf>,
+    next: usize,
+}
+
+impl Slideshow {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        Self { paths, next: 0 }
+    }
+
+    pub fn next_path(&mut self) -> Option<PathBuf> {
+        if self.paths.is_empty() {
+            return None;
+        }
+        let path = self.paths[self.next % self.paths.len()].clone();
+        self.next = (self.next + 1) % self.paths.len();
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_and_keyboard_selection() {
+        let paths = ["/a", "/b", "/c", "/d", "/e"].map(PathBuf::from);
+        let order = paths.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+        let mut selection = Selection::default();
+        selection.toggle(&paths[1]);
+        selection.select_range(&order, &paths[3]);
+        assert_eq!(selection.paths_in(&order), &order[1..=3]);
+        // The anchor stays, so the range can shrink
+        selection.move_cursor(&order, -1, true);
+        assert_eq!(selection.paths_in(&order), &order[1..=2]);
+        assert_eq!(selection.move_cursor(&order, 10, false), Some(4));
+        assert_eq!(selection.len(), 2);
+        selection.move_cursor(&order, -2, true);
+        assert_eq!(selection.paths_in(&order), &order[2..=4]);
+        selection.move_path(&paths[2], Path::new("/z"));
+        assert!(selection.contains(Path::new("/z")));
+    }
+
+    #[test]
+    fn slideshow_cycles() {
+        let mut slideshow = Slideshow::new(vec![PathBuf::from("/a"), PathBuf::from("/b")]);
+        let next = (0..3)
+            .filter_map(|_| slideshow.next_path())
+            .collect::<Vec<_>>();
+        assert_eq!(next, ["/a", "/b", "/a"].map(PathBuf::from));
+    }
+}
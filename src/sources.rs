@@ -0,0 +1,89 @@
+use crate::{get_config_dir, write_atomic, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+/// Where a downloaded image comes from, for attribution
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageSource {
+    /// Name of the provider on the config file
+    pub provider: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Page of the image on the provider website
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_url: Option<String>,
+}
+
+impl ImageSource {
+    /// E.g.: "Moonrise by Jane Doe (unsplash)"
+    pub fn attribution(&self) -> String {
+        let mut attribution = self.title.clone().unwrap_or_else(|| self.url.clone());
+        if let Some(author) = &self.author {
+            attribution.push_str(&format!(" by {}", author));
+        }
+        attribution.push_str(&format!(" ({})", self.provider));
+        attribution
+    }
+}
+
+/// Sources of the downloaded images, stored on
+/// `~/.config/regolith-wallpaper/sources.yaml`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SourceStore {
+    #[serde(default)]
+    pub sources: BTreeMap<PathBuf, ImageSource>,
+}
+
+impl SourceStore {
+    /// Whether an image was already downloaded from `url`
+    pub fn contains_url(&self, url: &str) -> bool {
+        self.sources.values().any(|source| source.url == url)
+    }
+
+    /// Keeps the source of a file that was renamed or moved
+    pub fn move_path(&mut self, from: &Path, to: &Path) -> bool {
+        match self.sources.remove(from) {
+            Some(source) => {
+                self.sources.insert(to.to_path_buf(), source);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn get_sources_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("sources.yaml"))
+}
+
+pub fn load_sources() -> Result<SourceStore> {
+    let path = get_sources_path()?;
+    if !path.exists() {
+        return Ok(SourceStore::default());
+    }
+    let content = read_to_string(&path).map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to read file.");
+        Error::FailedToRead(path.clone())
+    })?;
+    serde_yaml::from_str(&content).map_err(|e| Error::UnexpectedError(e.to_string()))
+}
+
+pub async fn save_sources(sources: SourceStore) -> Result<()> {
+    let content = serde_yaml::to_string(&sources).map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to serialize content.");
+        Error::UnexpectedError(e.to_string())
+    })?;
+    let path = get_sources_path()?;
+    write_atomic(&path, content).await.map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to write file.");
+        Error::FailedToWriteFile(path.clone())
+    })?;
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use iced::{
-    widget::{container, text},
+    widget::{container, horizontal_space, row, text},
     Background, Color, Element, Length,
 };
 
@@ -10,19 +10,24 @@ pub enum StatusBar {
 }
 
 impl StatusBar {
-    pub fn view<'a, T: 'a>(&'a self) -> Element<T> {
+    /// Message on the left, `info` (e.g.: the selection count) on the right
+    pub fn view<'a, T: 'a>(&'a self, info: Option<String>) -> Element<'a, T> {
         let text = match self {
             StatusBar::None => text(""),
             StatusBar::Ok(s) => text(s),
             StatusBar::Error(s) => text(s).style(Color::from_rgb(0.9, 0.2, 0.2)),
         };
-        container(text)
-            .padding([5, 10])
-            .width(Length::Fill)
-            .style(|_: &_| container::Appearance {
-                background: Some(Background::Color(Color::from_rgb(0.16, 0.21, 0.2))),
-                ..Default::default()
-            })
-            .into()
+        container(row!(
+            text,
+            horizontal_space(Length::Fill),
+            iced::widget::text(info.unwrap_or_default())
+        ))
+        .padding([5, 10])
+        .width(Length::Fill)
+        .style(|_: &_| container::Appearance {
+            background: Some(Background::Color(Color::from_rgb(0.16, 0.21, 0.2))),
+            ..Default::default()
+        })
+        .into()
     }
 }
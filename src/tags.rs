@@ -0,0 +1,204 @@
+use crate::{get_config_dir, write_atomic, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+/// Tag of the images marked as favorite
+pub const FAVORITE_TAG: &str = "favorite";
+
+/// Tags (collections) assigned to wallpapers, stored on
+/// `~/.config/regolith-wallpaper/tags.yaml`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TagStore {
+    #[serde(default)]
+    pub tags: BTreeMap<PathBuf, BTreeSet<String>>,
+}
+
+impl TagStore {
+    /// Tags set by the user plus the ones inferred from the sub-folders between
+    /// `root` and the image
+    pub fn tags_for(&self, path: &Path, root: Option<&Path>) -> BTreeSet<String> {
+        let mut tags = self.tags.get(path).cloned().unwrap_or_default();
+        if let Some(root) = root {
+            tags.extend(folder_tags(root, path));
+        }
+        tags
+    }
+
+    pub fn add_tag<'a>(&mut self, paths: impl IntoIterator<Item = &'a PathBuf>, tag: &str) {
+        let tag = normalize_tag(tag);
+        if tag.is_empty() {
+            return;
+        }
+        for path in paths {
+            self.tags
+                .entry(path.clone())
+                .or_default()
+                .insert(tag.clone());
+        }
+    }
+
+    /// Whether the user tagged the image with `tag`
+    pub fn has_tag(&self, path: &Path, tag: &str) -> bool {
+        self.tags.get(path).is_some_and(|tags| tags.contains(tag))
+    }
+
+    pub fn remove_tag<'a>(&mut self, paths: impl IntoIterator<Item = &'a PathBuf>, tag: &str) {
+        let tag = normalize_tag(tag);
+        for path in paths {
+            if let Some(tags) = self.tags.get_mut(path) {
+                tags.remove(&tag);
+                if tags.is_empty() {
+                    self.tags.remove(path);
+                }
+            }
+        }
+    }
+
+    /// Keeps the tags of a file that was renamed or moved, returns whether it
+    /// had any
+    pub fn move_path(&mut self, from: &Path, to: &Path) -> bool {
+        match self.tags.remove(from) {
+            Some(tags) => {
+                self.tags.insert(to.to_path_buf(), tags);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// All the collections available for the given images
+    pub fn collections<'a>(
+        &self,
+        paths: impl IntoIterator<Item = &'a PathBuf>,
+        root: Option<&Path>,
+    ) -> BTreeSet<String> {
+        paths
+            .into_iter()
+            .flat_map(|path| self.tags_for(path, root))
+            .collect()
+    }
+
+    pub fn matches(&self, path: &Path, root: Option<&Path>, collection: &Collection) -> bool {
+        match collection {
+            Collection::All => true,
+            Collection::Named(name) => self.tags_for(path, root).contains(name),
+        }
+    }
+}
+
+/// Filter used to limit the images to a named collection
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Collection {
+    #[default]
+    All,
+    Named(String),
+}
+
+impl From<Option<String>> for Collection {
+    fn from(name: Option<String>) -> Self {
+        match name {
+            Some(name) => Self::Named(normalize_tag(&name)),
+            None => Self::All,
+        }
+    }
+}
+
+impl fmt::Display for Collection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Collection::All => write!(f, "All wallpapers"),
+            Collection::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Tags inferred from the sub-folder names, e.g.: `<root>/space/nebula/img.png`
+/// gets the tags `space` and `nebula`
+pub fn folder_tags(root: &Path, path: &Path) -> Vec<String> {
+    path.parent()
+        .and_then(|parent| parent.strip_prefix(root).ok())
+        .map(|relative| {
+            relative
+                .components()
+                .filter_map(|component| component.as_os_str().to_str())
+                .map(normalize_tag)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn get_tags_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("tags.yaml"))
+}
+
+pub fn load_tags() -> Result<TagStore> {
+    let path = get_tags_path()?;
+    if !path.exists() {
+        return Ok(TagStore::default());
+    }
+    let content = read_to_string(&path).map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to read file.");
+        Error::FailedToRead(path.clone())
+    })?;
+    serde_yaml::from_str(&content).map_err(|e| Error::UnexpectedError(e.to_string()))
+}
+
+pub async fn save_tags(tags: TagStore) -> Result<()> {
+    let content = serde_yaml::to_string(&tags).map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to serialize content.");
+        Error::UnexpectedError(e.to_string())
+    })?;
+    let path = get_tags_path()?;
+    if let Some(folder) = path.parent() {
+        tokio::fs::create_dir_all(folder)
+            .await
+            .map_err(|_| Error::FailedToWriteFile(folder.into()))?;
+    }
+    write_atomic(&path, content).await.map_err(|e| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, ?path, "Failed to write file.");
+        Error::FailedToWriteFile(path.clone())
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folder_tags_are_inferred_from_sub_folders() {
+        let root = Path::new("/wallpapers");
+        let path = Path::new("/wallpapers/Space/nebula/img.png");
+        assert_eq!(folder_tags(root, path), vec!["space", "nebula"]);
+        assert!(folder_tags(root, Path::new("/wallpapers/img.png")).is_empty());
+        assert!(folder_tags(root, Path::new("/other/img.png")).is_empty());
+    }
+
+    #[test]
+    fn collection_filter_uses_stored_and_inferred_tags() {
+        let root = Path::new("/wallpapers");
+        let minimal = PathBuf::from("/wallpapers/a.png");
+        let space = PathBuf::from("/wallpapers/space/b.png");
+        let mut store = TagStore::default();
+        store.add_tag([&minimal], " Minimal ");
+
+        let collection = Collection::Named("minimal".into());
+        assert!(store.matches(&minimal, Some(root), &collection));
+        assert!(!store.matches(&space, Some(root), &collection));
+        let collection = Collection::Named("space".into());
+        assert!(store.matches(&space, Some(root), &collection));
+        assert!(store.matches(&minimal, Some(root), &Collection::All));
+
+        store.remove_tag([&minimal], " MINIMAL");
+        assert!(store.tags.is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{imageops::FilterType, DynamicImage, ImageFormat, RgbaImage};
+use std::fmt::Write;
+use std::io::Cursor;
+
+/// Size in pixels of a terminal cell when the terminal doesn't tell
+pub const DEFAULT_CELL_SIZE: (u16, u16) = (8, 16);
+
+/// Bytes of base64 sent on each kitty graphics escape sequence
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// Deletes every image shown with the kitty graphics protocol
+pub const KITTY_CLEAR: &str = "\x1b_Ga=d,q=2\x1b\\";
+
+/// How images are drawn on the terminal
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum GraphicsProtocol {
+    Kitty,
+    Sixel,
+    /// `▀` characters with the top and bottom pixels as colors, works on any
+    /// terminal with true colors
+    Blocks,
+}
+
+impl GraphicsProtocol {
+    /// Guesses the protocol supported by the terminal from its environment
+    /// variables
+    pub fn detect() -> Self {
+        let var = |name: &str| std::env::var(name).unwrap_or_default().to_lowercase();
+        let term = var("TERM");
+        let program = var("TERM_PROGRAM");
+        if std::env::var_os("KITTY_WINDOW_ID").is_some()
+            || term.contains("kitty")
+            || term.contains("ghostty")
+            || ["wezterm", "ghostty"].contains(&program.as_str())
+        {
+            Self::Kitty
+        } else if term.contains("sixel")
+            || term.starts_with("foot")
+            || term.starts_with("mlterm")
+            || program == "iterm.app"
+        {
+            Self::Sixel
+        } else {
+            Self::Blocks
+        }
+    }
+}
+
+/// Cells (columns, rows) taken by an image of `size` pixels scaled to fit on
+/// `area` cells, keeping its aspect ratio
+pub fn fit_cells(size: (u32, u32), area: (u16, u16), cell: (u16, u16)) -> (u16, u16) {
+    let (width, height) = (size.0.max(1) as f64, size.1.max(1) as f64);
+    let (cell_width, cell_height) = (cell.0.max(1) as f64, cell.1.max(1) as f64);
+    let scale = (area.0 as f64 * cell_width / width).min(area.1 as f64 * cell_height / height);
+    let columns = (width * scale / cell_width)
+        .round()
+        .clamp(1.0, area.0.max(1) as f64);
+    let rows = (height * scale / cell_height)
+        .round()
+        .clamp(1.0, area.1.max(1) as f64);
+    (columns as u16, rows as u16)
+}
+
+/// Colors (top and bottom pixels) of each cell of the image drawn with half
+/// blocks on `columns` x `rows` cells, row by row
+pub fn half_blocks(image: &RgbaImage, columns: u16, rows: u16) -> Vec<Vec<([u8; 3], [u8; 3])>> {
+    let image = DynamicImage::ImageRgba8(image.clone())
+        .resize_exact(columns as u32, rows as u32 * 2, FilterType::Triangle)
+        .into_rgb8();
+    (0..rows as u32)
+        .map(|row| {
+            (0..columns as u32)
+                .map(|column| {
+                    (
+                        image.get_pixel(column, row * 2).0,
+                        image.get_pixel(column, row * 2 + 1).0,
+                    )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Escape sequences showing the image (as PNG) on `columns` x `rows` cells at
+/// the cursor, with the kitty graphics protocol
+pub fn kitty_image(image: &RgbaImage, columns: u16, rows: u16) -> String {
+    let mut png = Cursor::new(Vec::new());
+    if let Err(e) = image.write_to(&mut png, ImageFormat::Png) {
+        tracing::warn!(error.message=%e, "Failed to encode preview.");
+        return String::new();
+    }
+    let data = STANDARD.encode(png.into_inner());
+    let chunks = data.as_bytes().chunks(KITTY_CHUNK_SIZE).collect::<Vec<_>>();
+    let mut sequence = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = String::from_utf8_lossy(chunk);
+        if i == 0 {
+            let _ = write!(
+                sequence,
+                "\x1b_Ga=T,f=100,q=2,C=1,c={},r={},m={};{}\x1b\\",
+                columns, rows, more, chunk
+            );
+        } else {
+            let _ = write!(sequence, "\x1b_Gm={};{}\x1b\\", more, chunk);
+        }
+    }
+    sequence
+}
+
+/// Color of the 6x6x6 cube used as sixel palette
+fn palette_index(pixel: &image::Rgba<u8>) -> usize {
+    let level = |c: u8| (c as usize * 5 + 127) / 255;
+    level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2])
+}
+
+/// Escape sequence drawing the image at the cursor with sixels, reduced to a
+/// 216 colors palette. Transparent pixels are left undrawn.
+pub fn sixel_image(image: &RgbaImage) -> String {
+    let (width, height) = image.dimensions();
+    let mut sequence = format!("\x1bPq\"1;1;{};{}", width, height);
+    for i in 0..216 {
+        let _ = write!(
+            sequence,
+            "#{};2;{};{};{}",
+            i,
+            i / 36 * 20,
+            i / 6 % 6 * 20,
+            i % 6 * 20
+        );
+    }
+    for top in (0..height).step_by(6) {
+        let band = (top..(top + 6).min(height)).collect::<Vec<_>>();
+        let mut colors = band
+            .iter()
+            .flat_map(|y| (0..width).map(|x| image.get_pixel(x, *y)))
+            .filter(|pixel| pixel[3] >= 128)
+            .map(palette_index)
+            .collect::<Vec<_>>();
+        colors.sort_unstable();
+        colors.dedup();
+        for color in colors {
+            let _ = write!(sequence, "#{}", color);
+            let sixels = (0..width).map(|x| {
+                let bits = band.iter().enumerate().fold(0, |bits, (bit, y)| {
+                    let pixel = image.get_pixel(x, *y);
+                    if pixel[3] >= 128 && palette_index(pixel) == color {
+                        bits | 1 << bit
+                    } else {
+                        bits
+                    }
+                });
+                char::from(63 + bits)
+            });
+            push_run_length(&mut sequence, sixels);
+            // Back to the start of the band for the next color
+            sequence.push('$');
+        }
+        sequence.push('-');
+    }
+    sequence.push_str("\x1b\\");
+    sequence
+}
+
+/// Writes the sixels, repeated ones as `!<count><sixel>`
+fn push_run_length(sequence: &mut String, sixels: impl Iterator<Item = char>) {
+    let mut run: Option<(char, usize)> = None;
+    let flush = |sequence: &mut String, run: Option<(char, usize)>| match run {
+        Some((sixel, count)) if count > 3 => {
+            let _ = write!(sequence, "!{}{}", count, sixel);
+        }
+        Some((sixel, count)) => sequence.extend(std::iter::repeat_n(sixel, count)),
+        None => {}
+    };
+    for sixel in sixels {
+        run = match run {
+            Some((last, count)) if last == sixel => Some((last, count + 1)),
+            _ => {
+                flush(sequence, run);
+                Some((sixel, 1))
+            }
+        };
+    }
+    flush(sequence, run);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images_are_encoded_for_the_terminal() {
+        assert_eq!(fit_cells((360, 200), (40, 40), (8, 16)), (40, 11));
+        assert_eq!(fit_cells((100, 400), (40, 10), (8, 16)), (5, 10));
+
+        let mut image = RgbaImage::from_pixel(4, 2, image::Rgba([255, 0, 0, 255]));
+        image.put_pixel(0, 1, image::Rgba([0, 0, 255, 255]));
+        let cells = half_blocks(&image, 4, 1);
+        assert_eq!(cells[0][3], ([255, 0, 0], [255, 0, 0]));
+        assert_eq!(cells[0][0].1[2], 255);
+
+        let sixel = sixel_image(&RgbaImage::from_pixel(8, 6, image::Rgba([255, 0, 0, 255])));
+        assert!(sixel.starts_with("\x1bPq\"1;1;8;6"));
+        // Red only, the 8 columns with all the 6 pixels set
+        assert!(sixel.ends_with("#180!8~$-\x1b\\"));
+
+        let kitty = kitty_image(&image, 4, 1);
+        assert!(kitty.starts_with("\x1b_Ga=T,f=100,q=2,C=1,c=4,r=1,m=0;"));
+    }
+}
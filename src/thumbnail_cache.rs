@@ -0,0 +1,199 @@
+use crate::{load_sized_thumbnail, Error, ImageEntry, Result};
+use iced::widget::image::Handle;
+use image::RgbaImage;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Max memory used by the decoded thumbnails, ~900 thumbnails of 360x200
+pub const THUMBNAIL_MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+
+/// Decoded thumbnail ready to be drawn
+#[derive(Debug, Clone)]
+pub struct Thumbnail {
+    pub handle: Handle,
+    /// Size of the decoded pixels
+    pub bytes: usize,
+    /// Thumbnail size it was rendered for
+    pub size: u32,
+}
+
+impl Thumbnail {
+    pub fn new(thumbnail: RgbaImage, size: u32) -> Self {
+        let (width, height) = thumbnail.dimensions();
+        let pixels = thumbnail.into_raw();
+        Self {
+            bytes: pixels.len(),
+            handle: Handle::from_pixels(width, height, pixels),
+            size,
+        }
+    }
+}
+
+/// Loads the thumbnail of an indexed image with the given size (width), from
+/// the disk cache if possible
+pub async fn load_thumbnail_async(entry: ImageEntry, size: u32) -> Result<Thumbnail> {
+    tokio::task::spawn_blocking(move || {
+        let thumbnail = load_sized_thumbnail(&entry, size)?;
+        Ok(Thumbnail::new(thumbnail, size))
+    })
+    .await
+    .map_err(|e| Error::UnexpectedError(e.to_string()))?
+}
+
+#[derive(Debug)]
+struct CachedThumbnail {
+    thumbnail: Thumbnail,
+    last_used: u64,
+}
+
+/// Thumbnails of the images shown on the gallery, the least recently shown
+/// ones are dropped when over the memory budget
+#[derive(Debug)]
+pub struct ThumbnailCache {
+    budget: usize,
+    used: usize,
+    /// Incremented on each request, to know which thumbnails were used last
+    tick: u64,
+    entries: HashMap<PathBuf, CachedThumbnail>,
+    loading: HashSet<PathBuf>,
+    /// Thumbnails that failed to load, not retried until the file changes
+    failed: HashSet<PathBuf>,
+}
+
+impl Default for ThumbnailCache {
+    fn default() -> Self {
+        Self::new(THUMBNAIL_MEMORY_BUDGET)
+    }
+}
+
+impl ThumbnailCache {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            used: 0,
+            tick: 0,
+            entries: HashMap::new(),
+            loading: HashSet::new(),
+            failed: HashSet::new(),
+        }
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&Handle> {
+        self.entries
+            .get(path)
+            .map(|cached| &cached.thumbnail.handle)
+    }
+
+    /// Marks the thumbnails as used, returns the ones that need to be loaded
+    /// (or rendered again with a different size)
+    pub fn request<'a>(
+        &mut self,
+        paths: impl IntoIterator<Item = &'a Path>,
+        size: u32,
+    ) -> Vec<PathBuf> {
+        self.tick += 1;
+        let mut missing = Vec::new();
+        for path in paths {
+            // Resized thumbnails keep showing the old one until loaded
+            let fresh = match self.entries.get_mut(path) {
+                Some(cached) => {
+                    cached.last_used = self.tick;
+                    cached.thumbnail.size == size
+                }
+                None => false,
+            };
+            if !fresh && !self.loading.contains(path) && !self.failed.contains(path) {
+                self.loading.insert(path.to_path_buf());
+                missing.push(path.to_path_buf());
+            }
+        }
+        missing
+    }
+
+    pub fn insert(&mut self, path: PathBuf, thumbnail: Thumbnail) {
+        self.loading.remove(&path);
+        self.failed.remove(&path);
+        self.used += thumbnail.bytes;
+        let cached = CachedThumbnail {
+            thumbnail,
+            last_used: self.tick,
+        };
+        if let Some(old) = self.entries.insert(path, cached) {
+            self.used -= old.thumbnail.bytes;
+        }
+        self.evict();
+    }
+
+    pub fn failed(&mut self, path: PathBuf) {
+        self.loading.remove(&path);
+        self.failed.insert(path);
+    }
+
+    /// Forgets the thumbnail, so it is loaded again when shown
+    pub fn remove(&mut self, path: &Path) {
+        self.loading.remove(path);
+        self.failed.remove(path);
+        if let Some(old) = self.entries.remove(path) {
+            self.used -= old.thumbnail.bytes;
+        }
+    }
+
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Drops the least recently used thumbnails until under the budget
+    fn evict(&mut self) {
+        if self.used <= self.budget {
+            return;
+        }
+        let mut by_age = self
+            .entries
+            .iter()
+            .map(|(path, cached)| (cached.last_used, path.clone()))
+            .collect::<Vec<_>>();
+        by_age.sort();
+        for (_, path) in by_age {
+            if self.used <= self.budget {
+                break;
+            }
+            self.remove(&path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thumbnail() -> Thumbnail {
+        Thumbnail::new(RgbaImage::new(10, 10), 10)
+    }
+
+    #[test]
+    fn least_recently_used_are_evicted() {
+        let mut cache = ThumbnailCache::new(1000);
+        let paths = ["/a.png", "/b.png", "/c.png"].map(PathBuf::from);
+        assert_eq!(cache.request(paths.iter().map(PathBuf::as_path), 10), paths);
+        // Already loading
+        assert!(cache.request([paths[0].as_path()], 10).is_empty());
+        cache.insert(paths[0].clone(), thumbnail());
+        cache.insert(paths[1].clone(), thumbnail());
+        cache.request([paths[0].as_path()], 10);
+        cache.insert(paths[2].clone(), thumbnail());
+        assert_eq!(cache.used(), 800);
+        assert!(cache.get(&paths[0]).is_some());
+        assert!(cache.get(&paths[1]).is_none());
+        assert!(cache.get(&paths[2]).is_some());
+        assert_eq!(
+            cache.request([paths[1].as_path()], 10),
+            vec![paths[1].clone()]
+        );
+        // A different size is loaded again, keeping the old one meanwhile
+        assert_eq!(
+            cache.request([paths[0].as_path()], 20),
+            vec![paths[0].clone()]
+        );
+        assert!(cache.get(&paths[0]).is_some());
+    }
+}
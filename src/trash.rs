@@ -0,0 +1,167 @@
+use crate::{move_file, Error, Result};
+use directories::BaseDirs;
+use std::{
+    io::ErrorKind,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+use tokio::{
+    fs::{create_dir_all, remove_file, symlink_metadata, OpenOptions},
+    io::AsyncWriteExt,
+};
+
+/// Home trash folder (`$XDG_DATA_HOME/Trash`)
+pub fn get_trash_dir() -> Result<PathBuf> {
+    Ok(BaseDirs::new()
+        .ok_or(Error::NoHomeDir)?
+        .data_dir()
+        .join("Trash"))
+}
+
+/// Moves a file to the home trash following the freedesktop.org Trash spec:
+/// the file goes to `Trash/files` and a `.trashinfo` file with its original
+/// location goes to `Trash/info`. Returns the path of the trashed file.
+/// A symlink is trashed itself, not the file it points to.
+#[tracing::instrument]
+pub async fn move_to_trash(path: &Path) -> Result<PathBuf> {
+    move_to_trash_dir(path, &get_trash_dir()?).await
+}
+
+async fn move_to_trash_dir(path: &Path, trash_dir: &Path) -> Result<PathBuf> {
+    let failed = |e: std::io::Error| {
+        tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to move file to trash.");
+        Error::FailedToTrash(path.to_path_buf())
+    };
+    symlink_metadata(path).await.map_err(failed)?;
+    // Only the folder is resolved, so the location of a link is kept
+    let name = path.file_name().ok_or(Error::FailedToTrash(path.into()))?;
+    let folder = match path.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => folder,
+        _ => Path::new("."),
+    };
+    let path = folder.canonicalize().map_err(failed)?.join(name);
+    let (files_dir, info_dir) = (trash_dir.join("files"), trash_dir.join("info"));
+    create_dir_all(&files_dir).await.map_err(failed)?;
+    create_dir_all(&info_dir).await.map_err(failed)?;
+
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(&path),
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+    // The info file is created first (with `create_new`) to reserve the name
+    let (name, info_path) = {
+        let mut n = 0;
+        loop {
+            let name = trash_name(&path, n);
+            let info_path = info_dir.join(format!("{}.trashinfo", name));
+            let created = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&info_path)
+                .await;
+            match created {
+                Ok(mut file) if !files_dir.join(&name).exists() => {
+                    file.write_all(info.as_bytes()).await.map_err(failed)?;
+                    break (name, info_path);
+                }
+                Ok(_) => {
+                    let _ = remove_file(&info_path).await;
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(failed(e)),
+            }
+            n += 1;
+        }
+    };
+
+    let trashed_path = files_dir.join(name);
+    // Files on another filesystem are copied to the home trash
+    if let Err(e) = move_file(&path, &trashed_path).await {
+        let _ = remove_file(&info_path).await;
+        return Err(failed(e));
+    }
+    tracing::info!(?trashed_path, "File moved to trash.");
+    Ok(trashed_path)
+}
+
+/// Moves all the files to the trash, returning the result for each one
+pub async fn move_all_to_trash(paths: Vec<PathBuf>) -> Vec<(PathBuf, Result<PathBuf>)> {
+    let mut results = Vec::with_capacity(paths.len());
+    for path in paths {
+        let result = move_to_trash(&path).await;
+        results.push((path, result));
+    }
+    results
+}
+
+/// File name used on the trash, `n` avoids collisions: `image.png`,
+/// `image.1.png`, `image.2.png`...
+fn trash_name(path: &Path, n: usize) -> String {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if n == 0 {
+        return name;
+    }
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => {
+            format!("{}.{}.{}", stem.to_string_lossy(), n, ext.to_string_lossy())
+        }
+        _ => format!("{}.{}", name, n),
+    }
+}
+
+/// Percent-encodes a path as required by the `Path` key of `.trashinfo` files
+fn percent_encode(path: &Path) -> String {
+    path.as_os_str()
+        .as_bytes()
+        .iter()
+        .map(|&b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trash_names_avoid_collisions() {
+        let path = Path::new("/wallpapers/my image.png");
+        assert_eq!(trash_name(path, 0), "my image.png");
+        assert_eq!(trash_name(path, 2), "my image.2.png");
+        assert_eq!(percent_encode(path), "/wallpapers/my%20image.png");
+    }
+
+    #[tokio::test]
+    async fn links_are_trashed_instead_of_their_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("moon.png");
+        let link = dir.path().join("library/moon.png");
+        std::fs::create_dir_all(link.parent().unwrap()).unwrap();
+        std::fs::write(&target, b"moon").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let trash_dir = dir.path().join("Trash");
+        let trashed = move_to_trash_dir(&link, &trash_dir).await.unwrap();
+        assert!(target.exists());
+        assert!(!link.exists());
+        assert!(std::fs::symlink_metadata(&trashed)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        let info = std::fs::read_to_string(trash_dir.join("info/moon.png.trashinfo")).unwrap();
+        let location = link
+            .parent()
+            .unwrap()
+            .canonicalize()
+            .unwrap()
+            .join("moon.png");
+        assert!(info.contains(&format!("Path={}\n", percent_encode(&location))));
+    }
+}
@@ -0,0 +1,527 @@
+use crate::{
+    apply_or_forward, fit_cells, half_blocks, kitty_image, load_sized_thumbnail, save_tags,
+    sixel_image, ApplyOptions, ApplySource, Collection, GraphicsProtocol, Hooks, ImageEntry,
+    TagStore, DEFAULT_CELL_SIZE, FAVORITE_TAG, KITTY_CLEAR, MENU_FAVORITE_PREFIX, THUMBNAIL_WIDTH,
+};
+use image::{imageops::FilterType, DynamicImage, RgbaImage};
+use ratatui::{
+    crossterm::{
+        cursor,
+        event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+        execute, terminal,
+    },
+    layout::{Constraint, Layout, Rect},
+    prelude::CrosstermBackend,
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, List, ListState, Paragraph},
+    Frame, Terminal,
+};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tokio::runtime::Runtime;
+
+/// Rows moved by Page Up and Page Down
+const PAGE_ROWS: isize = 10;
+
+/// Images listed on the terminal UI, filtered by collection and search
+#[derive(Debug)]
+pub struct TuiState {
+    root: PathBuf,
+    entries: Vec<ImageEntry>,
+    tags: TagStore,
+    /// All the wallpapers first, then each tag
+    collections: Vec<Collection>,
+    collection: usize,
+    query: String,
+    searching: bool,
+    /// Indices of the entries shown
+    visible: Vec<usize>,
+    cursor: usize,
+}
+
+impl TuiState {
+    pub fn new(root: PathBuf, entries: Vec<ImageEntry>, tags: TagStore) -> Self {
+        let mut state = Self {
+            root,
+            entries,
+            tags,
+            collections: Vec::new(),
+            collection: 0,
+            query: String::new(),
+            searching: false,
+            visible: Vec::new(),
+            cursor: 0,
+        };
+        state.refresh_collections();
+        state.refresh();
+        state
+    }
+
+    fn refresh_collections(&mut self) {
+        let names = self.tags.collections(
+            self.entries.iter().map(|entry| &entry.path),
+            Some(&self.root),
+        );
+        self.collections = std::iter::once(Collection::All)
+            .chain(names.into_iter().map(Collection::Named))
+            .collect();
+        self.collection = self.collection.min(self.collections.len() - 1);
+    }
+
+    fn relative_path<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    /// Whether the image is on the collection and matches the search, on its
+    /// path or its tags (case insensitive)
+    fn matches(&self, entry: &ImageEntry) -> bool {
+        if !self
+            .tags
+            .matches(&entry.path, Some(&self.root), self.collection())
+        {
+            return false;
+        }
+        let query = self.query.to_lowercase();
+        query.is_empty()
+            || self
+                .relative_path(&entry.path)
+                .to_string_lossy()
+                .to_lowercase()
+                .contains(&query)
+            || self
+                .tags
+                .tags_for(&entry.path, Some(&self.root))
+                .iter()
+                .any(|tag| tag.contains(&query))
+    }
+
+    /// Filters the images again, keeping the cursor on the same image if it
+    /// is still shown
+    fn refresh(&mut self) {
+        let current = self.current().map(|entry| entry.path.clone());
+        self.visible = (0..self.entries.len())
+            .filter(|i| self.matches(&self.entries[*i]))
+            .collect();
+        self.cursor = current
+            .and_then(|path| {
+                self.visible
+                    .iter()
+                    .position(|i| self.entries[*i].path == path)
+            })
+            .unwrap_or(0);
+    }
+
+    pub fn collection(&self) -> &Collection {
+        &self.collections[self.collection]
+    }
+
+    pub fn visible(&self) -> impl Iterator<Item = &ImageEntry> {
+        self.visible.iter().map(|i| &self.entries[*i])
+    }
+
+    pub fn current(&self) -> Option<&ImageEntry> {
+        self.visible.get(self.cursor).map(|i| &self.entries[*i])
+    }
+
+    pub fn is_favorite(&self, entry: &ImageEntry) -> bool {
+        self.tags.has_tag(&entry.path, FAVORITE_TAG)
+    }
+
+    pub fn move_cursor(&mut self, delta: isize) {
+        let last = self.visible.len().saturating_sub(1) as isize;
+        self.cursor = (self.cursor as isize + delta).clamp(0, last) as usize;
+    }
+
+    pub fn next_collection(&mut self) {
+        self.collection = (self.collection + 1) % self.collections.len();
+        self.refresh();
+    }
+
+    pub fn push_search(&mut self, c: char) {
+        self.query.push(c);
+        self.refresh();
+    }
+
+    pub fn pop_search(&mut self) {
+        self.query.pop();
+        self.refresh();
+    }
+
+    pub fn clear_search(&mut self) {
+        self.query.clear();
+        self.refresh();
+    }
+
+    /// Marks the current image as favorite, or unmarks it. Returns the tags
+    /// to save.
+    pub fn toggle_favorite(&mut self) -> Option<&TagStore> {
+        let path = self.current()?.path.clone();
+        if self.tags.has_tag(&path, FAVORITE_TAG) {
+            self.tags.remove_tag([&path], FAVORITE_TAG);
+        } else {
+            self.tags.add_tag([&path], FAVORITE_TAG);
+        }
+        self.refresh_collections();
+        self.refresh();
+        Some(&self.tags)
+    }
+}
+
+/// Thumbnail of the current image
+#[derive(Debug)]
+struct Preview {
+    path: PathBuf,
+    thumbnail: Option<RgbaImage>,
+}
+
+/// Terminal UI: the images on the left, the preview of the current one on
+/// the right
+pub struct Tui {
+    state: TuiState,
+    protocol: GraphicsProtocol,
+    hooks: Hooks,
+    list: ListState,
+    preview: Option<Preview>,
+    /// Image and area of the last preview drawn with kitty or sixel
+    drawn: Option<(PathBuf, Rect)>,
+    status: String,
+}
+
+impl Tui {
+    pub fn new(state: TuiState, protocol: GraphicsProtocol, hooks: Hooks) -> Self {
+        Self {
+            state,
+            protocol,
+            hooks,
+            list: ListState::default(),
+            preview: None,
+            drawn: None,
+            status: String::new(),
+        }
+    }
+
+    /// Runs until `q` is pressed, restoring the terminal even on errors
+    pub fn run(mut self, rt: &Runtime) -> io::Result<()> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::EnterAlternateScreen)?;
+        let result = self.event_loop(rt);
+        if self.protocol == GraphicsProtocol::Kitty {
+            print!("{}", KITTY_CLEAR);
+        }
+        execute!(io::stdout(), terminal::LeaveAlternateScreen, cursor::Show)?;
+        terminal::disable_raw_mode()?;
+        result
+    }
+
+    fn event_loop(&mut self, rt: &Runtime) -> io::Result<()> {
+        let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+        loop {
+            self.load_preview();
+            let current = self.preview.as_ref().map(|preview| preview.path.clone());
+            // Sixels stay on the screen until drawn over
+            if self.protocol == GraphicsProtocol::Sixel
+                && self.drawn.as_ref().map(|(path, _)| path) != current.as_ref()
+            {
+                terminal.clear()?;
+            }
+            let mut preview_area = Rect::default();
+            terminal.draw(|frame| preview_area = self.draw(frame))?;
+            self.draw_graphics(preview_area)?;
+            match event::read()? {
+                Event::Key(key)
+                    if key.kind == KeyEventKind::Press && !self.key_pressed(key, rt) =>
+                {
+                    return Ok(());
+                }
+                Event::Resize(..) => {
+                    terminal.clear()?;
+                    self.drawn = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Handles the key, returns whether to keep running
+    fn key_pressed(&mut self, key: KeyEvent, rt: &Runtime) -> bool {
+        if self.state.searching {
+            match key.code {
+                KeyCode::Char(c) => self.state.push_search(c),
+                KeyCode::Backspace => self.state.pop_search(),
+                KeyCode::Enter => self.state.searching = false,
+                KeyCode::Esc => {
+                    self.state.searching = false;
+                    self.state.clear_search();
+                }
+                _ => {}
+            }
+            return true;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.state.move_cursor(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.state.move_cursor(1),
+            KeyCode::PageUp => self.state.move_cursor(-PAGE_ROWS),
+            KeyCode::PageDown => self.state.move_cursor(PAGE_ROWS),
+            KeyCode::Home | KeyCode::Char('g') => self.state.move_cursor(isize::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => self.state.move_cursor(isize::MAX / 2),
+            KeyCode::Char('/') => self.state.searching = true,
+            KeyCode::Char('c') => self.state.next_collection(),
+            KeyCode::Char('f') => {
+                if let Some(tags) = self.state.toggle_favorite() {
+                    if let Err(e) = rt.block_on(save_tags(tags.clone())) {
+                        self.status = e.to_string();
+                    }
+                }
+            }
+            KeyCode::Enter => self.apply(rt),
+            _ => {}
+        }
+        true
+    }
+
+    fn apply(&mut self, rt: &Runtime) {
+        let Some(entry) = self.state.current() else {
+            return;
+        };
+        let options = ApplyOptions {
+            source: ApplySource::Tui,
+            colors: entry.colors.clone(),
+            hooks: self.hooks.clone(),
+        };
+        self.status = match rt.block_on(apply_or_forward(entry.path.clone(), options)) {
+            Ok(path) => format!("Wallpaper changed: {}", path.display()),
+            Err(e) => e.to_string(),
+        };
+    }
+
+    /// Loads the thumbnail of the current image if it changed
+    fn load_preview(&mut self) {
+        let Some(entry) = self.state.current() else {
+            self.preview = None;
+            return;
+        };
+        if self
+            .preview
+            .as_ref()
+            .is_some_and(|preview| preview.path == entry.path)
+        {
+            return;
+        }
+        let thumbnail = load_sized_thumbnail(entry, THUMBNAIL_WIDTH);
+        if let Err(e) = &thumbnail {
+            self.status = e.to_string();
+        }
+        self.preview = Some(Preview {
+            path: entry.path.clone(),
+            thumbnail: thumbnail.ok(),
+        });
+    }
+
+    /// Draws the widgets, returns the area left for the preview
+    fn draw(&mut self, frame: &mut Frame) -> Rect {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [list, preview] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(main);
+
+        let items = self
+            .state
+            .visible()
+            .map(|entry| {
+                let prefix = if self.state.is_favorite(entry) {
+                    MENU_FAVORITE_PREFIX
+                } else {
+                    "  "
+                };
+                let path = self.state.relative_path(&entry.path);
+                format!("{}{}", prefix, path.display())
+            })
+            .collect::<Vec<_>>();
+        let title = match (self.state.searching, self.state.query.is_empty()) {
+            (false, true) => format!(" {} ({}) ", self.state.collection(), items.len()),
+            _ => format!(
+                " {} ({}) /{} ",
+                self.state.collection(),
+                items.len(),
+                self.state.query
+            ),
+        };
+        self.list
+            .select((!items.is_empty()).then_some(self.state.cursor));
+        let list_widget = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(list_widget, list, &mut self.list);
+
+        let details = self
+            .state
+            .current()
+            .map(|entry| {
+                format!(
+                    " {} {}x{} {} ",
+                    entry.file_name(),
+                    entry.width,
+                    entry.height,
+                    entry.brightness
+                )
+            })
+            .unwrap_or_default();
+        let block = Block::bordered().title(details);
+        let inner = block.inner(preview);
+        frame.render_widget(block, preview);
+
+        let help = "Enter apply  / search  c collection  f favorite  q quit";
+        let status_line = if self.status.is_empty() {
+            help
+        } else {
+            &self.status
+        };
+        frame.render_widget(Paragraph::new(Line::from(status_line).dim()), status);
+
+        let Some(thumbnail) = self
+            .preview
+            .as_ref()
+            .and_then(|preview| preview.thumbnail.as_ref())
+            .filter(|_| !inner.is_empty())
+        else {
+            return Rect::default();
+        };
+        let cell = match self.protocol {
+            GraphicsProtocol::Blocks => (1, 2),
+            _ => cell_size(),
+        };
+        let (columns, rows) = fit_cells(thumbnail.dimensions(), (inner.width, inner.height), cell);
+        let area = Rect::new(
+            inner.x + (inner.width - columns) / 2,
+            inner.y + (inner.height - rows) / 2,
+            columns,
+            rows,
+        );
+        if self.protocol == GraphicsProtocol::Blocks {
+            let buffer = frame.buffer_mut();
+            for (y, row) in half_blocks(thumbnail, columns, rows)
+                .into_iter()
+                .enumerate()
+            {
+                for (x, (top, bottom)) in row.into_iter().enumerate() {
+                    if let Some(cell) = buffer.cell_mut((area.x + x as u16, area.y + y as u16)) {
+                        cell.set_char('▀')
+                            .set_fg(Color::Rgb(top[0], top[1], top[2]))
+                            .set_bg(Color::Rgb(bottom[0], bottom[1], bottom[2]));
+                    }
+                }
+            }
+        }
+        area
+    }
+
+    /// Draws the preview with kitty or sixel over the area left empty, if it
+    /// changed since the last time
+    fn draw_graphics(&mut self, area: Rect) -> io::Result<()> {
+        if self.protocol == GraphicsProtocol::Blocks {
+            return Ok(());
+        }
+        let Some(preview) = &self.preview else {
+            return Ok(());
+        };
+        let drawn = Some((preview.path.clone(), area));
+        if self.drawn == drawn {
+            return Ok(());
+        }
+        self.drawn = drawn;
+        let mut stdout = io::stdout();
+        if self.protocol == GraphicsProtocol::Kitty {
+            write!(stdout, "{}", KITTY_CLEAR)?;
+        }
+        let Some(thumbnail) = preview.thumbnail.as_ref().filter(|_| !area.is_empty()) else {
+            return stdout.flush();
+        };
+        execute!(stdout, cursor::MoveTo(area.x, area.y))?;
+        let sequence = match self.protocol {
+            GraphicsProtocol::Kitty => kitty_image(thumbnail, area.width, area.height),
+            _ => {
+                let (cell_width, cell_height) = cell_size();
+                let resized = DynamicImage::ImageRgba8(thumbnail.clone()).resize(
+                    (area.width * cell_width) as u32,
+                    (area.height * cell_height) as u32,
+                    FilterType::Triangle,
+                );
+                sixel_image(&resized.into_rgba8())
+            }
+        };
+        write!(stdout, "{}", sequence)?;
+        stdout.flush()
+    }
+}
+
+/// Size in pixels of the terminal cells
+fn cell_size() -> (u16, u16) {
+    match terminal::window_size() {
+        Ok(size) if size.width > 0 && size.columns > 0 && size.rows > 0 => {
+            (size.width / size.columns, size.height / size.rows)
+        }
+        _ => DEFAULT_CELL_SIZE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str) -> ImageEntry {
+        ImageEntry {
+            path: path.into(),
+            size: 0,
+            mtime: 0,
+            width: 1,
+            height: 1,
+            format: "png".into(),
+            hash: String::new(),
+            phash: 0,
+            colors: Vec::new(),
+            histogram: Vec::new(),
+            luminance: Default::default(),
+            brightness: Default::default(),
+        }
+    }
+
+    #[test]
+    fn images_are_filtered_by_collection_and_search() {
+        let entries = vec![
+            entry("/wp/space/moon.png"),
+            entry("/wp/space/mars.png"),
+            entry("/wp/forest.png"),
+        ];
+        let mut state = TuiState::new("/wp".into(), entries, TagStore::default());
+        assert_eq!(state.visible().count(), 3);
+        state.move_cursor(1);
+        state.push_search('m');
+        state.push_search('a');
+        assert_eq!(state.visible().count(), 1);
+        assert_eq!(
+            state.current().unwrap().path,
+            Path::new("/wp/space/mars.png")
+        );
+        state.clear_search();
+        assert_eq!(
+            state.current().unwrap().path,
+            Path::new("/wp/space/mars.png")
+        );
+
+        state.toggle_favorite();
+        // All, favorite and space
+        state.next_collection();
+        assert_eq!(state.collection(), &Collection::Named(FAVORITE_TAG.into()));
+        assert_eq!(state.visible().count(), 1);
+        state.next_collection();
+        assert_eq!(state.visible().count(), 2);
+        state.move_cursor(isize::MAX / 2);
+        assert_eq!(
+            state.current().unwrap().path,
+            Path::new("/wp/space/mars.png")
+        );
+    }
+}
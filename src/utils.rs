@@ -1,4 +1,8 @@
-use std::path::PathBuf;
+use crate::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 
 pub fn expand_home_dir(path: impl AsRef<str>) -> PathBuf {
     let path = path.as_ref().replace(
@@ -7,3 +11,101 @@ pub fn expand_home_dir(path: impl AsRef<str>) -> PathBuf {
     );
     PathBuf::from(path)
 }
+
+/// Formats a number with thousands separators (e.g.: `1,204`)
+pub fn format_count(n: usize) -> String {
+    let digits = n.to_string();
+    let mut formatted = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Rough human readable duration (e.g.: `2 min`)
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{} s", secs.max(1)),
+        60..=3599 => format!("{} min", secs / 60),
+        _ => format!("{} h {} min", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// Replaces the file with `content` by renaming a temporary file written next
+/// to it, so a crash while writing doesn't leave it half written. A symlink is
+/// kept (its target is replaced) and so are the permissions.
+pub async fn write_atomic(path: &Path, content: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let path = &tokio::fs::canonicalize(path)
+        .await
+        .unwrap_or_else(|_| path.to_path_buf());
+    // Saves of the same file can run at the same time
+    static NEXT_TMP: AtomicU64 = AtomicU64::new(0);
+    let tmp_path = path.with_file_name(format!(
+        ".{}.tmp-{}-{}",
+        path.file_name().unwrap_or_default().to_string_lossy(),
+        std::process::id(),
+        NEXT_TMP.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = async {
+        let mut tmp = tokio::fs::File::create(&tmp_path).await?;
+        tmp.write_all(content.as_ref()).await?;
+        tmp.sync_all().await?;
+        if let Ok(metadata) = tokio::fs::metadata(path).await {
+            tmp.set_permissions(metadata.permissions()).await?;
+        }
+        tokio::fs::rename(&tmp_path, path).await
+    }
+    .await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+    }
+    result
+}
+
+/// Opens the folder containing `path` on the default file manager
+pub async fn open_in_file_manager(path: PathBuf) -> Result<()> {
+    let folder = path.parent().map(Path::to_path_buf).unwrap_or(path);
+    tokio::process::Command::new("xdg-open")
+        .arg(&folder)
+        .spawn()
+        .map_err(|e| {
+            tracing::error!(error.cause_chain=?e, error.message=%e, ?folder, "Failed to run xdg-open.");
+            Error::UnexpectedError(format!("Failed to run xdg-open: {}", e))
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_counts_and_durations() {
+        assert_eq!(format_count(312), "312");
+        assert_eq!(format_count(1204), "1,204");
+        assert_eq!(format_count(1234567), "1,234,567");
+        assert_eq!(format_duration(Duration::from_millis(300)), "1 s");
+        assert_eq!(format_duration(Duration::from_secs(150)), "2 min");
+        assert_eq!(format_duration(Duration::from_secs(3720)), "1 h 2 min");
+    }
+
+    #[tokio::test]
+    async fn atomic_writes_replace_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.json");
+        std::fs::write(&path, "old").unwrap();
+        write_atomic(&path, "new").await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        // No temporary file is left behind
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        let link = dir.path().join("link.json");
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+        write_atomic(&link, "linked").await.unwrap();
+        assert!(link.is_symlink());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "linked");
+    }
+}
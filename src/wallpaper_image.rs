@@ -1,57 +1,109 @@
-use crate::{Error, Message, Result};
+use crate::{
+    analyze_image, context_menu_view, load_thumbnail_async, resolution_badges, thumbnail_height,
+    Error, ImageEntry, Message, Resolution, ResolutionBadge, Result, Thumbnail, THUMBNAIL_WIDTH,
+};
 use iced::{
-    widget::{container, image::Handle, mouse_area, Image},
-    Color, Element,
+    widget::{column, container, image::Handle, mouse_area, row, text, Image},
+    Alignment, Background, Color, Element, Length,
 };
+use iced_aw::ContextMenu;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
 pub struct WallpaperImage {
     pub id: usize,
     pub path: PathBuf,
-    pub image: Handle,
+    pub entry: ImageEntry,
+    /// Thumbnail decoded while loading, the gallery moves it to its cache
+    pub thumbnail: Option<Thumbnail>,
+}
+
+/// How a tile is highlighted on the gallery
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TileState {
     pub selected: bool,
+    /// Keyboard cursor
+    pub focused: bool,
 }
 
 impl WallpaperImage {
+    /// Decodes the image, the resulting `entry` should be stored on the index
     #[tracing::instrument]
     pub async fn from_path(id: usize, path: PathBuf) -> Result<Self> {
         tracing::info!("Loading image...");
         let now = std::time::Instant::now();
-        let img = image::io::Reader::open(&path)
-            .map_err(|e| Error::UnexpectedError(e.to_string()))?
-            .decode()
-            .map_err(|e| Error::UnexpectedError(e.to_string()))?
-            .resize(360, 200, image::imageops::FilterType::Gaussian);
-        let width = img.width();
-        let height = img.height();
-        let pixels = img.into_rgba8().into_raw();
-        let image = iced::widget::image::Handle::from_pixels(width, height, pixels);
+        let analysis = {
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || analyze_image(&path))
+                .await
+                .map_err(|e| Error::UnexpectedError(e.to_string()))??
+        };
         tracing::info!(elapsed=?now.elapsed(), "Image loaded.");
-        Ok(Self {
+        let mut image = Self::new(id, analysis.entry);
+        image.thumbnail = Some(Thumbnail::new(analysis.thumbnail, THUMBNAIL_WIDTH));
+        Ok(image)
+    }
+
+    /// Loads the image from the thumbnail cache, decoding it again if the
+    /// thumbnail is missing
+    pub async fn from_entry(id: usize, entry: ImageEntry) -> Result<Self> {
+        let thumbnail = load_thumbnail_async(entry.clone(), THUMBNAIL_WIDTH).await?;
+        let mut image = Self::new(id, entry);
+        image.thumbnail = Some(thumbnail);
+        Ok(image)
+    }
+
+    /// Image from the index, without thumbnail: it is loaded when shown
+    pub fn new(id: usize, entry: ImageEntry) -> Self {
+        Self {
             id,
-            path,
-            image,
-            selected: false,
-        })
+            path: entry.path.clone(),
+            entry,
+            thumbnail: None,
+        }
     }
 
     pub fn select(&mut self) -> PathBuf {
         self.path.clone()
     }
 
-    pub fn view(&self) -> Element<Message> {
-        let border_color = if self.selected {
+    /// Image tile, with resolution badges when the screen resolution is known
+    /// and a placeholder while the thumbnail is loading. `size` is the width of
+    /// the thumbnail, the image keeps its aspect ratio inside.
+    pub fn view(
+        &self,
+        thumbnail: Option<&Handle>,
+        screen: Option<Resolution>,
+        size: u32,
+        state: TileState,
+    ) -> Element<'_, Message> {
+        let border_color = if state.selected {
             Color::from_rgb(0.741, 0.576, 0.976)
+        } else if state.focused {
+            Color::from_rgb(0.6, 0.6, 0.6)
         } else {
             Color::TRANSPARENT
         };
-        mouse_area(
+        let badges = resolution_badges(self.entry.resolution(), screen)
+            .into_iter()
+            .map(badge_view)
+            .collect::<Vec<_>>();
+        let badges = row(badges).spacing(4).height(18);
+        let thumbnail: Element<_> = match thumbnail {
+            Some(handle) => Image::new(handle.clone()).height(Length::Fill).into(),
+            None => container(text(self.entry.file_name()).size(12))
+                .height(Length::Fill)
+                .center_y()
+                .into(),
+        };
+        let tile = mouse_area(
             container(
-                Image::new(self.image.clone()), // .content_fit(ContentFit::Contain)
+                column!(thumbnail, badges)
+                    .spacing(2)
+                    .align_items(Alignment::Center),
             )
-            .width(360)
-            .height(200)
+            .width(size as f32)
+            .height((thumbnail_height(size) + 20) as f32)
             .center_x()
             .center_y()
             .padding(10)
@@ -61,7 +113,24 @@ impl WallpaperImage {
                 ..Default::default()
             }),
         )
-        .on_press(Message::SelectImage(self.id))
-        .into()
+        .on_press(Message::SelectImage(self.id));
+        let id = self.id;
+        ContextMenu::new(tile, move || context_menu_view(id)).into()
     }
 }
+
+fn badge_view<'a>(badge: ResolutionBadge) -> Element<'a, Message> {
+    let background = if badge.is_warning() {
+        Color::from_rgb(0.6, 0.35, 0.1)
+    } else {
+        Color::from_rgb(0.2, 0.45, 0.3)
+    };
+    container(text(badge.to_string()).size(12))
+        .padding([1, 5])
+        .style(move |_: &_| container::Appearance {
+            background: Some(Background::Color(background)),
+            border_radius: 3.0.into(),
+            ..Default::default()
+        })
+        .into()
+}
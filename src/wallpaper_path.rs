@@ -21,13 +21,12 @@ pub struct WallpaperPath {
 impl WallpaperPath {
     pub fn from_config(config: &Configuration) -> Self {
         let input = config
-            .wallpapers_path
-            .as_ref()
+            .wallpapers_path()
             .map(|path| path.to_str().unwrap_or_default().to_string())
             .unwrap_or_default();
         Self {
             input,
-            path: config.wallpapers_path.clone(),
+            path: config.wallpapers_path().map(PathBuf::from),
             input_id: text_input::Id::unique(),
         }
     }
@@ -66,7 +65,7 @@ impl WallpaperPath {
         }
     }
 
-    pub fn view(&self) -> Element<WallpaperPathMessage> {
+    pub fn view(&self) -> Element<'_, WallpaperPathMessage> {
         let label = text("Wallpapers folder path:").size(16);
         let input = text_input("Enter folder path...", &self.input)
             .id(self.input_id.clone())
@@ -0,0 +1,182 @@
+use crate::{get_regolith_config_path, load_image_files};
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+use iced::{subscription, Subscription};
+use image::ImageFormat;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Time to wait for more events before sending a batch, so copying a folder
+/// of images results in a single update
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Changes on the library and the Regolith config since the last batch
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LibraryChanges {
+    /// Image files created or modified
+    pub changed: BTreeSet<PathBuf>,
+    /// Files or folders removed (or moved out of the library)
+    pub removed: BTreeSet<PathBuf>,
+    /// The Regolith config file (Xresources) changed
+    pub regolith_config: bool,
+}
+
+impl LibraryChanges {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty() && !self.regolith_config
+    }
+
+    /// Whether `path` was removed, by itself or as part of a removed folder
+    pub fn is_removed(&self, path: &Path) -> bool {
+        self.removed.iter().any(|removed| path.starts_with(removed))
+    }
+}
+
+/// Paths touched by a burst of events, classified once the burst ends
+#[derive(Debug, Default)]
+struct PendingPaths {
+    paths: BTreeSet<PathBuf>,
+}
+
+impl PendingPaths {
+    fn add(&mut self, event: notify::Event) {
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        self.paths.extend(event.paths);
+    }
+
+    /// Checks the current state of the touched paths: existing image files are
+    /// changed, new folders are scanned and missing paths are removed
+    async fn into_changes(self, root: &Path, regolith_config: &Path) -> LibraryChanges {
+        let mut changes = LibraryChanges::default();
+        for path in self.paths {
+            if path == regolith_config {
+                changes.regolith_config = true;
+            } else if !path.starts_with(root) || is_hidden(root, &path) {
+                continue;
+            } else if path.is_dir() {
+                match load_image_files(path.clone()).await {
+                    Ok(paths) => changes.changed.extend(paths),
+                    Err(e) => tracing::warn!(?path, error.message=%e, "Failed to scan folder."),
+                }
+            } else if path.exists() {
+                if is_image(&path) {
+                    changes.changed.insert(path);
+                }
+            } else {
+                changes.removed.insert(path);
+            }
+        }
+        changes
+    }
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(ImageFormat::from_extension)
+        .is_some()
+}
+
+/// Hidden files and folders are skipped, like when loading the library
+fn is_hidden(root: &Path, path: &Path) -> bool {
+    path.strip_prefix(root).is_ok_and(|relative| {
+        relative
+            .components()
+            .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+    })
+}
+
+/// Watches the library folder (recursively) and the Regolith config file,
+/// sending a batch of changes after each burst of events
+pub fn watch_library(root: PathBuf) -> Subscription<LibraryChanges> {
+    subscription::channel(root.clone(), 16, |mut output| async move {
+        let regolith_config = get_regolith_config_path();
+        let (tx, mut rx) = mpsc::unbounded();
+        let watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) => {
+                    let _ = tx.unbounded_send(event);
+                }
+                Err(e) => tracing::warn!(error.message=%e, "Filesystem watch error."),
+            });
+        // Keeps the watcher alive while the subscription runs
+        let _watcher = match watcher {
+            Ok(mut watcher) => {
+                if let Err(e) = watcher.watch(&root, RecursiveMode::Recursive) {
+                    tracing::error!(error.cause_chain=?e, error.message=%e, ?root, "Failed to watch the library.");
+                }
+                // Editors and `set_wallpaper_on_config` replace the file, so
+                // the folder is watched instead
+                if let Some(folder) = regolith_config.parent() {
+                    if let Err(e) = watcher.watch(folder, RecursiveMode::NonRecursive) {
+                        tracing::warn!(error.message=%e, ?folder, "Failed to watch the regolith config.");
+                    }
+                }
+                Some(watcher)
+            }
+            Err(e) => {
+                tracing::error!(error.cause_chain=?e, error.message=%e, "Failed to create filesystem watcher.");
+                None
+            }
+        };
+        loop {
+            let mut pending = PendingPaths::default();
+            match rx.next().await {
+                Some(event) => pending.add(event),
+                // The watcher failed, nothing will be sent anymore
+                None => futures::future::pending().await,
+            }
+            while let Ok(Some(event)) = tokio::time::timeout(DEBOUNCE, rx.next()).await {
+                pending.add(event);
+            }
+            let changes = pending.into_changes(&root, &regolith_config).await;
+            if !changes.is_empty() {
+                let _ = output.send(changes).await;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, RemoveKind};
+
+    #[tokio::test]
+    async fn events_are_classified_by_current_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        std::fs::create_dir_all(root.join("space")).unwrap();
+        std::fs::create_dir_all(root.join(".hidden")).unwrap();
+        for file in ["new.png", "space/blue.jpg", ".hidden/x.png", "notes.txt"] {
+            std::fs::write(root.join(file), b"").unwrap();
+        }
+        let regolith_config = root.join("Xresources");
+        let mut pending = PendingPaths::default();
+        let mut create = notify::Event::new(EventKind::Create(CreateKind::Any));
+        create.paths = vec![
+            root.join("new.png"),
+            root.join("space"),
+            root.join(".hidden/x.png"),
+            root.join("notes.txt"),
+            regolith_config.clone(),
+        ];
+        pending.add(create);
+        pending.add(
+            notify::Event::new(EventKind::Remove(RemoveKind::Any))
+                .add_path(root.join("old/gone.png")),
+        );
+        let changes = pending.into_changes(&root, &regolith_config).await;
+        assert_eq!(
+            changes.changed,
+            BTreeSet::from([root.join("new.png"), root.join("space/blue.jpg")])
+        );
+        assert_eq!(changes.removed, BTreeSet::from([root.join("old/gone.png")]));
+        assert!(changes.regolith_config);
+        assert!(changes.is_removed(&root.join("old/gone.png")));
+        assert!(!changes.is_removed(&root.join("new.png")));
+    }
+}